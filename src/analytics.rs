@@ -0,0 +1,918 @@
+use clap::ValueEnum;
+use ethers::types::{Address, U256, U512};
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::{pricing, PricePoint};
+
+/// What each interval between samples is weighted by
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, JsonSchema, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Weighting {
+    /// Seconds between the samples' timestamps
+    #[default]
+    Time,
+    /// Blocks between the samples, as some on-chain oracles do
+    Blocks,
+}
+
+/// Time-weighted average price of a time-ordered series of samples.
+///
+/// Trapezoidal: each interval is weighted by the mean of the prices at its two ends, so the last
+/// sample counts as much as the first. Intervals between samples in the same block add nothing.
+/// Falls back to the last price when the samples span no time.
+pub fn compute_twap(price_points: &[PricePoint]) -> f64 {
+    weighted_twap(price_points, Weighting::Time)
+}
+
+/// `compute_twap` with a choice of what the intervals are weighted by
+pub fn weighted_twap(price_points: &[PricePoint], weighting: Weighting) -> f64 {
+    let mut total_weighted_price = 0.0f64;
+    let mut total_weight = 0u64;
+
+    for pair in price_points.windows(2) {
+        let weight = match weighting {
+            Weighting::Time => pair[1].timestamp - pair[0].timestamp,
+            Weighting::Blocks => pair[1].block - pair[0].block,
+        };
+        total_weighted_price += interval_price(pair) * weight as f64;
+        total_weight += weight;
+    }
+
+    if total_weight > 0 {
+        total_weighted_price / total_weight as f64
+    } else {
+        price_points.last().map_or(0.0, |p| p.price)
+    }
+}
+
+/// Mean of the prices at an interval's two ends, which the trapezoidal TWAP weights it by
+fn interval_price(pair: &[PricePoint]) -> f64 {
+    (pair[0].price + pair[1].price) / 2.0
+}
+
+/// The trapezoidal integral of the price over [from, to] within one interval, interpolating the
+/// price linearly between the interval's two samples
+fn partial_integral(pair: &[PricePoint], from: u64, to: u64) -> f64 {
+    let (start, end) = (pair[0].timestamp, pair[1].timestamp);
+    let at = |t: u64| {
+        if end == start {
+            pair[0].price
+        } else {
+            pair[0].price + (pair[1].price - pair[0].price) * (t - start) as f64 / (end - start) as f64
+        }
+    };
+    (at(from) + at(to)) / 2.0 * (to - from) as f64
+}
+
+/// Trapezoidal TWAP with the intervals touching a gap sample left out, so a gap's price
+/// neither counts nor is bridged by its neighbors. Also returns the seconds the gaps covered;
+/// the average is None when every interval is a gap.
+pub fn twap_skipping_gaps(price_points: &[PricePoint], is_gap: impl Fn(&PricePoint) -> bool) -> (Option<f64>, u64) {
+    let (mut weighted, mut included, mut gap_seconds) = (0.0f64, 0u64, 0u64);
+    for pair in price_points.windows(2) {
+        let seconds = pair[1].timestamp - pair[0].timestamp;
+        if is_gap(&pair[0]) || is_gap(&pair[1]) {
+            gap_seconds += seconds;
+        } else {
+            weighted += interval_price(pair) * seconds as f64;
+            included += seconds;
+        }
+    }
+    ((included > 0).then(|| weighted / included as f64), gap_seconds)
+}
+
+/// A copy of the series with each price replaced by the mean of the `window` samples centered on
+/// it. Near the ends the window is cut to the samples that exist, so the first and last prices
+/// are averaged over fewer neighbors rather than padded. `window` is odd.
+pub fn moving_average(price_points: &[PricePoint], window: usize) -> Vec<PricePoint> {
+    let half = window / 2;
+    (0..price_points.len())
+        .map(|i| {
+            let neighbors = &price_points[i.saturating_sub(half)..(i + half + 1).min(price_points.len())];
+            PricePoint {
+                block: price_points[i].block,
+                timestamp: price_points[i].timestamp,
+                price: neighbors.iter().map(|p| p.price).sum::<f64>() / neighbors.len() as f64,
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+/// Median of the sampled prices, the mean of the middle two for an even count
+pub fn median_price(price_points: &[PricePoint]) -> Option<f64> {
+    let mut prices: Vec<f64> = price_points.iter().map(|p| p.price).collect();
+    prices.sort_by(f64::total_cmp);
+    let middle = prices.len() / 2;
+    match prices.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((prices[middle - 1] + prices[middle]) / 2.0),
+        _ => Some(prices[middle]),
+    }
+}
+
+/// `compute_twap` over integer prices, so fixed-point prices never go through a float.
+/// `prices` pairs each sample's timestamp with its price.
+pub fn fixed_point_twap(prices: &[(u64, U256)]) -> Option<U256> {
+    let mut total_weighted_price = U512::zero();
+    let mut total_time = 0u64;
+
+    for pair in prices.windows(2) {
+        let time_diff = pair[1].0 - pair[0].0;
+        total_weighted_price += (U512::from(pair[0].1) + U512::from(pair[1].1)) * U512::from(time_diff);
+        total_time += time_diff;
+    }
+
+    // Each interval's endpoint sum counts twice its mean price, so the halving is left to the end
+    if total_time > 0 {
+        U256::try_from(total_weighted_price / (U512::from(total_time) * 2)).ok()
+    } else {
+        prices.last().map(|&(_, price)| price)
+    }
+}
+
+const SECONDS_PER_YEAR: f64 = 365.0 * 86400.0;
+
+/// Annualized volatility (in percent) from log returns between consecutive samples.
+///
+/// Each return is divided by the square root of its interval so unevenly spaced samples
+/// contribute comparably, then the per-second deviation is scaled to a year.
+pub fn annualized_volatility(price_points: &[PricePoint]) -> Option<f64> {
+    let scaled_returns: Vec<f64> = price_points
+        .windows(2)
+        .filter(|pair| pair[1].timestamp > pair[0].timestamp && pair[0].price > 0.0 && pair[1].price > 0.0)
+        .map(|pair| {
+            let dt = (pair[1].timestamp - pair[0].timestamp) as f64;
+            (pair[1].price / pair[0].price).ln() / dt.sqrt()
+        })
+        .collect();
+
+    if scaled_returns.len() < 2 {
+        return None;
+    }
+
+    let n = scaled_returns.len() as f64;
+    let mean = scaled_returns.iter().sum::<f64>() / n;
+    let variance = scaled_returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0);
+
+    Some(variance.sqrt() * SECONDS_PER_YEAR.sqrt() * 100.0)
+}
+
+/// Realized variance of the window's log returns, and that variance scaled to a year
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RealizedVariance {
+    /// Sum of squared log returns between consecutive samples
+    pub window_variance: f64,
+    pub annualized_variance: f64,
+    /// Square root of the annualized variance, in percent
+    pub annualized_volatility_pct: f64,
+    pub returns: usize,
+    /// Length of the year the variance was scaled to
+    pub annualization_days: f64,
+}
+
+impl RealizedVariance {
+    /// Scale a sum of squared returns spanning `span_seconds` to a year of `annualization_days`.
+    ///
+    /// The sum estimates the variance accumulated over the whole span however unevenly it was
+    /// sampled, so it's scaled by the span rather than a per-interval count.
+    fn from_sum(window_variance: f64, returns: usize, span_seconds: u64, annualization_days: f64) -> Option<Self> {
+        if returns == 0 || span_seconds == 0 {
+            return None;
+        }
+        let annualized_variance = window_variance * annualization_days * 86400.0 / span_seconds as f64;
+        Some(Self {
+            window_variance,
+            annualized_variance,
+            annualized_volatility_pct: annualized_variance.sqrt() * 100.0,
+            returns,
+            annualization_days,
+        })
+    }
+}
+
+/// Realized variance (sum of squared log returns) of the samples, annualized to `annualization_days`
+pub fn realized_variance(price_points: &[PricePoint], annualization_days: f64) -> Option<RealizedVariance> {
+    let mut sum = 0.0;
+    let mut returns = 0;
+    let mut span_seconds = 0;
+    for pair in price_points.windows(2) {
+        if pair[1].timestamp > pair[0].timestamp && pair[0].price > 0.0 && pair[1].price > 0.0 {
+            sum += (pair[1].price / pair[0].price).ln().powi(2);
+            returns += 1;
+            span_seconds += pair[1].timestamp - pair[0].timestamp;
+        }
+    }
+    RealizedVariance::from_sum(sum, returns, span_seconds, annualization_days)
+}
+
+/// Cheapest single-block push that skews the TWAP by a target percentage
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ManipulationCost {
+    pub target_pct: f64,
+    /// Sample that is cheapest to push, usually the one with the most weight or least depth
+    pub block: u64,
+    /// Share of the TWAP's total weight that sample carries: half of each interval it ends
+    pub weight_share: f64,
+    /// How far that sample's price has to move for the TWAP to move by the target
+    pub required_move_pct: f64,
+    /// Token1 swapped in to move the price that far on x * y = k, in whole tokens
+    pub capital_token1: f64,
+}
+
+/// Approximate the capital needed to raise the TWAP by `target_pct`, assuming the attacker moves
+/// one sample's price (no arbitrage before it's read), on a constant-product pool. Tries every
+/// sample and keeps the cheapest.
+///
+/// Raising one sample's price by a factor m adds `share * price * (m - 1)` to the TWAP, and moving
+/// an x * y = k pool's price by m takes `reserve1 * (sqrt(m) - 1)` of token1.
+pub fn manipulation_cost(
+    price_points: &[PricePoint],
+    weighting: Weighting,
+    target_pct: f64,
+    token1_decimals: u8,
+) -> Option<ManipulationCost> {
+    let twap = weighted_twap(price_points, weighting);
+    let intervals: Vec<u64> = price_points
+        .windows(2)
+        .map(|pair| match weighting {
+            Weighting::Time => pair[1].timestamp - pair[0].timestamp,
+            Weighting::Blocks => pair[1].block - pair[0].block,
+        })
+        .collect();
+    let total_weight: u64 = intervals.iter().sum();
+    if total_weight == 0 || twap <= 0.0 {
+        return None;
+    }
+
+    let mut cheapest: Option<ManipulationCost> = None;
+    for (i, point) in price_points.iter().enumerate() {
+        // Doubled: the trapezoidal TWAP gives a sample half of the interval on either side of it
+        let weight = i.checked_sub(1).map_or(0, |before| intervals[before]) + intervals.get(i).copied().unwrap_or(0);
+        let (Some(_), Some(reserve1)) = (point.reserve0, point.reserve1) else {
+            continue;
+        };
+        if weight == 0 || point.price <= 0.0 {
+            continue;
+        }
+        let weight_share = weight as f64 / (2 * total_weight) as f64;
+        let move_factor = target_pct / 100.0 * twap / (weight_share * point.price);
+        let reserve1 = reserve1 as f64 / 10f64.powi(token1_decimals as i32);
+        let capital_token1 = reserve1 * ((1.0 + move_factor).sqrt() - 1.0);
+        if cheapest.as_ref().is_none_or(|best| capital_token1 < best.capital_token1) {
+            cheapest = Some(ManipulationCost {
+                target_pct,
+                block: point.block,
+                weight_share,
+                required_move_pct: move_factor * 100.0,
+                capital_token1,
+            });
+        }
+    }
+    cheapest
+}
+
+/// Constant-product invariant k = reserve0 * reserve1 in whole-token units, if the sample has reserves.
+///
+/// The raw product is taken in U256, which holds any two uint112 reserves (or u128 balances)
+/// exactly, and only the result is scaled down as a float.
+pub fn invariant_k(point: &PricePoint, token0_decimals: u8, token1_decimals: u8) -> Option<f64> {
+    let raw_k = U256::from(point.reserve0?) * U256::from(point.reserve1?);
+    let scale = 10f64.powi(token0_decimals as i32) * 10f64.powi(token1_decimals as i32);
+    Some(pricing::u256_to_f64(raw_k) / scale)
+}
+
+/// Indices of samples that moved more than `threshold_pct` away from both neighbors in the
+/// same direction, i.e. a single-sample spike that snapped back
+pub fn detect_spikes(price_points: &[PricePoint], threshold_pct: f64) -> Vec<usize> {
+    let change_pct = |from: f64, to: f64| (to - from) / from * 100.0;
+
+    (1..price_points.len().saturating_sub(1))
+        .filter(|&i| {
+            let price = price_points[i].price;
+            let from_prev = change_pct(price_points[i - 1].price, price);
+            let from_next = change_pct(price_points[i + 1].price, price);
+            from_prev.abs() > threshold_pct
+                && from_next.abs() > threshold_pct
+                && from_prev.signum() == from_next.signum()
+        })
+        .collect()
+}
+
+/// One interval's step in the weighted sum behind the TWAP
+#[derive(Debug, Serialize)]
+pub struct AuditInterval {
+    pub from_block: u64,
+    pub to_block: u64,
+    /// Mean of the prices at the interval's two ends, which the whole interval is weighted by
+    pub price: f64,
+    /// Seconds or blocks, per the weighting
+    pub weight: u64,
+    /// price × weight
+    pub contribution: f64,
+    /// Sum of contributions so far
+    pub running_weighted_sum: f64,
+    pub running_weight: u64,
+}
+
+/// Every step of a TWAP computation, so it can be reproduced by hand
+#[derive(Debug, Serialize)]
+pub struct TwapAudit<'a> {
+    pub weighting: Weighting,
+    pub samples: &'a [PricePoint],
+    pub intervals: Vec<AuditInterval>,
+    pub total_weighted_sum: f64,
+    pub total_weight: u64,
+    /// total_weighted_sum / total_weight, or the last price when the samples span no time
+    pub twap: f64,
+}
+
+/// Record each interval of `weighted_twap` and the final division
+pub fn twap_audit(price_points: &[PricePoint], weighting: Weighting) -> TwapAudit<'_> {
+    let mut intervals = Vec::new();
+    let mut total_weighted_sum = 0.0f64;
+    let mut total_weight = 0u64;
+
+    for pair in price_points.windows(2) {
+        let weight = match weighting {
+            Weighting::Time => pair[1].timestamp - pair[0].timestamp,
+            Weighting::Blocks => pair[1].block - pair[0].block,
+        };
+        let price = interval_price(pair);
+        let contribution = price * weight as f64;
+        total_weighted_sum += contribution;
+        total_weight += weight;
+        intervals.push(AuditInterval {
+            from_block: pair[0].block,
+            to_block: pair[1].block,
+            price,
+            weight,
+            contribution,
+            running_weighted_sum: total_weighted_sum,
+            running_weight: total_weight,
+        });
+    }
+
+    TwapAudit {
+        weighting,
+        samples: price_points,
+        intervals,
+        total_weighted_sum,
+        total_weight,
+        twap: weighted_twap(price_points, weighting),
+    }
+}
+
+/// The worst peak-to-trough decline in the window, from --max-drawdown
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Drawdown {
+    pub peak_block: u64,
+    pub peak_timestamp: u64,
+    pub peak_price: f64,
+    pub trough_block: u64,
+    pub trough_timestamp: u64,
+    pub trough_price: f64,
+    /// Decline from the peak to the trough, as a positive percent
+    pub drawdown_pct: f64,
+    /// First sample after the trough back at or above the peak, if the price recovered in the window
+    pub recovered_block: Option<u64>,
+    pub recovered_timestamp: Option<u64>,
+}
+
+/// Find the largest decline from a running peak to a later sample, in one pass over the
+/// time-ordered samples. None with fewer than two samples.
+pub fn max_drawdown(price_points: &[PricePoint]) -> Option<Drawdown> {
+    if price_points.len() < 2 {
+        return None;
+    }
+    let (mut peak, mut worst, mut drawdown_pct) = (0, (0, 0), 0.0);
+    for (i, point) in price_points.iter().enumerate() {
+        if point.price > price_points[peak].price {
+            peak = i;
+        }
+        let decline_pct = (1.0 - point.price / price_points[peak].price) * 100.0;
+        if decline_pct > drawdown_pct {
+            (worst, drawdown_pct) = ((peak, i), decline_pct);
+        }
+    }
+
+    let (peak, trough) = (&price_points[worst.0], &price_points[worst.1]);
+    let recovered = (drawdown_pct > 0.0).then(|| price_points[worst.1..].iter().find(|point| point.price >= peak.price)).flatten();
+    Some(Drawdown {
+        peak_block: peak.block,
+        peak_timestamp: peak.timestamp,
+        peak_price: peak.price,
+        trough_block: trough.block,
+        trough_timestamp: trough.timestamp,
+        trough_price: trough.price,
+        drawdown_pct,
+        recovered_block: recovered.map(|point| point.block),
+        recovered_timestamp: recovered.map(|point| point.timestamp),
+    })
+}
+
+/// Deviation of a sample's price from the TWAP of all samples before it
+#[derive(Debug)]
+pub struct DeviationPoint {
+    pub timestamp: u64,
+    pub price: f64,
+    pub trailing_twap: f64,
+    pub deviation_pct: f64,
+}
+
+/// Compute each sample's deviation from the TWAP-so-far.
+///
+/// The first sample has no preceding interval, so the series starts at the second one.
+pub fn deviation_series(price_points: &[PricePoint]) -> Vec<DeviationPoint> {
+    let mut series = Vec::new();
+    let mut total_weighted_price = 0.0f64;
+    let mut total_time = 0u64;
+
+    for pair in price_points.windows(2) {
+        let (prev, point) = (&pair[0], &pair[1]);
+        let time_diff = point.timestamp - prev.timestamp;
+        total_weighted_price += interval_price(pair) * time_diff as f64;
+        total_time += time_diff;
+
+        if total_time == 0 {
+            continue;
+        }
+
+        let trailing_twap = total_weighted_price / total_time as f64;
+        series.push(DeviationPoint {
+            timestamp: point.timestamp,
+            price: point.price,
+            trailing_twap,
+            deviation_pct: (point.price - trailing_twap) / trailing_twap * 100.0,
+        });
+    }
+
+    series
+}
+
+/// Sample with the largest positive and largest negative deviation
+pub fn deviation_extremes(series: &[DeviationPoint]) -> Option<(&DeviationPoint, &DeviationPoint)> {
+    let max = series
+        .iter()
+        .max_by(|a, b| a.deviation_pct.total_cmp(&b.deviation_pct))?;
+    let min = series
+        .iter()
+        .min_by(|a, b| a.deviation_pct.total_cmp(&b.deviation_pct))?;
+    Some((max, min))
+}
+
+/// Render the deviation series as CSV
+pub fn deviation_series_csv(series: &[DeviationPoint]) -> String {
+    let mut csv = String::from("timestamp,price,trailing_twap,deviation_pct\n");
+    for point in series {
+        csv.push_str(&format!(
+            "{},{},{},{}\n",
+            point.timestamp, point.price, point.trailing_twap, point.deviation_pct
+        ));
+    }
+    csv
+}
+
+/// Open, high, low and close of the samples in one fixed time bucket
+#[derive(Debug, Serialize)]
+pub struct Candle {
+    /// Start of the bucket, a multiple of the interval since the Unix epoch
+    pub timestamp: u64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub samples: usize,
+}
+
+/// Bucket samples into `interval`-second candles aligned to the epoch. Buckets without samples
+/// are left out rather than carried forward.
+pub fn ohlc_candles(price_points: &[PricePoint], interval: u64) -> Vec<Candle> {
+    let mut candles: Vec<Candle> = Vec::new();
+    for point in price_points {
+        let bucket = point.timestamp - point.timestamp % interval;
+        match candles.last_mut() {
+            Some(candle) if candle.timestamp == bucket => {
+                candle.high = candle.high.max(point.price);
+                candle.low = candle.low.min(point.price);
+                candle.close = point.price;
+                candle.samples += 1;
+            }
+            _ => candles.push(Candle {
+                timestamp: bucket,
+                open: point.price,
+                high: point.price,
+                low: point.price,
+                close: point.price,
+                samples: 1,
+            }),
+        }
+    }
+    candles
+}
+
+/// Render candles as CSV
+pub fn ohlc_csv(candles: &[Candle]) -> String {
+    let mut csv = String::from("timestamp,open,high,low,close,samples\n");
+    for candle in candles {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            candle.timestamp, candle.open, candle.high, candle.low, candle.close, candle.samples
+        ));
+    }
+    csv
+}
+
+/// A sample's price next to the TWAP of the trailing window ending at it, for --compare-to-spot-series
+#[derive(Debug, Serialize)]
+pub struct SpotVsTwap {
+    pub block: u64,
+    pub timestamp: u64,
+    pub spot_price: f64,
+    pub trailing_twap: f64,
+    pub deviation_pct: f64,
+    /// 1 when the spot price crossed above the trailing TWAP at this sample, -1 when it crossed
+    /// below, 0 otherwise
+    pub cross: i8,
+    /// Whether a whole window of samples precedes this one; early samples average what there is
+    pub full_window: bool,
+}
+
+/// Pair each sample's price with the time-weighted TWAP of the `window` seconds before it.
+///
+/// The price moves linearly between samples, as in the trapezoidal TWAP. The window's integral is
+/// a difference of prefix sums with the start found by a pointer that only moves forward, so the
+/// series takes one pass. The first sample has no preceding interval, so the series starts at the
+/// second one.
+pub fn spot_vs_twap_series(price_points: &[PricePoint], window: u64) -> Vec<SpotVsTwap> {
+    let mut prefix = vec![0.0f64; price_points.len()];
+    for i in 1..price_points.len() {
+        let pair = &price_points[i - 1..=i];
+        prefix[i] = prefix[i - 1] + interval_price(pair) * (pair[1].timestamp - pair[0].timestamp) as f64;
+    }
+
+    let mut series: Vec<SpotVsTwap> = Vec::new();
+    let first = price_points.first().map_or(0, |point| point.timestamp);
+    let mut start = 0;
+    for (i, point) in price_points.iter().enumerate().skip(1) {
+        let from = point.timestamp.saturating_sub(window).max(first);
+        while start + 1 < i && price_points[start + 1].timestamp <= from {
+            start += 1;
+        }
+        let seconds = point.timestamp - from;
+        if seconds == 0 {
+            continue;
+        }
+
+        let anchor = &price_points[start..=start + 1];
+        let before_window = prefix[start] + partial_integral(anchor, anchor[0].timestamp, from);
+        let trailing_twap = (prefix[i] - before_window) / seconds as f64;
+        let deviation_pct = (point.price - trailing_twap) / trailing_twap * 100.0;
+        let cross = match series.last() {
+            Some(prev) if prev.deviation_pct <= 0.0 && deviation_pct > 0.0 => 1,
+            Some(prev) if prev.deviation_pct >= 0.0 && deviation_pct < 0.0 => -1,
+            _ => 0,
+        };
+        series.push(SpotVsTwap {
+            block: point.block,
+            timestamp: point.timestamp,
+            spot_price: point.price,
+            trailing_twap,
+            deviation_pct,
+            cross,
+            full_window: point.timestamp - first >= window,
+        });
+    }
+    series
+}
+
+/// Render the spot-vs-TWAP series as CSV
+pub fn spot_vs_twap_csv(series: &[SpotVsTwap]) -> String {
+    let mut csv = String::from("block,timestamp,spot_price,trailing_twap,deviation_pct,cross,full_window\n");
+    for point in series {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{}\n",
+            point.block, point.timestamp, point.spot_price, point.trailing_twap, point.deviation_pct, point.cross,
+            point.full_window
+        ));
+    }
+    csv
+}
+
+/// Time-weighted average over one --twap-of-twaps sub-period, [start, end)
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SubPeriodTwap {
+    pub start: u64,
+    pub end: u64,
+    pub twap: f64,
+}
+
+/// TWAPs over consecutive, non-overlapping sub-periods and their time-weighted aggregate
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct TwapOfTwaps {
+    pub period_seconds: u64,
+    /// Oldest first; the last one is cut short when the window isn't a whole number of periods
+    pub periods: Vec<SubPeriodTwap>,
+    /// The sub-TWAPs averaged by each period's length
+    pub aggregate: f64,
+}
+
+/// Split the window into `period`-second sub-periods from its first sample and time-weight each
+/// on its own with the trapezoidal rule. An interval crossing a boundary is split where the
+/// linearly interpolated price meets it, each part counting toward its own period. None with
+/// fewer than two samples.
+pub fn twap_of_twaps(price_points: &[PricePoint], period: u64) -> Option<TwapOfTwaps> {
+    let (first, last) = (price_points.first()?.timestamp, price_points.last()?.timestamp);
+    if last <= first || period == 0 {
+        return None;
+    }
+
+    let count = (last - first).div_ceil(period) as usize;
+    let mut weighted = vec![0.0f64; count];
+    for pair in price_points.windows(2) {
+        let mut from = pair[0].timestamp;
+        while from < pair[1].timestamp {
+            let index = ((from - first) / period) as usize;
+            let to = pair[1].timestamp.min(first + (index as u64 + 1) * period);
+            weighted[index] += partial_integral(pair, from, to);
+            from = to;
+        }
+    }
+
+    let periods: Vec<SubPeriodTwap> = weighted
+        .into_iter()
+        .enumerate()
+        .map(|(index, weighted)| {
+            let start = first + index as u64 * period;
+            let end = (start + period).min(last);
+            SubPeriodTwap { start, end, twap: weighted / (end - start) as f64 }
+        })
+        .collect();
+    let aggregate = periods.iter().map(|p| p.twap * (p.end - p.start) as f64).sum::<f64>() / (last - first) as f64;
+    Some(TwapOfTwaps { period_seconds: period, periods, aggregate })
+}
+
+/// How two pools sharing a token combine into a cross price of their other two tokens
+#[derive(Debug)]
+pub struct CrossLegs {
+    /// Pool A's non-shared token
+    pub base: Address,
+    /// Pool B's non-shared token
+    pub quote: Address,
+    /// The token both pools share
+    pub via: Address,
+    invert_a: bool,
+    invert_b: bool,
+}
+
+impl CrossLegs {
+    /// Work out the shared token from each pool's (token0, token1), or None unless exactly one is shared
+    pub fn new(pool_a: (Address, Address), pool_b: (Address, Address)) -> Option<Self> {
+        let shared: Vec<Address> = [pool_a.0, pool_a.1]
+            .into_iter()
+            .filter(|token| *token == pool_b.0 || *token == pool_b.1)
+            .collect();
+        let [via] = shared[..] else {
+            return None;
+        };
+
+        let base = if pool_a.0 == via { pool_a.1 } else { pool_a.0 };
+        let quote = if pool_b.0 == via { pool_b.1 } else { pool_b.0 };
+
+        Some(Self {
+            base,
+            quote,
+            via,
+            // Pool prices are token1 per token0; we need via per base and quote per via
+            invert_a: pool_a.0 != base,
+            invert_b: pool_b.0 != via,
+        })
+    }
+
+    /// Quote-per-base prices at every block both series sampled
+    pub fn combine(&self, points_a: &[PricePoint], points_b: &[PricePoint]) -> Vec<PricePoint> {
+        let orient = |price: f64, invert: bool| if invert { 1.0 / price } else { price };
+        let prices_b: HashMap<u64, f64> = points_b.iter().map(|p| (p.block, p.price)).collect();
+
+        points_a
+            .iter()
+            .filter_map(|a| {
+                let price_b = prices_b.get(&a.block)?;
+                Some(PricePoint {
+                    block: a.block,
+                    timestamp: a.timestamp,
+                    price: orient(a.price, self.invert_a) * orient(*price_b, self.invert_b),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+}
+
+/// Weighted basket of price series, each normalized to 1.0 at its first sample. `components`
+/// pairs each series with its weight; weights are used as given, so they should sum to 1.
+/// Only blocks every series sampled are included.
+pub fn composite_index(components: &[(f64, &[PricePoint])]) -> Vec<PricePoint> {
+    let Some(&(_, first)) = components.first() else {
+        return Vec::new();
+    };
+    let normalized: Vec<(f64, HashMap<u64, f64>)> = components
+        .iter()
+        .map(|&(weight, points)| {
+            let start = points.first().map_or(1.0, |p| p.price);
+            (weight, points.iter().map(|p| (p.block, p.price / start)).collect())
+        })
+        .collect();
+
+    first
+        .iter()
+        .filter_map(|point| {
+            let mut value = 0.0;
+            for (weight, series) in &normalized {
+                value += weight * series.get(&point.block)?;
+            }
+            Some(PricePoint {
+                block: point.block,
+                timestamp: point.timestamp,
+                price: value,
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Running summary of a time-ordered series, folded one sample at a time so the samples
+/// themselves don't have to be kept.
+///
+/// Produces the same TWAP as `compute_twap`; volatility uses Welford's online variance, which
+/// matches `annualized_volatility` up to floating-point rounding.
+#[derive(Debug)]
+pub struct StreamingTwap {
+    token0_decimals: u8,
+    token1_decimals: u8,
+    total_weighted_price: f64,
+    total_time: u64,
+    pub count: usize,
+    /// Block and timestamp of the first sample
+    pub first: Option<(u64, u64)>,
+    pub min_price: f64,
+    pub max_price: f64,
+    /// Most recent sample, kept for the spot price and the next interval's weight
+    pub last: Option<PricePoint>,
+    returns: usize,
+    returns_mean: f64,
+    returns_m2: f64,
+    /// Sum of squared log returns and the seconds they span, for the realized variance
+    realized_sum: f64,
+    realized_span: u64,
+    pub first_k: Option<f64>,
+    pub last_k: Option<f64>,
+    pub min_k: f64,
+    pub max_k: f64,
+}
+
+impl StreamingTwap {
+    pub fn new(token0_decimals: u8, token1_decimals: u8) -> Self {
+        Self {
+            token0_decimals,
+            token1_decimals,
+            total_weighted_price: 0.0,
+            total_time: 0,
+            count: 0,
+            first: None,
+            min_price: f64::INFINITY,
+            max_price: f64::NEG_INFINITY,
+            last: None,
+            returns: 0,
+            returns_mean: 0.0,
+            returns_m2: 0.0,
+            realized_sum: 0.0,
+            realized_span: 0,
+            first_k: None,
+            last_k: None,
+            min_k: f64::INFINITY,
+            max_k: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Fold in the next sample; samples must arrive in timestamp order
+    pub fn push(&mut self, point: PricePoint) {
+        if let Some(prev) = &self.last {
+            let time_diff = point.timestamp - prev.timestamp;
+            self.total_weighted_price += (prev.price + point.price) / 2.0 * time_diff as f64;
+            self.total_time += time_diff;
+
+            if time_diff > 0 && prev.price > 0.0 && point.price > 0.0 {
+                let scaled_return = (point.price / prev.price).ln() / (time_diff as f64).sqrt();
+                self.returns += 1;
+                let delta = scaled_return - self.returns_mean;
+                self.returns_mean += delta / self.returns as f64;
+                self.returns_m2 += delta * (scaled_return - self.returns_mean);
+                self.realized_sum += (point.price / prev.price).ln().powi(2);
+                self.realized_span += time_diff;
+            }
+        }
+
+        if let Some(k) = invariant_k(&point, self.token0_decimals, self.token1_decimals) {
+            self.first_k.get_or_insert(k);
+            self.last_k = Some(k);
+            self.min_k = self.min_k.min(k);
+            self.max_k = self.max_k.max(k);
+        }
+
+        self.first.get_or_insert((point.block, point.timestamp));
+        self.count += 1;
+        self.min_price = self.min_price.min(point.price);
+        self.max_price = self.max_price.max(point.price);
+        self.last = Some(point);
+    }
+
+    /// TWAP of everything folded so far, falling back to the last price when no time has passed
+    pub fn twap(&self) -> f64 {
+        if self.total_time > 0 {
+            self.total_weighted_price / self.total_time as f64
+        } else {
+            self.last.as_ref().map_or(0.0, |p| p.price)
+        }
+    }
+
+    /// Annualized volatility in percent, as in `annualized_volatility`
+    pub fn annualized_volatility(&self) -> Option<f64> {
+        if self.returns < 2 {
+            return None;
+        }
+        let variance = self.returns_m2 / (self.returns - 1) as f64;
+        Some(variance.sqrt() * SECONDS_PER_YEAR.sqrt() * 100.0)
+    }
+
+    /// Realized variance of everything folded so far, as in `realized_variance`
+    pub fn realized_variance(&self, annualization_days: f64) -> Option<RealizedVariance> {
+        RealizedVariance::from_sum(self.realized_sum, self.returns, self.realized_span, annualization_days)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(timestamp: u64, price: f64) -> PricePoint {
+        PricePoint { block: timestamp / 2, timestamp, price, ..Default::default() }
+    }
+
+    #[test]
+    fn trapezoidal_differs_from_left_endpoint() {
+        // A price rising 1 -> 2 -> 4 over two equal intervals: left endpoints give (1 + 2) / 2,
+        // trapezoids give (1.5 + 3) / 2
+        let series = [point(0, 1.0), point(100, 2.0), point(200, 4.0)];
+        let left_endpoint = series.windows(2).map(|pair| pair[0].price).sum::<f64>() / 2.0;
+        assert_eq!(left_endpoint, 1.5);
+        assert_eq!(compute_twap(&series), 2.25);
+        assert_eq!(twap_skipping_gaps(&series, |_| false), (Some(2.25), 0));
+        assert_eq!(twap_of_twaps(&series, 200).unwrap().aggregate, 2.25);
+    }
+
+    #[test]
+    fn samples_in_the_same_block_add_nothing() {
+        let series = [point(0, 1.0), point(100, 3.0), point(100, 5.0), point(200, 3.0)];
+        let twap = compute_twap(&series);
+        assert!(!twap.is_nan());
+        assert_eq!(twap, 3.0);
+        assert!(spot_vs_twap_series(&series, 150).iter().all(|s| !s.trailing_twap.is_nan()));
+    }
+
+    #[test]
+    fn single_sample_falls_back_to_its_price() {
+        assert_eq!(compute_twap(&[point(100, 1.25)]), 1.25);
+        assert_eq!(compute_twap(&[point(100, 1.25), point(100, 1.5)]), 1.5);
+        assert_eq!(twap_skipping_gaps(&[point(100, 1.25)], |_| false), (None, 0));
+        assert!(twap_of_twaps(&[point(100, 1.25)], 60).is_none());
+    }
+
+    #[test]
+    fn sub_periods_split_an_interval_at_the_interpolated_price() {
+        // 0 -> 4 over 100s, split at 50s: the halves average 1 and 3
+        let series = [point(0, 0.0), point(100, 4.0)];
+        let split = twap_of_twaps(&series, 50).unwrap();
+        assert_eq!(split.periods.iter().map(|p| p.twap).collect::<Vec<_>>(), vec![1.0, 3.0]);
+        assert_eq!(split.aggregate, 2.0);
+    }
+
+    #[test]
+    fn invariant_k_holds_uint112_reserves() {
+        let max = (1u128 << 112) - 1;
+        let full = PricePoint { reserve0: Some(max), reserve1: Some(max), ..point(0, 1.0) };
+        let k = invariant_k(&full, 18, 18).unwrap();
+        assert!((k - 2f64.powi(224) / 1e36).abs() <= k * 1e-15);
+        assert_eq!(invariant_k(&point(0, 1.0), 18, 18), None);
+    }
+
+    #[test]
+    fn gap_samples_drop_the_intervals_on_both_sides() {
+        let series = [point(0, 1.0), point(100, 1000.0), point(200, 1.0), point(300, 3.0)];
+        let (twap, gap_seconds) = twap_skipping_gaps(&series, |p| p.price > 100.0);
+        assert_eq!(twap, Some(2.0));
+        assert_eq!(gap_seconds, 200);
+    }
+}
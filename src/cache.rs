@@ -0,0 +1,121 @@
+use anyhow::{Context, Result};
+use ethers::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::error::TwapError;
+
+/// (chain id, pool, block)
+type Key = (u64, Address, u64);
+
+/// One cached reserves read, as stored in the cache file
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    chain_id: u64,
+    pool: Address,
+    block: u64,
+    reserve0: u128,
+    reserve1: u128,
+}
+
+/// Reserves memoized by (chain id, pool, block), optionally persisted to a JSON file.
+///
+/// Only blocks at least `reorg_depth` below the chain head when the cache was opened are
+/// stored, since only those reserves can't change any more.
+#[derive(Debug)]
+pub struct ReservesCache {
+    chain_id: u64,
+    immutable_before: AtomicU64,
+    path: Option<String>,
+    entries: Mutex<HashMap<Key, (u128, u128)>>,
+    inserted: Mutex<usize>,
+}
+
+impl ReservesCache {
+    /// Open the cache, loading `path` if it exists
+    pub async fn open(provider: &Provider<Http>, path: Option<&str>, reorg_depth: u64) -> Result<Self> {
+        let unavailable = |e: ProviderError| TwapError::RpcUnavailable(e.to_string());
+        let chain_id = provider.get_chainid().await.map_err(unavailable)?.as_u64();
+        let latest_block = provider.get_block_number().await.map_err(unavailable)?;
+
+        let mut entries = HashMap::new();
+        if let Some(path) = path {
+            match std::fs::read_to_string(path) {
+                Ok(json) => {
+                    let stored: Vec<Entry> = serde_json::from_str(&json)
+                        .context(format!("Reserves cache {} is corrupt; delete it to start over", path))?;
+                    for entry in stored {
+                        entries.insert((entry.chain_id, entry.pool, entry.block), (entry.reserve0, entry.reserve1));
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e).context(format!("Failed to read reserves cache {}", path)),
+            }
+        }
+
+        Ok(Self {
+            chain_id,
+            immutable_before: AtomicU64::new(latest_block.as_u64().saturating_sub(reorg_depth)),
+            path: path.map(str::to_string),
+            entries: Mutex::new(entries),
+            inserted: Mutex::new(0),
+        })
+    }
+
+    /// Cached reserves of a pool at a block
+    pub fn get(&self, pool: Address, block: u64) -> Option<(u128, u128)> {
+        self.entries.lock().unwrap().get(&(self.chain_id, pool, block)).copied()
+    }
+
+    /// Remember reserves read at a block, unless it's too close to the head to be final
+    pub fn insert(&self, pool: Address, block: u64, reserves: (u128, u128)) {
+        if block >= self.immutable_before.load(Ordering::Relaxed) {
+            return;
+        }
+        if self.entries.lock().unwrap().insert((self.chain_id, pool, block), reserves).is_none() {
+            *self.inserted.lock().unwrap() += 1;
+        }
+    }
+
+    /// Move the cache along with a long-running poller: blocks `reorg_depth` below the new head
+    /// become cacheable, and entries below `keep_from` are dropped so memory stays bounded to the window
+    pub fn advance(&self, latest_block: u64, reorg_depth: u64, keep_from: u64) {
+        self.immutable_before.fetch_max(latest_block.saturating_sub(reorg_depth), Ordering::Relaxed);
+        self.entries.lock().unwrap().retain(|&(_, _, block), _| block >= keep_from);
+    }
+
+    /// Write the cache back to its file, if it has one and anything new was read since the last save
+    pub fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        // Held for the whole write, so concurrent saves from the server don't interleave
+        let mut inserted = self.inserted.lock().unwrap();
+        if *inserted == 0 {
+            return Ok(());
+        }
+
+        let mut stored: Vec<Entry> = self
+            .entries
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(&(chain_id, pool, block), &(reserve0, reserve1))| Entry {
+                chain_id,
+                pool,
+                block,
+                reserve0,
+                reserve1,
+            })
+            .collect();
+        stored.sort_by_key(|entry| (entry.chain_id, entry.pool, entry.block));
+
+        let json = serde_json::to_string(&stored)?;
+        std::fs::write(path, json).context(format!("Failed to write reserves cache {}", path))?;
+        status!("🗄️  Cached {} new reserve reads in {}", *inserted, path);
+        *inserted = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use anyhow::{Context, Result};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+
+use crate::PricePoint;
+
+/// What a checkpoint was collected for, stored as its first line
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Header {
+    pub pool: Address,
+    pub days: u64,
+    pub samples: u64,
+    pub end_block: u64,
+}
+
+/// An append-only JSON-lines file of collected samples: a header line, then one PricePoint per line
+pub struct Checkpoint {
+    path: String,
+    file: Option<File>,
+    pub points: Vec<PricePoint>,
+}
+
+/// The header of an existing checkpoint, or None if there's no checkpoint at `path` yet
+pub fn read_header(path: &str) -> Result<Option<Header>> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context(format!("Failed to read checkpoint {}", path)),
+    };
+    let Some(line) = BufReader::new(file).lines().next() else {
+        return Ok(None);
+    };
+    let line = line.context(format!("Failed to read checkpoint {}", path))?;
+    let header = serde_json::from_str(&line).context(format!("Checkpoint {} has no valid header; delete it to start over", path))?;
+    Ok(Some(header))
+}
+
+impl Checkpoint {
+    /// Load the samples already in `path`, or start a new checkpoint there.
+    /// Fails if the existing checkpoint was collected for a different pool or window.
+    pub fn open(path: &str, header: Header) -> Result<Self> {
+        let mut points = Vec::new();
+        match read_header(path)? {
+            Some(existing) => {
+                if existing != header {
+                    anyhow::bail!(
+                        "Checkpoint {} is for a different run (pool {:?}, {} days, {} samples, end block {}); \
+                         delete it or pick another --resume path",
+                        path,
+                        existing.pool,
+                        existing.days,
+                        existing.samples,
+                        existing.end_block
+                    );
+                }
+                let file = File::open(path).context(format!("Failed to read checkpoint {}", path))?;
+                for line in BufReader::new(file).lines().skip(1) {
+                    let line = line.context(format!("Failed to read checkpoint {}", path))?;
+                    // A run killed mid-write leaves a truncated last line; that sample is just fetched again
+                    match serde_json::from_str(&line) {
+                        Ok(point) => points.push(point),
+                        Err(_) => break,
+                    }
+                }
+                // Rewrite without any truncated tail so new lines append cleanly
+                let mut contents = serde_json::to_string(&header)? + "\n";
+                for point in &points {
+                    contents += &(serde_json::to_string(point)? + "\n");
+                }
+                std::fs::write(path, contents).context(format!("Failed to write checkpoint {}", path))?;
+            }
+            None => {
+                std::fs::write(path, serde_json::to_string(&header)? + "\n")
+                    .context(format!("Failed to write checkpoint {}", path))?;
+            }
+        }
+
+        let file = OpenOptions::new()
+            .append(true)
+            .open(path)
+            .context(format!("Failed to open checkpoint {}", path))?;
+        Ok(Self {
+            path: path.to_string(),
+            file: Some(file),
+            points,
+        })
+    }
+
+    /// Append a sample; a failed write only warns, since the run itself can still finish
+    pub fn record(&mut self, point: &PricePoint) {
+        let Some(file) = &mut self.file else {
+            return;
+        };
+        let written = serde_json::to_string(point)
+            .map_err(std::io::Error::from)
+            .and_then(|line| writeln!(file, "{}", line));
+        if let Err(e) = written {
+            status!("⚠️  Failed to write checkpoint {} ({}); no further samples will be checkpointed", self.path, e);
+            self.file = None;
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use anyhow::{Context, Result};
+use ethers::prelude::*;
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::metadata::TokenCache;
+use crate::{events, report};
+use crate::AerodromePool;
+
+/// Aerodrome's PoolFactory on Base
+pub const AERODROME_FACTORY: &str = "0x420DD381b31aEf6683db6B902084cB0FFECe40Da";
+
+/// Aerodrome's Voter on Base, which maps pools to their gauges
+pub const AERODROME_VOTER: &str = "0x16613524e02ad97eDfeF371bC883F2F5d6C480A5";
+
+/// A block before the Aerodrome factory was deployed, where the PoolCreated scan starts
+pub const AERODROME_FACTORY_START_BLOCK: u64 = 2_500_000;
+
+const POOL_CREATED_SIGNATURE: &str = "PoolCreated(address,address,bool,address,uint256)";
+
+/// A pool found for a token
+#[derive(Debug, Serialize)]
+pub struct PoolListing {
+    #[serde(serialize_with = "report::serialize_checksummed")]
+    pub pool: Address,
+    #[serde(serialize_with = "report::serialize_checksummed")]
+    pub paired_token: Address,
+    pub paired_symbol: String,
+    pub stable: bool,
+    /// Current reserves in whole tokens: the listed token first, then the paired token
+    pub token_reserve: f64,
+    pub paired_reserve: f64,
+}
+
+/// Find every pool the factory created that contains `token`, by scanning PoolCreated events
+pub async fn list_pools(
+    provider: &Arc<Provider<Http>>,
+    token_cache: &mut TokenCache,
+    factory: Address,
+    token: Address,
+    from_block: U64,
+    chunk_size: u64,
+) -> Result<Vec<PoolListing>> {
+    let latest_block = provider.get_block_number().await.context("Failed to get current block")?;
+    let topic0 = H256::from(ethers::utils::keccak256(POOL_CREATED_SIGNATURE.as_bytes()));
+    let token_topic = H256::from(token);
+
+    // The token is indexed as either token0 (topic1) or token1 (topic2)
+    let as_token0 = Filter::new().address(factory).topic0(topic0).topic1(token_topic);
+    let as_token1 = Filter::new().address(factory).topic0(topic0).topic2(token_topic);
+
+    let mut logs = events::fetch_logs_chunked(provider, as_token0, from_block, latest_block, chunk_size).await?;
+    logs.extend(events::fetch_logs_chunked(provider, as_token1, from_block, latest_block, chunk_size).await?);
+
+    let mut listings = Vec::new();
+    for log in logs {
+        let (Some(token0_topic), Some(token1_topic), Some(stable_topic)) =
+            (log.topics.get(1), log.topics.get(2), log.topics.get(3))
+        else {
+            continue;
+        };
+        if log.data.len() < 32 {
+            continue;
+        }
+
+        let pool_address = Address::from_slice(&log.data[12..32]);
+        let token0 = Address::from(*token0_topic);
+        let token1 = Address::from(*token1_topic);
+        let token_is_token0 = token0 == token;
+        let paired = if token_is_token0 { token1 } else { token0 };
+
+        let token_info = token_cache.get(token).await?;
+        let paired_info = token_cache.get(paired).await?;
+
+        let pool = AerodromePool::new(pool_address, provider.clone());
+        let (reserve0, reserve1, _) = pool
+            .get_reserves()
+            .call()
+            .await
+            .context(format!("Failed to get reserves for pool {}", report::checksum(&pool_address)))?;
+        let (token_reserve, paired_reserve) = if token_is_token0 {
+            (reserve0, reserve1)
+        } else {
+            (reserve1, reserve0)
+        };
+
+        listings.push(PoolListing {
+            pool: pool_address,
+            paired_token: paired,
+            paired_symbol: paired_info.symbol,
+            stable: !stable_topic.is_zero(),
+            token_reserve: token_reserve as f64 / 10f64.powi(token_info.decimals as i32),
+            paired_reserve: paired_reserve as f64 / 10f64.powi(paired_info.decimals as i32),
+        });
+    }
+
+    Ok(listings)
+}
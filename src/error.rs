@@ -0,0 +1,78 @@
+use ethers::types::Address;
+use thiserror::Error;
+
+use crate::report;
+
+/// Failures callers may want to tell apart, rather than just print
+#[derive(Debug, Error)]
+pub enum TwapError {
+    #[error("RPC endpoint is unavailable: {0}")]
+    RpcUnavailable(String),
+
+    #[error("{} is not a pool (no contract code, or token0()/token1() failed)", report::checksum(.0))]
+    NotAPool(Address),
+
+    #[error("No price data collected")]
+    NoData,
+
+    #[error("Timestamp {0} is after the latest block")]
+    FutureTimestamp(u64),
+
+    #[error("RPC node has no state for block {0}; use an archive node for historical reads")]
+    ArchiveRequired(u64),
+
+    #[error("Block {0} is not available yet, possibly mid-reorg")]
+    BlockUnavailable(u64),
+
+    #[error("Can't reach the RPC endpoint {0}: {1}. Check the --rpc URL (and --proxy, if set), or try another endpoint")]
+    RpcUnreachable(String, String),
+
+    #[error("RPC endpoint {0} answered with an error: {1}. Check the --rpc URL, including any API key in it, or try another endpoint")]
+    RpcRejected(String, String),
+
+    #[error("Invalid TwapConfig: {0}")]
+    InvalidConfig(String),
+}
+
+impl TwapError {
+    /// Process exit code for this failure. 1 is any other error and 3 is a breached deviation threshold.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            TwapError::RpcUnavailable(_) => 4,
+            TwapError::NotAPool(_) => 5,
+            TwapError::NoData => 6,
+            TwapError::FutureTimestamp(_) => 7,
+            TwapError::ArchiveRequired(_) => 8,
+            TwapError::BlockUnavailable(_) => 9,
+            TwapError::RpcUnreachable(..) => 12,
+            TwapError::RpcRejected(..) => 13,
+            TwapError::InvalidConfig(_) => 14,
+        }
+    }
+
+    /// Whether retrying the same call could succeed
+    pub fn is_transient(&self) -> bool {
+        matches!(self, TwapError::RpcUnavailable(_) | TwapError::BlockUnavailable(_))
+    }
+}
+
+/// Messages non-archive nodes return when asked for pruned historical state
+const PRUNED_STATE_MESSAGES: [&str; 4] = [
+    "missing trie node",
+    "header not found",
+    "historical state",
+    "state is not available",
+];
+
+/// Turn a failed read at a historical block into `ArchiveRequired` if the node has pruned that
+/// state, or a contextualized error otherwise
+pub fn historical_read<E>(error: E, what: &str, block: u64) -> anyhow::Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let message = error.to_string().to_lowercase();
+    if PRUNED_STATE_MESSAGES.iter().any(|pruned| message.contains(pruned)) {
+        return TwapError::ArchiveRequired(block).into();
+    }
+    anyhow::Error::new(error).context(format!("Failed to get {} at block {}", what, block))
+}
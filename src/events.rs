@@ -0,0 +1,124 @@
+use anyhow::{Context, Result};
+use ethers::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::pricing;
+use crate::PricePoint;
+
+/// Relative price change below which a move without swaps is put down to mint/burn rounding
+const UNEXPLAINED_MOVE_TOLERANCE: f64 = 1e-6;
+
+/// `Swap` event signatures: Aerodrome's, and the Uniswap V2 layout some forks keep
+const SWAP_EVENT_SIGNATURES: [&str; 2] = [
+    "Swap(address,address,uint256,uint256,uint256,uint256)",
+    "Swap(address,uint256,uint256,uint256,uint256,address)",
+];
+
+/// Fetch the pool's `Swap` logs between two blocks (inclusive), `chunk_size` blocks per request
+pub async fn fetch_swap_logs(
+    provider: &Provider<Http>,
+    pool_address: Address,
+    from_block: U64,
+    to_block: U64,
+    chunk_size: u64,
+) -> Result<Vec<Log>> {
+    let topics: Vec<H256> = SWAP_EVENT_SIGNATURES
+        .iter()
+        .map(|signature| H256::from(ethers::utils::keccak256(signature.as_bytes())))
+        .collect();
+
+    let filter = Filter::new().address(pool_address).topic0(topics);
+    fetch_logs_chunked(provider, filter, from_block, to_block, chunk_size).await
+}
+
+/// Run a log filter over a block range (inclusive), `chunk_size` blocks per request
+pub async fn fetch_logs_chunked(
+    provider: &Provider<Http>,
+    filter: Filter,
+    from_block: U64,
+    to_block: U64,
+    chunk_size: u64,
+) -> Result<Vec<Log>> {
+    let mut logs = Vec::new();
+    let mut chunk_start = from_block.as_u64();
+    let chunk_size = chunk_size.max(1);
+
+    while chunk_start <= to_block.as_u64() {
+        let chunk_end = (chunk_start + chunk_size - 1).min(to_block.as_u64());
+
+        let chunk = provider
+            .get_logs(&filter.clone().from_block(chunk_start).to_block(chunk_end))
+            .await
+            .context(format!("Failed to get logs for blocks {}-{}", chunk_start, chunk_end))?;
+        logs.extend(chunk);
+
+        chunk_start = chunk_end + 1;
+    }
+
+    Ok(logs)
+}
+
+/// Indices of samples whose price moved from the previous sample's with no Swap log in between
+/// (blocks after the previous sample through this one). Mints and burns keep the price, so such a
+/// move usually means a balance changed under the pool, as a rebase followed by `sync()` does.
+pub fn moves_without_swaps(price_points: &[PricePoint], swap_logs: &[Log]) -> Vec<usize> {
+    let swap_blocks: BTreeSet<u64> = swap_logs.iter().filter_map(|log| log.block_number).map(|b| b.as_u64()).collect();
+    (1..price_points.len())
+        .filter(|&i| {
+            let (previous, current) = (&price_points[i - 1], &price_points[i]);
+            let moved = ((current.price - previous.price) / previous.price).abs() > UNEXPLAINED_MOVE_TOLERANCE;
+            moved && swap_blocks.range(previous.block + 1..=current.block).next().is_none()
+        })
+        .collect()
+}
+
+/// Distinct blocks containing at least one of the logs, in ascending order
+pub fn log_blocks(logs: &[Log]) -> Vec<U64> {
+    logs.iter()
+        .filter_map(|log| log.block_number)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect()
+}
+
+/// Blocks at which the running count of the logs, in chain order, reaches each multiple of
+/// `per_sample`. Several thresholds crossed in one block give a single sample block.
+pub fn swap_count_blocks(logs: &[Log], per_sample: u64) -> Vec<U64> {
+    let mut ordered: Vec<(U64, U256)> = logs
+        .iter()
+        .filter_map(|log| Some((log.block_number?, log.log_index.unwrap_or_default())))
+        .collect();
+    ordered.sort();
+
+    let mut blocks: Vec<U64> = ordered
+        .iter()
+        .skip(per_sample.saturating_sub(1) as usize)
+        .step_by(per_sample.max(1) as usize)
+        .map(|&(block, _)| block)
+        .collect();
+    blocks.dedup();
+    blocks
+}
+
+/// Execution price (token1 per token0) of each decodable swap, grouped by block in log order.
+///
+/// Both supported layouts carry `amount0In, amount1In, amount0Out, amount1Out` as their data, and
+/// the price is the token1 moved over the token0 moved, so it includes the fee and price impact.
+pub fn swap_prices_by_block(logs: &[Log], token0_decimals: u8, token1_decimals: u8) -> BTreeMap<u64, Vec<f64>> {
+    let mut ordered: Vec<&Log> = logs.iter().collect();
+    ordered.sort_by_key(|log| (log.block_number, log.log_index));
+
+    let mut prices: BTreeMap<u64, Vec<f64>> = BTreeMap::new();
+    for log in ordered {
+        let (Some(block), Some(amounts)) = (log.block_number, log.data.get(..128)) else {
+            continue;
+        };
+        let amount = |i: usize| pricing::u256_to_f64(U256::from_big_endian(&amounts[i * 32..(i + 1) * 32]));
+        let amount0 = (amount(0) + amount(2)) / 10f64.powi(token0_decimals as i32);
+        let amount1 = (amount(1) + amount(3)) / 10f64.powi(token1_decimals as i32);
+        if amount0 > 0.0 && amount1 > 0.0 {
+            prices.entry(block.as_u64()).or_default().push(amount1 / amount0);
+        }
+    }
+    prices
+}
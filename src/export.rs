@@ -0,0 +1,311 @@
+use anyhow::{Context, Result};
+
+use ethers::types::U256;
+use serde_json::ser::Formatter;
+use std::io;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::PricePoint;
+
+/// Column layout shared by `--csv` and `--from-csv`
+const CSV_HEADER: &str = "block,timestamp,price,reserve0,reserve1,sqrt_price_x96,tick";
+
+/// Extra last column when the samples were tagged under --record-endpoint
+const ENDPOINT_COLUMN: &str = "endpoint";
+
+/// Write samples as CSV, leaving columns empty when a sample doesn't carry that raw value
+pub fn write_csv(path: &str, price_points: &[PricePoint]) -> Result<()> {
+    std::fs::write(path, samples_csv(price_points)).context(format!("Failed to write CSV to {}", path))
+}
+
+/// Samples in `write_csv`'s layout, for `--format csv` on stdout
+pub fn samples_csv(price_points: &[PricePoint]) -> String {
+    let with_endpoint = price_points.iter().any(|p| p.endpoint.is_some());
+    let mut csv = if with_endpoint {
+        format!("{},{}\n", CSV_HEADER, ENDPOINT_COLUMN)
+    } else {
+        format!("{}\n", CSV_HEADER)
+    };
+    for point in price_points {
+        csv.push_str(&format!(
+            "{},{},{},{},{},{},{}",
+            point.block,
+            point.timestamp,
+            point.price,
+            optional(point.reserve0),
+            optional(point.reserve1),
+            optional(point.sqrt_price_x96),
+            optional(point.tick),
+        ));
+        if with_endpoint {
+            csv.push_str(&format!(",{}", point.endpoint.as_deref().unwrap_or_default()));
+        }
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Write a composite index series as `block,timestamp,index` CSV
+pub fn write_index_csv(path: &str, series: &[PricePoint]) -> Result<()> {
+    let mut csv = String::from("block,timestamp,index\n");
+    for point in series {
+        csv.push_str(&format!("{},{},{}\n", point.block, point.timestamp, point.price));
+    }
+    std::fs::write(path, csv).context(format!("Failed to write CSV to {}", path))
+}
+
+/// Read samples back from a CSV written by `write_csv`
+pub fn read_csv(path: &str) -> Result<Vec<PricePoint>> {
+    let contents = std::fs::read_to_string(path).context(format!("Failed to read CSV from {}", path))?;
+    parse_csv(&contents)
+}
+
+/// Samples from CSV text in `write_csv`'s layout, ordered by timestamp
+fn parse_csv(contents: &str) -> Result<Vec<PricePoint>> {
+    let mut lines = contents.lines();
+
+    let header = lines.next().unwrap_or_default().trim();
+    let with_endpoint = header == format!("{},{}", CSV_HEADER, ENDPOINT_COLUMN);
+    if header != CSV_HEADER && !with_endpoint {
+        anyhow::bail!("Unexpected CSV header '{}'. Expected '{}'", header, CSV_HEADER);
+    }
+    let columns = if with_endpoint { 8 } else { 7 };
+
+    let mut price_points = Vec::new();
+    for (line_no, line) in lines.enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let row = line_no + 2;
+        let fields: Vec<&str> = line.trim().split(',').collect();
+        if fields.len() != columns {
+            anyhow::bail!("CSV row {} has {} columns, expected {}", row, fields.len(), columns);
+        }
+
+        price_points.push(PricePoint {
+            block: fields[0].parse().context(format!("Invalid block on row {}", row))?,
+            timestamp: fields[1].parse().context(format!("Invalid timestamp on row {}", row))?,
+            price: fields[2].parse().context(format!("Invalid price on row {}", row))?,
+            reserve0: parse_optional(fields[3]).context(format!("Invalid reserve0 on row {}", row))?,
+            reserve1: parse_optional(fields[4]).context(format!("Invalid reserve1 on row {}", row))?,
+            sqrt_price_x96: match fields[5] {
+                "" => None,
+                v => Some(U256::from_dec_str(v).context(format!("Invalid sqrt_price_x96 on row {}", row))?),
+            },
+            tick: parse_optional(fields[6]).context(format!("Invalid tick on row {}", row))?,
+            endpoint: fields.get(7).filter(|v| !v.is_empty()).map(|v| v.to_string()),
+            substitution: None,
+        });
+    }
+
+    price_points.sort_by_key(|p| p.timestamp);
+    Ok(price_points)
+}
+
+/// Format an optional CSV value, empty when absent
+fn optional<T: Display>(value: Option<T>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// Parse an optional CSV value, treating an empty field as absent
+fn parse_optional<T: FromStr>(field: &str) -> Result<Option<T>, T::Err> {
+    match field {
+        "" => Ok(None),
+        v => v.parse().map(Some),
+    }
+}
+
+/// Write samples as Parquet, one typed column per field with the raw values nullable.
+/// Reserves are 128-bit, so they're stored as decimal strings; sqrtPriceX96 likewise.
+#[cfg(feature = "parquet")]
+pub fn write_parquet(path: &str, price_points: &[PricePoint]) -> Result<()> {
+    use arrow_array::{ArrayRef, Float64Array, Int32Array, RecordBatch, StringArray, UInt64Array};
+    use arrow_schema::{DataType, Field, Schema};
+    use parquet::arrow::ArrowWriter;
+    use std::sync::Arc;
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new("block", DataType::UInt64, false),
+        Field::new("timestamp", DataType::UInt64, false),
+        Field::new("price", DataType::Float64, false),
+        Field::new("reserve0", DataType::Utf8, true),
+        Field::new("reserve1", DataType::Utf8, true),
+        Field::new("sqrt_price_x96", DataType::Utf8, true),
+        Field::new("tick", DataType::Int32, true),
+    ]));
+
+    let columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt64Array::from_iter_values(price_points.iter().map(|p| p.block))),
+        Arc::new(UInt64Array::from_iter_values(price_points.iter().map(|p| p.timestamp))),
+        Arc::new(Float64Array::from_iter_values(price_points.iter().map(|p| p.price))),
+        Arc::new(StringArray::from_iter(price_points.iter().map(|p| p.reserve0.map(|r| r.to_string())))),
+        Arc::new(StringArray::from_iter(price_points.iter().map(|p| p.reserve1.map(|r| r.to_string())))),
+        Arc::new(StringArray::from_iter(price_points.iter().map(|p| p.sqrt_price_x96.map(|s| s.to_string())))),
+        Arc::new(Int32Array::from_iter(price_points.iter().map(|p| p.tick))),
+    ];
+    let batch = RecordBatch::try_new(schema.clone(), columns).context("Failed to build Parquet batch")?;
+
+    let file = std::fs::File::create(path).context(format!("Failed to create {}", path))?;
+    let mut writer = ArrowWriter::try_new(file, schema, None).context("Failed to start Parquet writer")?;
+    writer.write(&batch).context(format!("Failed to write {}", path))?;
+    writer.close().context(format!("Failed to finish {}", path))?;
+    Ok(())
+}
+
+/// Stand-in when built without the `parquet` feature
+#[cfg(not(feature = "parquet"))]
+pub fn write_parquet(_path: &str, _price_points: &[PricePoint]) -> Result<()> {
+    anyhow::bail!("--parquet needs a build with Parquet support: cargo build --release --features parquet")
+}
+
+/// JSON with object keys sorted and whitespace dropped, the form --hash digests. Numbers and
+/// strings are kept exactly as written, so 128-bit reserves and float formatting survive intact.
+pub fn canonical_json(json: &str) -> Result<String> {
+    use serde_json::value::RawValue;
+    use std::collections::BTreeMap;
+
+    let json = json.trim();
+    if json.starts_with('{') {
+        let map: BTreeMap<String, Box<RawValue>> = serde_json::from_str(json)?;
+        let fields = map
+            .iter()
+            .map(|(key, value)| Ok(format!("{}:{}", serde_json::to_string(key)?, canonical_json(value.get())?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(format!("{{{}}}", fields.join(",")))
+    } else if json.starts_with('[') {
+        let items: Vec<Box<RawValue>> = serde_json::from_str(json)?;
+        let items = items.iter().map(|item| canonical_json(item.get())).collect::<Result<Vec<_>>>()?;
+        Ok(format!("[{}]", items.join(",")))
+    } else {
+        Ok(json.to_string())
+    }
+}
+
+/// Lowercase hex SHA-256 of `bytes`
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// JSON formatter that rounds floats to `digits` significant digits, so values like
+/// 1234.9999999998 come out as 1235. With `as_strings`, floats are written as decimal strings
+/// instead, for consumers that parse into their own decimal types. Everything else is left to
+/// the wrapped formatter.
+pub struct CompactFloats<F> {
+    pub inner: F,
+    pub digits: u8,
+    pub as_strings: bool,
+}
+
+impl<F: Formatter> Formatter for CompactFloats<F> {
+    fn write_f64<W: ?Sized + io::Write>(&mut self, writer: &mut W, value: f64) -> io::Result<()> {
+        let rounded: f64 = format!("{:.*e}", self.digits.saturating_sub(1) as usize, value)
+            .parse()
+            .unwrap_or(value);
+        if self.as_strings {
+            write!(writer, "\"{}\"", rounded)
+        } else {
+            self.inner.write_f64(writer, rounded)
+        }
+    }
+
+    fn begin_array<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.begin_array(writer)
+    }
+
+    fn end_array<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_array(writer)
+    }
+
+    fn begin_array_value<W: ?Sized + io::Write>(&mut self, writer: &mut W, first: bool) -> io::Result<()> {
+        self.inner.begin_array_value(writer, first)
+    }
+
+    fn end_array_value<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_array_value(writer)
+    }
+
+    fn begin_object<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.begin_object(writer)
+    }
+
+    fn end_object<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_object(writer)
+    }
+
+    fn begin_object_key<W: ?Sized + io::Write>(&mut self, writer: &mut W, first: bool) -> io::Result<()> {
+        self.inner.begin_object_key(writer, first)
+    }
+
+    fn end_object_key<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_object_key(writer)
+    }
+
+    fn begin_object_value<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.begin_object_value(writer)
+    }
+
+    fn end_object_value<W: ?Sized + io::Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_object_value(writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(block: u64, price: f64) -> PricePoint {
+        PricePoint { block, timestamp: block * 2, price, ..Default::default() }
+    }
+
+    #[test]
+    fn samples_round_trip_through_csv() {
+        let points = vec![
+            PricePoint {
+                reserve0: Some(u128::MAX >> 16),
+                reserve1: Some(1),
+                endpoint: Some("https://mainnet.base.org".to_string()),
+                ..sample(100, 1234.9999999998)
+            },
+            PricePoint { sqrt_price_x96: Some(U256::one() << 96), tick: Some(-887272), ..sample(101, 1.0) },
+        ];
+        let read = parse_csv(&samples_csv(&points)).unwrap();
+        assert_eq!(read.len(), points.len());
+        for (read, written) in read.iter().zip(&points) {
+            assert_eq!((read.block, read.timestamp, read.price), (written.block, written.timestamp, written.price));
+            assert_eq!((read.reserve0, read.reserve1), (written.reserve0, written.reserve1));
+            assert_eq!((read.sqrt_price_x96, read.tick), (written.sqrt_price_x96, written.tick));
+            assert_eq!(read.endpoint, written.endpoint);
+        }
+    }
+
+    #[test]
+    fn csv_rows_come_back_in_timestamp_order() {
+        let read = parse_csv(&samples_csv(&[sample(300, 3.0), sample(100, 1.0)])).unwrap();
+        assert_eq!(read.iter().map(|p| p.block).collect::<Vec<_>>(), vec![100, 300]);
+    }
+
+    #[test]
+    fn unexpected_csv_headers_are_rejected() {
+        let missing_price = "block,timestamp,reserve0,reserve1,sqrt_price_x96,tick\n1,2,,,,\n";
+        assert!(parse_csv(missing_price).unwrap_err().to_string().contains("Unexpected CSV header"));
+        let reordered = "block,price,timestamp,reserve0,reserve1,sqrt_price_x96,tick\n1,1.5,2,,,,\n";
+        assert!(parse_csv(reordered).unwrap_err().to_string().contains("Unexpected CSV header"));
+        let short_row = format!("{}\n1,2,1.5\n", CSV_HEADER);
+        assert!(parse_csv(&short_row).unwrap_err().to_string().contains("row 2"));
+    }
+
+    #[test]
+    fn canonical_json_sorts_keys_and_keeps_numbers_as_written() {
+        let json = r#"{ "b": 1, "a": [ { "d": 2, "c": 1.50 } ], "big": 340282366920938463463374607431768211455 }"#;
+        let canonical = canonical_json(json).unwrap();
+        assert_eq!(canonical, r#"{"a":[{"c":1.50,"d":2}],"b":1,"big":340282366920938463463374607431768211455}"#);
+        assert_eq!(canonical_json(&canonical).unwrap(), canonical);
+        let reordered = r#"{"big":340282366920938463463374607431768211455,"a":[{"c":1.50,"d":2}],"b":1}"#;
+        assert_eq!(sha256_hex(canonical_json(reordered).unwrap().as_bytes()), sha256_hex(canonical.as_bytes()));
+    }
+
+    #[test]
+    fn sha256_hex_matches_the_standard_digest() {
+        assert_eq!(sha256_hex(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(sha256_hex(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    }
+}
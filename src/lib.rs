@@ -0,0 +1,655 @@
+//! Time-weighted average prices of Aerodrome pools on Base, read from historical pool state.
+//!
+//! The `twap` binary is the command-line front end. Other programs can call [`compute_twap`] for a
+//! uniformly sampled TWAP, or put the pieces together themselves from [`sampler`], [`analytics`]
+//! and [`report`].
+
+use anyhow::{Context, Result};
+use chrono_tz::Tz;
+use ethers::prelude::*;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, OnceLock};
+
+use error::TwapError;
+
+/// Seconds in a day, which windows are measured in
+pub const SECONDS_PER_DAY: u64 = 86400;
+
+/// Set when stdout carries machine-readable output, so progress goes to stderr instead
+pub static STATUS_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Drops progress and diagnostic lines altogether. Set until cleared, so the library prints
+/// nothing unless asked; the binary clears it unless --quiet is given
+pub static QUIET: AtomicBool = AtomicBool::new(true);
+
+/// Timezone datetimes are displayed in, from --output-timezone
+pub static OUTPUT_TIMEZONE: OnceLock<Tz> = OnceLock::new();
+
+/// Decimal places and rounding mode prices are displayed with, from --decimals and --rounding
+pub static PRICE_DISPLAY: OnceLock<(usize, report::Rounding)> = OnceLock::new();
+
+/// Print a progress or diagnostic line without polluting machine-readable stdout
+#[macro_export]
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::QUIET.load(std::sync::atomic::Ordering::Relaxed) {
+            // --quiet: results only
+        } else if $crate::STATUS_TO_STDERR.load(std::sync::atomic::Ordering::Relaxed) {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+pub mod analytics;
+pub mod cache;
+pub mod checkpoint;
+pub mod discovery;
+pub mod error;
+pub mod events;
+pub mod export;
+pub mod metadata;
+pub mod oracle;
+pub mod pricing;
+pub mod prompt;
+pub mod report;
+pub mod retry;
+pub mod sampler;
+pub mod serve;
+pub mod session;
+pub mod subgraph;
+
+// Aerodrome Pool ABI (simplified - includes the methods we need)
+abigen!(
+    AerodromePool,
+    r#"[
+        function getReserves() external view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast)
+        function token0() external view returns (address)
+        function token1() external view returns (address)
+        function decimals() external view returns (uint8)
+        function name() external view returns (string)
+        function slot0() external view returns (uint160 sqrtPriceX96, int24 tick)
+        function stable() external view returns (bool)
+        function observe(uint32[] secondsAgos) external view returns (int56[] tickCumulatives, uint160[] secondsPerLiquidityCumulativeX128s)
+        function currentCumulativePrices() external view returns (uint256 reserve0Cumulative, uint256 reserve1Cumulative, uint256 blockTimestamp)
+    ]"#,
+);
+
+// LP wrapper ABI (Gamma Hypervisors and ICHI vaults)
+abigen!(
+    LpWrapper,
+    r#"[
+        function getTotalAmounts() external view returns (uint256 total0, uint256 total1)
+    ]"#,
+);
+
+// Quoter ABI (Aerodrome pool-style getAmountOut)
+abigen!(
+    Quoter,
+    r#"[
+        function getAmountOut(uint256 amountIn, address tokenIn) external view returns (uint256)
+    ]"#,
+);
+
+// Aerodrome Voter ABI, for looking up a pool's gauge
+abigen!(
+    AerodromeVoter,
+    r#"[
+        function gauges(address pool) external view returns (address)
+        function isAlive(address gauge) external view returns (bool)
+    ]"#,
+);
+
+// Chainlink-style price feed ABI
+abigen!(
+    ChainlinkAggregator,
+    r#"[
+        function decimals() external view returns (uint8)
+        function latestRoundData() external view returns (uint80 roundId, int256 answer, uint256 startedAt, uint256 updatedAt, uint80 answeredInRound)
+    ]"#,
+);
+
+abigen!(
+    ERC20,
+    r#"[
+        function decimals() external view returns (uint8)
+        function symbol() external view returns (string)
+        function balanceOf(address account) external view returns (uint256)
+        function totalSupply() external view returns (uint256)
+    ]"#,
+);
+
+// Legacy ERC20s (MKR-style) that return symbol() as bytes32
+abigen!(
+    ERC20Bytes32,
+    r#"[
+        function symbol() external view returns (bytes32)
+    ]"#,
+);
+
+/// One sample of the pool's price, with the raw values it was derived from
+#[derive(Debug, Default, Serialize, Deserialize, JsonSchema)]
+pub struct PricePoint {
+    pub block: u64,
+    pub timestamp: u64,
+    pub price: f64,
+    /// Raw reserves, present when the price was derived from them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reserve0: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reserve1: Option<u128>,
+    /// Raw slot0 values, only present for concentrated-liquidity pools
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<String>")]
+    pub sqrt_price_x96: Option<U256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tick: Option<i32>,
+    /// RPC endpoint the sample was read from, under --record-endpoint
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+    /// Set when --sample-retry-different-block read this block in place of an unreadable one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub substitution: Option<report::Substitution>,
+}
+
+/// Find the latest block at or before a timestamp using binary search. With a nonzero
+/// `tolerance`, stop as soon as a block at most that many seconds before the target turns up.
+pub async fn find_block_at_timestamp(
+    provider: Arc<Provider<Http>>,
+    target_timestamp: u64,
+    tolerance: u64,
+) -> Result<U64> {
+    // Blocks near the tip can briefly come back empty during a reorg, so give them a few retries
+    let retry = retry::RetryPolicy::new(3, None);
+
+    let latest_block = provider.get_block_number().await?;
+
+    // Get latest block timestamp to check if target is in the future
+    let latest_timestamp = retry.run(|| read_block_timestamp(&provider, latest_block)).await?;
+
+    if target_timestamp > latest_timestamp {
+        return Err(TwapError::FutureTimestamp(target_timestamp).into());
+    }
+    if target_timestamp == latest_timestamp {
+        return Ok(latest_block);
+    }
+
+    // Binary search for the block
+    let mut low = 1u64;
+    let mut high = latest_block.as_u64();
+    let mut best_block = latest_block.as_u64();
+
+    status!("🔍 Finding block at timestamp {}...", target_timestamp);
+
+    while low <= high {
+        let mid = (low + high) / 2;
+
+        let block_timestamp = retry.run(|| read_block_timestamp(&provider, U64::from(mid))).await?;
+
+        if block_timestamp <= target_timestamp {
+            best_block = mid;
+            if tolerance > 0 && target_timestamp - block_timestamp <= tolerance {
+                status!("✓ Found block {} for timestamp {} ({}s before it, within --block-tolerance)",
+                    best_block, target_timestamp, target_timestamp - block_timestamp);
+                return Ok(U64::from(best_block));
+            }
+            low = mid + 1;
+        } else {
+            high = mid - 1;
+        }
+    }
+
+    status!("✓ Found block {} for timestamp {}", best_block, target_timestamp);
+    Ok(U64::from(best_block))
+}
+
+/// The latest block at or before each of `targets` (unix timestamps, oldest first, none after
+/// `end`'s), with its timestamp. Searches back from `end`, each found block bounding the search for
+/// the next older target, and guesses from the block time seen so far (`seconds_per_block` to
+/// start), so a sample usually costs a few reads rather than a full binary search. Targets before
+/// block 1 resolve to it.
+pub async fn blocks_at_timestamps(
+    provider: &Provider<Http>,
+    retry: &retry::RetryPolicy,
+    end: (U64, u64),
+    targets: &[u64],
+    seconds_per_block: f64,
+) -> Result<Vec<(U64, u64)>> {
+    let mut found = Vec::with_capacity(targets.len());
+    let (mut upper, mut seconds_per_block) = (end, seconds_per_block);
+    for &target in targets.iter().rev() {
+        let block = block_at_or_before(provider, retry, target, upper, seconds_per_block).await?;
+        if block.0 < upper.0 && block.1 < upper.1 {
+            seconds_per_block = (upper.1 - block.1) as f64 / (upper.0 - block.0).as_u64() as f64;
+        }
+        upper = block;
+        found.push(block);
+    }
+    found.reverse();
+    Ok(found)
+}
+
+async fn block_at_or_before(
+    provider: &Provider<Http>,
+    retry: &retry::RetryPolicy,
+    target: u64,
+    upper: (U64, u64),
+    seconds_per_block: f64,
+) -> Result<(U64, u64)> {
+    if upper.1 <= target {
+        return Ok(upper);
+    }
+    let read = |block: U64| async move { Ok::<_, anyhow::Error>((block, retry.run(|| read_block_timestamp(provider, block)).await?)) };
+
+    // Bracket the target from below, doubling the guessed distance back until a block at or before it turns up
+    let mut high = upper;
+    let mut distance = ((upper.1 - target) as f64 / seconds_per_block.max(f64::MIN_POSITIVE)).ceil().max(1.0) as u64;
+    let mut low = loop {
+        let (block, timestamp) = read(high.0.saturating_sub(U64::from(distance)).max(U64::one())).await?;
+        if timestamp <= target || block == U64::one() {
+            break (block, timestamp);
+        }
+        high = (block, timestamp);
+        distance = distance.saturating_mul(2);
+    };
+    if low.1 > target {
+        return Ok(low);
+    }
+
+    // Interpolate inside the bracket, bisecting every other step so uneven block times can't stall it
+    let mut bisect = false;
+    while (high.0 - low.0).as_u64() > 1 {
+        let span = (high.0 - low.0).as_u64();
+        let offset = if bisect {
+            span / 2
+        } else {
+            ((target - low.1) as f64 / (high.1 - low.1) as f64 * span as f64) as u64
+        };
+        let (block, timestamp) = read(low.0 + offset.clamp(1, span - 1)).await?;
+        if timestamp <= target {
+            low = (block, timestamp);
+        } else {
+            high = (block, timestamp);
+        }
+        bisect = !bisect;
+    }
+    Ok(low)
+}
+
+/// Blocks within this many of the tip may be missing mid-reorg rather than pruned
+pub const REORG_DEPTH: u64 = 64;
+
+/// Get the timestamp of a block. A block the node doesn't return is retryable if it's near the
+/// tip (likely a reorg in progress) and an archive error if it's older.
+pub async fn read_block_timestamp(provider: &Provider<Http>, block: U64) -> Result<u64> {
+    if let Some(found) = provider.get_block(block).await.context("Failed to get block")? {
+        return Ok(found.timestamp.as_u64());
+    }
+
+    let latest_block = provider.get_block_number().await.context("Failed to get current block")?;
+    if block.as_u64() + REORG_DEPTH >= latest_block.as_u64() {
+        Err(TwapError::BlockUnavailable(block.as_u64()).into())
+    } else {
+        Err(TwapError::ArchiveRequired(block.as_u64()).into())
+    }
+}
+
+/// Format a unix timestamp as a datetime in the --output-timezone for display
+pub fn format_timestamp(timestamp: u64) -> String {
+    let timezone = OUTPUT_TIMEZONE.get().copied().unwrap_or(Tz::UTC);
+    chrono::DateTime::from_timestamp(timestamp as i64, 0)
+        .map(|dt| dt.with_timezone(&timezone).format("%Y-%m-%d %H:%M:%S %Z").to_string())
+        .unwrap_or_else(|| timestamp.to_string())
+}
+
+/// A pool and the metadata of its two tokens
+#[derive(Clone, Debug)]
+pub struct PoolInfo {
+    pub address: Address,
+    /// The pool contract's name(), like "vAMM-WETH/USDC", when it has one
+    pub name: Option<String>,
+    pub token0: report::TokenInfo,
+    pub token1: report::TokenInfo,
+}
+
+/// Read a pool's tokens and their metadata. `tokens` stands in for the token0()/token1() getters.
+pub async fn load_pool(
+    provider: &Arc<Provider<Http>>,
+    token_cache: &mut metadata::TokenCache,
+    pool_address: Address,
+    tokens: Option<(Address, Address)>,
+    decimals_override: (Option<u8>, Option<u8>),
+) -> Result<PoolInfo> {
+    let code = provider
+        .get_code(pool_address, None)
+        .await
+        .map_err(|e| TwapError::RpcUnavailable(e.to_string()))?;
+    if code.is_empty() {
+        return Err(TwapError::NotAPool(pool_address).into());
+    }
+
+    // Get token information, from the getters unless the caller already knows the tokens
+    let (token0_addr, token1_addr) = match tokens {
+        Some(tokens) => {
+            status!("⚠️  Using --token0/--token1 instead of the pool's getters");
+            status!("   getReserves() is assumed to return reserves in that order; if they're swapped, the price is inverted");
+            status!();
+            tokens
+        }
+        None => {
+            let pool = AerodromePool::new(pool_address, provider.clone());
+            (
+                pool.token_0().call().await.map_err(|_| TwapError::NotAPool(pool_address))?,
+                pool.token_1().call().await.map_err(|_| TwapError::NotAPool(pool_address))?,
+            )
+        }
+    };
+
+    let mut token0 = token_cache.get(token0_addr).await?;
+    let mut token1 = token_cache.get(token1_addr).await?;
+    if let Some(decimals) = decimals_override.0 {
+        token0.decimals = decimals;
+    }
+    if let Some(decimals) = decimals_override.1 {
+        token1.decimals = decimals;
+    }
+
+    let name = pool_name(provider, pool_address).await;
+    if let Some(name) = &name {
+        status!("🏷️  Pool name: {}", name);
+    }
+    status!("📌 Token0: {} ({})", token0.symbol, report::checksum(&token0_addr));
+    status!("📌 Token1: {} ({})", token1.symbol, report::checksum(&token1_addr));
+    status!();
+
+    // Genuine 0-decimal tokens are rare; a 0 usually means the getter is missing or broken
+    for (name, token, flag) in [("token0", &token0, "--token0-decimals"), ("token1", &token1, "--token1-decimals")] {
+        if token.decimals == 0 {
+            status!("⚠️  WARNING: {} ({}) reports 0 decimals", name, token.symbol);
+            status!("   Prices will be off by orders of magnitude if that's wrong. Pass {} to override", flag);
+            status!();
+        }
+    }
+
+    for (name, token) in [("token0", &token0), ("token1", &token1)] {
+        if let Some(getter) = metadata::fee_on_transfer_getter(provider, token.address).await {
+            status!("⚠️  WARNING: {} ({}) looks like a fee-on-transfer token ({} is nonzero)", name, token.symbol, getter);
+            status!("   Reserves don't account for the transfer tax, so the reported price may not be executable");
+            status!();
+        }
+    }
+
+    for (name, token) in [("token0", &token0), ("token1", &token1)] {
+        if let Some(hint) = metadata::rebasing_hint(provider, token).await {
+            status!("⚠️  WARNING: {} ({}) looks like a rebasing token ({})", name, token.symbol, hint);
+            status!("   Its balances change without swaps, so the TWAP may reflect rebases rather than price moves");
+            status!("   Pass --detect-rebases to flag intervals where the price moved without a swap");
+            status!();
+        }
+    }
+
+    Ok(PoolInfo {
+        address: pool_address,
+        name,
+        token0,
+        token1,
+    })
+}
+
+/// The pool's name(), or None if it has no such getter or returns an empty one
+pub async fn pool_name(provider: &Arc<Provider<Http>>, pool_address: Address) -> Option<String> {
+    let name = AerodromePool::new(pool_address, provider.clone()).name().call().await.ok()?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+/// Whether the pool reports itself stable (x³y + xy³ = k). Pools without stable() count as
+/// volatile, since they're priced with the constant-product formula.
+pub async fn is_stable_pool(provider: &Arc<Provider<Http>>, pool_address: Address) -> bool {
+    AerodromePool::new(pool_address, provider.clone()).stable().call().await.unwrap_or(false)
+}
+
+/// A duration in its two largest units, like `4h 30m` or `5m`
+pub fn format_duration(seconds: u64) -> String {
+    let units = [("d", SECONDS_PER_DAY), ("h", 3600), ("m", 60), ("s", 1)];
+    let Some(first) = units.iter().position(|&(_, size)| seconds >= size) else {
+        return "0s".to_string();
+    };
+    let mut parts = Vec::new();
+    let mut left = seconds;
+    for &(unit, size) in &units[first..(first + 2).min(units.len())] {
+        if left / size > 0 {
+            parts.push(format!("{}{}", left / size, unit));
+        }
+        left %= size;
+    }
+    parts.join(" ")
+}
+
+/// Evenly spaced sample blocks from `samples` intervals before `end_block` up to `end_block` itself
+pub fn plan_sample_blocks(end_block: U64, samples: u64, blocks_per_interval: u64) -> Vec<U64> {
+    (0..=samples)
+        .map(|i| {
+            let blocks_back = (samples - i) * blocks_per_interval;
+            if blocks_back > end_block.as_u64() {
+                U64::from(1) // Genesis block if we go too far back
+            } else {
+                end_block - blocks_back
+            }
+        })
+        .collect()
+}
+
+/// The block at or before each of `samples` evenly spaced intervals' boundaries, ending at `end`
+/// itself, with their timestamps. Times that land in the same block are merged.
+pub async fn plan_timestamp_blocks(
+    provider: &Provider<Http>,
+    retry: &retry::RetryPolicy,
+    end: (U64, u64),
+    samples: u64,
+    interval_seconds: u64,
+    seconds_per_block: f64,
+) -> Result<Vec<(U64, u64)>> {
+    let targets: Vec<u64> = (0..=samples).map(|i| end.1.saturating_sub((samples - i) * interval_seconds)).collect();
+    let mut blocks = blocks_at_timestamps(provider, retry, end, &targets, seconds_per_block).await?;
+    blocks.dedup_by_key(|&mut (block, _)| block);
+    Ok(blocks)
+}
+
+/// Base's usual block time, which only seeds the search for each sample's block
+const BLOCK_TIME_SECONDS: f64 = 2.0;
+
+/// A step of [`compute_twap`], handed to [`TwapConfig::progress`] as it happens
+#[derive(Debug)]
+pub enum TwapProgress {
+    /// About to find the block at each of `samples` sample times, `interval_seconds` apart
+    Planning { samples: u64, interval_seconds: u64 },
+    /// Found `blocks` sample blocks; `merged` sample times shared a block with another
+    Planned { blocks: usize, merged: usize },
+    /// `done` of the `total` samples have been read
+    Sampled { done: usize, total: usize },
+    /// A sample failed after its retries and was skipped under `skip_failed`
+    Skipped { block: u64, error: String },
+}
+
+/// What [`compute_twap`] samples, with the command line's defaults from [`TwapConfig::new`]
+#[derive(Clone, Debug)]
+pub struct TwapConfig {
+    pub pool: Address,
+    pub days: u64,
+    pub samples: u64,
+    /// Unix timestamp the window ends at; the latest block when None
+    pub end_timestamp: Option<u64>,
+    /// Block the window ends at, taking precedence over `end_timestamp`
+    pub end_block: Option<U64>,
+    /// Block to spread the samples back to instead of `days` before the end, such as a pool's creation
+    pub start_block: Option<U64>,
+    /// Metadata already read for `pool`, so it isn't read again
+    pub pool_info: Option<PoolInfo>,
+    /// Whether to price from the stable curve; detected from the pool when None
+    pub stable: Option<bool>,
+    /// Times to retry a failed RPC read
+    pub retries: u32,
+    /// Seeds the retry backoff's jitter, for reproducible runs
+    pub seed: Option<u64>,
+    /// Samples fetched from the RPC at once
+    pub concurrency: usize,
+    /// Skip a sample that still fails after the retries instead of failing the whole computation
+    pub skip_failed: bool,
+    /// Seeds the search for each sample's block
+    pub seconds_per_block: f64,
+    /// Reserves already read, shared across computations
+    pub reserves_cache: Option<Arc<cache::ReservesCache>>,
+    /// Called at each step; nothing is reported when None
+    pub progress: Option<fn(&TwapProgress)>,
+}
+
+impl TwapConfig {
+    /// A week of hourly samples ending at the latest block
+    pub fn new(pool: Address) -> Self {
+        Self {
+            pool,
+            days: 7,
+            samples: 168,
+            end_timestamp: None,
+            end_block: None,
+            start_block: None,
+            pool_info: None,
+            stable: None,
+            retries: 3,
+            seed: None,
+            concurrency: 8,
+            skip_failed: false,
+            seconds_per_block: BLOCK_TIME_SECONDS,
+            reserves_cache: None,
+            progress: None,
+        }
+    }
+
+    fn report(&self, progress: TwapProgress) {
+        if let Some(callback) = self.progress {
+            callback(&progress);
+        }
+    }
+}
+
+/// A pool's TWAP over a window and the samples it came from
+#[derive(Debug, Serialize)]
+pub struct TwapResult {
+    pub pool: Address,
+    pub token0: report::TokenInfo,
+    pub token1: report::TokenInfo,
+    pub end_block: u64,
+    pub twap: f64,
+    /// Price at the last sample
+    pub current_price: f64,
+    pub min_price: f64,
+    pub max_price: f64,
+    pub price_points: Vec<PricePoint>,
+    /// Sampled blocks where the pool had no reserves
+    pub empty_blocks: Vec<u64>,
+    /// Sampled blocks skipped under `skip_failed`
+    pub failed_blocks: Vec<u64>,
+}
+
+/// Sample a pool's reserves at evenly spaced times over the configured window, each at the
+/// latest block at or before it, and compute its TWAP. Stable pools are priced from their curve.
+/// Blocks where the pool was empty are skipped; a read that still fails after the retries fails
+/// the whole computation unless `skip_failed` is set. This is the command line's default sampling.
+pub async fn compute_twap(provider: Arc<Provider<Http>>, config: &TwapConfig) -> Result<TwapResult> {
+    use futures::stream::{self, StreamExt};
+
+    if config.days == 0 || config.samples == 0 {
+        return Err(TwapError::InvalidConfig("days and samples must be positive".to_string()).into());
+    }
+    let pool = match &config.pool_info {
+        Some(pool) => pool.clone(),
+        None => load_pool(&provider, &mut metadata::TokenCache::new(provider.clone()), config.pool, None, (None, None)).await?,
+    };
+
+    let end_block = match (config.end_block, config.end_timestamp) {
+        (Some(block), _) => block,
+        (None, Some(timestamp)) => find_block_at_timestamp(provider.clone(), timestamp, 0).await?,
+        (None, None) => provider.get_block_number().await.context("Failed to get current block")?,
+    };
+    let retry = retry::RetryPolicy::new(config.retries, config.seed);
+    let end_timestamp = retry.run(|| read_block_timestamp(&provider, end_block)).await?;
+    let interval_seconds = match config.start_block {
+        Some(start_block) => {
+            let start_timestamp = retry.run(|| read_block_timestamp(&provider, start_block)).await?;
+            end_timestamp.saturating_sub(start_timestamp) / config.samples
+        }
+        None => config.days * SECONDS_PER_DAY / config.samples,
+    };
+    config.report(TwapProgress::Planning { samples: config.samples + 1, interval_seconds });
+    let end = (end_block, end_timestamp);
+    let blocks = plan_timestamp_blocks(&provider, &retry, end, config.samples, interval_seconds, config.seconds_per_block).await?;
+    config.report(TwapProgress::Planned { blocks: blocks.len(), merged: config.samples as usize + 1 - blocks.len() });
+
+    let mut sampler = sampler::Sampler::new(provider.clone(), pool.address, &pool.token0, &pool.token1, false, false);
+    if let Some(cache) = &config.reserves_cache {
+        sampler = sampler.with_reserves_cache(cache.clone());
+    }
+    let stable = match config.stable {
+        Some(stable) => stable,
+        None => is_stable_pool(&provider, pool.address).await,
+    };
+    if stable {
+        sampler = sampler.with_stable_curve();
+    }
+
+    let total = blocks.len();
+    let mut reads = stream::iter(blocks)
+        .map(|(block, timestamp)| {
+            let (sampler, retry) = (&sampler, &retry);
+            async move { (block, read_point(sampler, retry, block, timestamp).await) }
+        })
+        .buffered(config.concurrency.max(1))
+        .enumerate();
+    let (mut price_points, mut empty_blocks, mut failed_blocks) = (Vec::new(), Vec::new(), Vec::new());
+    while let Some((i, (block, read))) = reads.next().await {
+        match read {
+            Ok(Some(point)) => price_points.push(point),
+            Ok(None) => empty_blocks.push(block.as_u64()),
+            Err(e) if config.skip_failed => {
+                config.report(TwapProgress::Skipped { block: block.as_u64(), error: format!("{:#}", e) });
+                failed_blocks.push(block.as_u64());
+            }
+            Err(e) => return Err(e.context(format!("Sample at block {} failed", block))),
+        }
+        config.report(TwapProgress::Sampled { done: i + 1, total });
+    }
+
+    let Some(last) = price_points.last() else {
+        return Err(TwapError::NoData.into());
+    };
+    Ok(TwapResult {
+        pool: pool.address,
+        token0: pool.token0,
+        token1: pool.token1,
+        end_block: end_block.as_u64(),
+        twap: analytics::compute_twap(&price_points),
+        current_price: last.price,
+        min_price: price_points.iter().map(|p| p.price).fold(f64::INFINITY, f64::min),
+        max_price: price_points.iter().map(|p| p.price).fold(f64::NEG_INFINITY, f64::max),
+        price_points,
+        empty_blocks,
+        failed_blocks,
+    })
+}
+
+/// One sample for `compute_twap`, or None if the pool was empty at `block`
+async fn read_point(sampler: &sampler::Sampler, retry: &retry::RetryPolicy, block: U64, timestamp: u64) -> Result<Option<PricePoint>> {
+    let sample = retry.run(|| sampler.read_price(block)).await?;
+    Ok(sample.map(|sample| PricePoint {
+        block: block.as_u64(),
+        timestamp,
+        price: sample.price,
+        reserve0: sample.reserve0,
+        reserve1: sample.reserve1,
+        ..Default::default()
+    }))
+}
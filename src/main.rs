@@ -1,40 +1,90 @@
 use anyhow::{Context, Result};
 use chrono::{NaiveDate, TimeZone};
+use chrono_tz::Tz;
 use chrono_tz::US::Central;
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+use futures::stream::{self, StreamExt};
 use ethers::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use serde_json::ser::{CompactFormatter, Formatter, PrettyFormatter};
+use std::sync::atomic::Ordering;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io::IsTerminal;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
 use std::sync::Arc;
 
-// Aerodrome Pool ABI (simplified - includes the methods we need)
-abigen!(
-    AerodromePool,
-    r#"[
-        function getReserves() external view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast)
-        function token0() external view returns (address)
-        function token1() external view returns (address)
-        function decimals() external view returns (uint8)
-    ]"#,
-);
-
-abigen!(
-    ERC20,
-    r#"[
-        function decimals() external view returns (uint8)
-        function symbol() external view returns (string)
-    ]"#,
-);
-
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
+use aerodrome_twap::error::TwapError;
+use aerodrome_twap::{
+    analytics, cache, checkpoint, discovery, error, events, export, metadata, oracle, pricing, prompt, report, retry, sampler, serve,
+    session, status, subgraph,
+};
+use aerodrome_twap::{
+    find_block_at_timestamp, format_duration, format_timestamp, is_stable_pool, load_pool, plan_sample_blocks, plan_timestamp_blocks,
+    read_block_timestamp, PoolInfo, PricePoint, OUTPUT_TIMEZONE, PRICE_DISPLAY, QUIET, REORG_DEPTH, SECONDS_PER_DAY, STATUS_TO_STDERR,
+    TwapConfig, TwapProgress,
+};
+use aerodrome_twap::{AerodromePool, AerodromeVoter, ERC20};
+
+/// Exit code when `--fail-if-deviation-exceeds` is breached
+const EXIT_DEVIATION_EXCEEDED: u8 = 3;
+
+/// Exit code when the TWAP misses `--assert-twap`
+const EXIT_ASSERTION_FAILED: u8 = 10;
+
+/// Exit code when two consecutive samples are further apart than `--fail-on-gap`
+const EXIT_GAP_EXCEEDED: u8 = 11;
+
+#[derive(Parser, Debug, Clone)]
+#[command(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Aerodrome pool address
-    #[arg(short, long)]
-    pool: String,
+    #[arg(short, long, required_unless_present_any = ["json_schema", "from_csv", "cross", "list_pools", "interactive", "wrapper", "compare_pools", "index", "pool_sequence"])]
+    pool: Option<String>,
 
     /// Base RPC URL (defaults to public Base RPC)
     #[arg(short, long, default_value = "https://mainnet.base.org")]
     rpc: String,
 
+    /// Write every setting's effective value, including defaults, to this TOML file, keyed by flag name
+    #[arg(long, value_name = "PATH", conflicts_with = "interactive")]
+    export_config: Option<String>,
+
+    /// Recompute the report every this many seconds until Ctrl-C, reusing the connection and caches
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..), conflicts_with_all = ["from_csv",
+        "cross", "compare_pools", "index", "pool_sequence", "wrapper", "probe", "streaming_aggregate", "end_date", "list_pools"])]
+    repeat: Option<u64>,
+
+    /// Check the arguments, addresses and dates and print the resolved configuration, then exit without any RPC calls
+    #[arg(long, conflicts_with_all = ["from_csv", "json_schema", "interactive"])]
+    validate: bool,
+
+    /// Tag every sample with the RPC endpoint (scheme and host only) that served it, in the CSV and JSON samples
+    #[arg(long)]
+    record_endpoint: bool,
+
+    /// HTTP(S) proxy for RPC requests, overriding HTTPS_PROXY/HTTP_PROXY
+    #[arg(long)]
+    proxy: Option<String>,
+
+    /// Talk to the RPC over HTTP/1.1 only, for gateways that stall or reset multiplexed HTTP/2 connections
+    #[arg(long, conflicts_with = "force_http2")]
+    force_http1: bool,
+
+    /// Talk to the RPC over HTTP/2 without negotiating it first, for gateways that mishandle the upgrade
+    #[arg(long)]
+    force_http2: bool,
+
+    /// Extra HTTP header for every RPC request, as "Name: Value" (repeatable), e.g. for API keys
+    #[arg(long = "header", value_name = "HEADER")]
+    headers: Vec<String>,
+
     /// Number of days to calculate TWAP (defaults to 7)
     #[arg(short, long, default_value = "7")]
     days: u64,
@@ -43,216 +93,4277 @@ struct Args {
     #[arg(short, long, default_value = "168")]
     samples: u64,
 
+    /// Refuse to run with more samples than this, to catch typos that would flood the RPC
+    #[arg(long, default_value = "100000")]
+    max_samples: u64,
+
+    /// Subgraph to read hourly prices (poolHourDatas) from when the RPC lacks the window's history
+    #[arg(long, value_name = "URL", conflicts_with_all = ["block_hashes", "concentrated", "wrapper", "quoter", "probe"])]
+    subgraph: Option<String>,
+
+    /// Refuse windows longer than this many days, which most non-archive RPCs can't serve
+    #[arg(long, default_value = "365")]
+    max_window_days: u64,
+
     /// End date for TWAP range in YYYY-MM-DD format (midnight US Central Time). If not specified, uses current time.
+    /// Pass - to read it from stdin
     #[arg(short, long)]
     end_date: Option<String>,
+
+    /// End the window at this block number instead of a date or tag. Pass - to read it from stdin
+    #[arg(long, value_name = "N", conflicts_with = "end_date")]
+    end_block: Option<String>,
+
+    /// IANA timezone for displayed datetimes, e.g. Europe/Berlin. Doesn't affect how --end-date is read
+    #[arg(long, default_value = "UTC")]
+    output_timezone: Tz,
+
+    /// Decimal places prices are shown with in text and Markdown output
+    #[arg(long, default_value = "8")]
+    decimals: usize,
+
+    /// Show prices down to this many digits below the quote token's smallest unit instead of at --decimals places,
+    /// so a 6-decimal quote like USDC still shows small moves
+    #[arg(long, value_name = "N", conflicts_with_all = ["decimals", "compare_pools", "index"])]
+    quote_decimals: Option<usize>,
+
+    /// Pick --decimals from the TWAP's magnitude so prices show this many significant digits (default 8),
+    /// readable for both a 40000 and a 0.000000001 price
+    #[arg(long, value_name = "DIGITS", num_args = 0..=1, default_missing_value = "8",
+        conflicts_with_all = ["decimals", "quote_decimals", "compare_pools", "index"])]
+    price_precision_auto: Option<usize>,
+
+    /// Quote the pair the other way round (token0 per token1) when the pool's spot price is below this,
+    /// so a tiny price like 0.0000003 reads as 3333333.33
+    #[arg(long, value_name = "PRICE", conflicts_with_all = ["concentrated", "wrapper", "streaming_aggregate", "cross",
+        "compare_pools", "pool_sequence", "index"])]
+    pair_inverse_threshold: Option<f64>,
+
+    /// How displayed prices are cut to --decimals places; calculations keep full precision
+    #[arg(long, value_enum, default_value_t = report::Rounding::Nearest)]
+    rounding: report::Rounding,
+
+    /// Derive reserves from the pool's token balances when getReserves reverts
+    #[arg(long)]
+    balances_fallback: bool,
+
+    /// Address to send the sampled price eth_calls (getReserves/slot0, or their quoter, wrapper and balance stand-ins)
+    /// from, for pool forks that gate their views by caller
+    #[arg(long, value_name = "ADDRESS")]
+    from_address: Option<String>,
+
+    /// Write each sample's deviation from the TWAP-so-far to a CSV file and summarize the extremes
+    #[arg(long)]
+    deviation_series: Option<String>,
+
+    /// Report the realized variance (sum of squared log returns) and realized volatility of the window
+    #[arg(long)]
+    realized_variance: bool,
+
+    /// Report the window's largest peak-to-trough price decline, when it happened and whether it recovered
+    #[arg(long, conflicts_with = "streaming_aggregate")]
+    max_drawdown: bool,
+
+    /// Days in the year the realized variance is annualized to (e.g. 252 for trading days)
+    #[arg(long, value_name = "DAYS", default_value = "365", requires = "realized_variance")]
+    annualization_days: f64,
+
+    /// Estimate the capital needed to skew the TWAP up by this percentage with a single-block push
+    #[arg(long, value_name = "TARGET_PCT", conflicts_with_all = ["concentrated", "wrapper", "streaming_aggregate"])]
+    manipulation_cost: Option<f64>,
+
+    /// Error if the pool's last trade before the end block (getReserves' blockTimestampLast) is older than this
+    #[arg(long, value_name = "SECONDS", conflicts_with_all = ["concentrated", "wrapper", "cross", "compare_pools", "index",
+        "pool_sequence", "from_csv"])]
+    require_fresh_end: Option<u64>,
+
+    /// Only warn, instead of erroring, when --require-fresh-end finds a stale end
+    #[arg(long, requires = "require_fresh_end")]
+    stale_end_warn: bool,
+
+    /// Also report time-weighted TWAPs over consecutive sub-periods of this length (e.g. 1d) and their aggregate
+    #[arg(long, value_name = "INTERVAL", value_parser = parse_interval,
+        conflicts_with_all = ["streaming_aggregate", "compare_pools", "index", "weekday_filter", "time_of_day_filter"])]
+    twap_of_twaps: Option<u64>,
+
+    /// Write each sample's price paired with the TWAP of the --spot-series-window before it, for
+    /// backtesting spot-vs-TWAP crossovers: JSON if the path ends in .json, otherwise CSV
+    #[arg(long, value_name = "PATH", requires = "spot_series_window", conflicts_with = "streaming_aggregate")]
+    compare_to_spot_series: Option<String>,
+
+    /// Length of the trailing TWAP window in --compare-to-spot-series (e.g. 30m, 1h)
+    #[arg(long, value_name = "INTERVAL", value_parser = parse_interval, requires = "compare_to_spot_series")]
+    spot_series_window: Option<u64>,
+
+    /// Bucket the samples into open/high/low/close candles of this length (e.g. 15m, 1h, 1d)
+    #[arg(long, value_name = "INTERVAL", value_parser = parse_interval, requires = "ohlc_out",
+        conflicts_with = "streaming_aggregate")]
+    ohlc: Option<u64>,
+
+    /// Where to write the --ohlc candles: JSON if the path ends in .json, otherwise CSV
+    #[arg(long, value_name = "PATH", requires = "ohlc")]
+    ohlc_out: Option<String>,
+
+    /// Average block time in seconds, overriding the built-in Base estimate of 2 seconds
+    #[arg(long)]
+    block_time: Option<f64>,
+
+    /// Report the first and last sampled blocks against the block range the requested days should span
+    #[arg(long, conflicts_with = "streaming_aggregate")]
+    block_span: bool,
+
+    /// Treat the pool as concentrated-liquidity and price it from slot0() instead of getReserves()
+    #[arg(long)]
+    concentrated: bool,
+
+    /// Which curve reserve prices come from: read the pool's stable() flag (auto), constant-product x*y=k
+    /// (volatile) or Aerodrome's stable x³y + xy³ = k (stable)
+    #[arg(long, value_enum, default_value_t = PoolType::Auto, conflicts_with_all = ["concentrated", "wrapper"])]
+    pool_type: PoolType,
+
+    /// Output format for the results
+    #[arg(short = 'o', long, visible_alias = "output", value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+
+    /// Output only these metrics, in this order (text and JSON): twap, spot, min, max, median, volatility, deviation, range
+    #[arg(long, value_enum, value_delimiter = ',', conflicts_with_all = ["bps_only", "compare_pools", "index"])]
+    metrics: Vec<report::Metric>,
+
+    /// Print the JSON Schema of the JSON output and exit
+    #[arg(long)]
+    json_schema: bool,
+
+    /// Indent JSON output for reading by hand
+    #[arg(long)]
+    pretty: bool,
+
+    /// Round JSON floats to --json-significant-digits, as numbers or as decimal strings
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "number")]
+    json_compact_numbers: Option<JsonNumbers>,
+
+    /// Significant digits kept by --json-compact-numbers
+    #[arg(long, default_value = "12", value_parser = clap::value_parser!(u8).range(1..=17))]
+    json_significant_digits: u8,
+
+    /// Also write the JSON report to a file, whatever --format prints to the console
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["index", "compare_pools", "reserves_at_end_only"])]
+    json_out: Option<String>,
+
+    /// Also write the Markdown report to a file, whatever --format prints to the console
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["index", "compare_pools", "reserves_at_end_only"])]
+    markdown_out: Option<String>,
+
+    /// Write the collected samples to a CSV file
+    #[arg(long)]
+    csv: Option<String>,
+
+    /// Write the sampled prices to a Parquet file (needs the `parquet` build feature)
+    #[arg(long)]
+    parquet: Option<String>,
+
+    /// Recompute the TWAP from a CSV written by --csv instead of querying the chain
+    #[arg(long, conflicts_with = "csv")]
+    from_csv: Option<String>,
+
+    /// When a block's state still can't be read after retries, sample the nearest readable block within this many
+    /// blocks of it (default 5) instead, recording the substitution
+    #[arg(long, value_name = "BLOCKS", num_args = 0..=1, default_missing_value = "5", conflicts_with = "streaming_aggregate",
+        value_parser = clap::value_parser!(u64).range(1..))]
+    sample_retry_different_block: Option<u64>,
+
+    /// Skip samples that land less than this many seconds after the previous one
+    #[arg(long)]
+    min_gap_seconds: Option<u64>,
+
+    /// Flag samples whose price jumps more than this percent from both neighbors and snaps back
+    #[arg(long)]
+    jump_threshold: Option<f64>,
+
+    /// Flag samples with a reserve within 2^BITS of the uint112 maximum, a sign of a misread or exotic token
+    #[arg(long, value_name = "BITS", num_args = 0..=1, default_missing_value = "16",
+        value_parser = clap::value_parser!(u32).range(1..112))]
+    head_room: Option<u32>,
+
+    /// Scan Swap events and warn about sampled intervals where the price moved without a swap, as rebases cause
+    #[arg(long, conflicts_with_all = ["streaming_aggregate", "concentrated", "wrapper", "quoter", "from_csv", "cross",
+        "compare_pools", "index", "pool_sequence"])]
+    detect_rebases: bool,
+
+    /// Check every sampled reserve against its token's totalSupply() at that block (two extra calls per sample)
+    #[arg(long, visible_alias = "verify", conflicts_with_all = ["streaming_aggregate", "concentrated", "quoter", "from_csv",
+        "cross", "compare_pools", "index", "pool_sequence"])]
+    verify_reserves_sum: bool,
+
+    /// While collecting, flag a sample whose price moved more than this percent within its own block
+    #[arg(long, value_name = "PCT")]
+    max_price_change_per_block: Option<f64>,
+
+    /// What tripping --max-price-change-per-block does beyond flagging the block: pause to ask whether to go on,
+    /// or abort the run
+    #[arg(long, value_enum, requires = "max_price_change_per_block")]
+    circuit_breaker: Option<CircuitBreaker>,
+
+    /// Drop samples flagged by --jump-threshold or --head-room before computing the TWAP
+    #[arg(long)]
+    exclude_suspected: bool,
+
+    /// Report prices relative to the price at this block
+    #[arg(long, conflicts_with_all = ["baseline_date", "from_csv"])]
+    baseline_block: Option<u64>,
+
+    /// Report prices relative to the price at midnight US Central on this YYYY-MM-DD date
+    #[arg(long, conflicts_with = "from_csv")]
+    baseline_date: Option<String>,
+
+    /// Price each sample by quoting --quote-amount of token0 on this quoter contract instead of reading reserves
+    #[arg(long)]
+    quoter: Option<String>,
+
+    /// Amount of token0 to quote when --quoter is set
+    #[arg(long, default_value = "1", requires = "quoter")]
+    quote_amount: f64,
+
+    /// Whether a failed sample aborts the run or is logged and skipped
+    #[arg(long, value_enum, default_value_t = OnError::Abort)]
+    on_error: OnError,
+
+    /// Error instead of sampling if the requested window starts before the pool was created
+    #[arg(long)]
+    strict_window: bool,
+
+    /// Fail if the pool had zero reserves at any sampled block, instead of skipping those samples
+    #[arg(long)]
+    strict_reserves: bool,
+
+    /// Block the pool was created at, e.g. from a block explorer, instead of searching for it
+    #[arg(long, value_name = "N", conflicts_with_all = ["concentrated", "wrapper", "cross", "compare_pools", "index",
+        "pool_sequence", "from_csv"])]
+    pool_created_block: Option<u64>,
+
+    /// Triangulate the price of the non-shared tokens of two pools that share a token (POOL_A,POOL_B)
+    #[arg(long, conflicts_with_all = ["pool", "from_csv", "baseline_block", "baseline_date", "strict_window"])]
+    cross: Option<String>,
+
+    /// Compute the TWAP of the same pair on several pools (e.g. across DEX forks) and compare them
+    #[arg(long, value_name = "POOLS", conflicts_with_all = ["pool", "cross", "wrapper", "from_csv", "streaming_aggregate",
+        "baseline_block", "baseline_date", "method", "bps_only", "oracle_feed", "benchmark", "ref_amount"])]
+    compare_pools: Option<String>,
+
+    /// Track a weighted basket of pools (POOL[:WEIGHT],...), each normalized to 1.0 at the window start
+    #[arg(long, value_name = "POOLS", visible_alias = "normalize-to-one-token", conflicts_with_all = ["pool", "cross",
+        "compare_pools", "wrapper", "from_csv", "streaming_aggregate", "baseline_block", "baseline_date", "method",
+        "bps_only", "oracle_feed", "benchmark", "ref_amount", "parquet", "deviation_series"])]
+    index: Option<String>,
+
+    /// Stitch one series across a pool migration: which pool is authoritative from which block (ADDR@BLOCK,...)
+    #[arg(long, value_name = "ADDR@BLOCK,...", conflicts_with_all = ["pool", "cross", "compare_pools", "index", "wrapper",
+        "from_csv", "streaming_aggregate", "method", "block_hashes", "reserves_at_end_only", "probe"])]
+    pool_sequence: Option<String>,
+
+    /// token0's address, for pools without a token0() getter (requires --token1)
+    #[arg(long, requires = "token1", conflicts_with_all = ["cross", "compare_pools", "index"])]
+    token0: Option<String>,
+
+    /// token1's address, for pools without a token1() getter (requires --token0)
+    #[arg(long, requires = "token0", conflicts_with_all = ["cross", "compare_pools", "index"])]
+    token1: Option<String>,
+
+    /// Check that getReserves() is ordered like token0/token1 (always done with --token0/--token1)
+    #[arg(long, conflicts_with_all = ["cross", "compare_pools", "index", "concentrated", "wrapper"])]
+    check_reserve_order: bool,
+
+    /// Read the pool's own decimals() and warn if it looks inconsistent with its tokens
+    #[arg(long, conflicts_with_all = ["cross", "compare_pools"])]
+    strict_decimals: bool,
+
+    /// Override token0's decimals instead of reading them from the token
+    #[arg(long, conflicts_with = "cross")]
+    token0_decimals: Option<u8>,
+
+    /// Override token1's decimals instead of reading them from the token
+    #[arg(long, conflicts_with = "cross")]
+    token1_decimals: Option<u8>,
+
+    /// Times to retry a failed RPC read, with exponential backoff and jitter
+    #[arg(long, default_value = "3")]
+    retries: u32,
+
+    /// Samples fetched from the RPC at once; lower it if the endpoint rate-limits
+    #[arg(long, default_value = "8", value_parser = clap::value_parser!(u64).range(1..))]
+    concurrency: u64,
+
+    /// Seed for the retry and --sample-jitter randomness, for reproducible runs
+    #[arg(long, hide = true)]
+    seed: Option<u64>,
+
+    /// Move each sample up to this many blocks either way at random, to avoid lining up with periodic trades
+    #[arg(long, conflicts_with_all = ["daily_boundaries", "samples_from_events", "reserves_at_end_only"])]
+    sample_jitter: Option<u64>,
+
+    /// Exit with code 3 if the current price deviates from the TWAP by more than this percent
+    #[arg(long)]
+    fail_if_deviation_exceeds: Option<f64>,
+
+    /// Exit with code 11 if any two consecutive collected samples are more than this many seconds apart
+    #[arg(long, value_name = "SECONDS", conflicts_with_all = ["streaming_aggregate", "weekday_filter", "time_of_day_filter",
+        "compare_pools", "index"])]
+    fail_on_gap: Option<u64>,
+
+    /// Exit with code 10 if the TWAP differs from this expected value by more than --assert-tolerance
+    #[arg(long, value_name = "VALUE")]
+    assert_twap: Option<f64>,
+
+    /// External reference price (e.g. a peg or off-chain fair value) to report the spot's and TWAP's deviation from
+    #[arg(long, value_name = "PRICE", visible_alias = "twap-reference")]
+    reference_price: Option<f64>,
+
+    /// Percent the TWAP may differ from --assert-twap
+    #[arg(long, value_name = "PCT", default_value = "0.01", requires = "assert_twap")]
+    assert_tolerance: f64,
+
+    /// Sample at every block with a Swap event in the window instead of at evenly spaced blocks
+    #[arg(long, conflicts_with = "cross")]
+    samples_from_events: bool,
+
+    /// Sample where every N-th Swap event in the window lands, so busy periods are sampled more densely
+    #[arg(long, value_name = "N", conflicts_with_all = ["samples_from_events", "daily_boundaries", "sample_jitter",
+        "block_hashes", "reserves_at_end_only", "cross", "compare_pools", "index", "pool_sequence", "from_csv"])]
+    swaps_per_sample: Option<u64>,
+
+    /// Price each --samples-from-events block from its post-block reserves, or its swaps' mean or median price
+    #[arg(long, value_enum, default_value_t = PriceSource::Last, requires = "samples_from_events")]
+    price_source: PriceSource,
+
+    /// Block range per eth_getLogs request when scanning events
+    #[arg(long, default_value = "10000")]
+    log_chunk_blocks: u64,
+
+    /// List the Aerodrome pools containing this token and exit
+    #[arg(long, conflicts_with_all = ["pool", "cross", "from_csv"])]
+    list_pools: Option<String>,
+
+    /// Look up the pool's gauge on the Aerodrome Voter and show it in the header
+    #[arg(long, conflicts_with_all = ["cross", "compare_pools"])]
+    show_gauge: bool,
+
+    /// Voter contract to look gauges up on with --show-gauge
+    #[arg(long, default_value = discovery::AERODROME_VOTER, requires = "show_gauge")]
+    voter: String,
+
+    /// Pool factory to search with --list-pools
+    #[arg(long, default_value = discovery::AERODROME_FACTORY)]
+    factory: String,
+
+    /// Block to start the --list-pools PoolCreated scan from
+    #[arg(long, default_value_t = discovery::AERODROME_FACTORY_START_BLOCK)]
+    factory_start_block: u64,
+
+    /// Compare the pool TWAP against a Chainlink-style price feed read over the same window
+    #[arg(long, conflicts_with_all = ["from_csv", "cross"])]
+    oracle_feed: Option<String>,
+
+    /// Use the reciprocal of the --oracle-feed answer, for feeds quoted the other way round
+    #[arg(long, requires = "oracle_feed")]
+    oracle_invert: bool,
+
+    /// Fold samples into the TWAP as they arrive instead of keeping them all in memory
+    #[arg(long, conflicts_with_all = ["cross", "from_csv", "csv", "parquet", "deviation_series", "jump_threshold", "oracle_feed"])]
+    streaming_aggregate: bool,
+
+    /// Sample once per day at 00:00 UTC instead of at --samples evenly spaced points
+    #[arg(long, conflicts_with = "samples_from_events")]
+    daily_boundaries: bool,
+
+    /// Also read the pool's reserve accumulators at the first and last sample and report the TWAP they imply
+    #[arg(long, conflicts_with_all = ["concentrated", "wrapper", "quoter", "from_csv", "cross", "compare_pools", "index",
+        "pool_sequence", "pair_inverse_threshold"])]
+    accumulator_check: bool,
+
+    /// Compare the last sample's reserve price with the price the accumulators imply over its block, flagging a
+    /// divergence above this percent (default 1) as a likely just-traded or manipulated block
+    #[arg(long, value_name = "PCT", num_args = 0..=1, default_missing_value = "1", conflicts_with_all = ["concentrated",
+        "wrapper", "quoter", "from_csv", "cross", "compare_pools", "index", "pool_sequence", "pair_inverse_threshold"])]
+    spot_divergence_check: Option<f64>,
+
+    /// Average only over these days of the week in --output-timezone: weekdays, weekends or a list like mon,wed,fri
+    #[arg(long, value_name = "DAYS", value_parser = session::parse_weekdays, conflicts_with_all = ["streaming_aggregate",
+        "warmup_samples", "cross", "compare_pools", "index", "pool_sequence"])]
+    weekday_filter: Option<session::Weekdays>,
+
+    /// Average only over this daily wall-clock window in --output-timezone, like 13:00-21:00
+    #[arg(long, value_name = "HH:MM-HH:MM", value_parser = session::parse_time_of_day, conflicts_with_all = ["streaming_aggregate",
+        "warmup_samples", "cross", "compare_pools", "index", "pool_sequence"])]
+    time_of_day_filter: Option<session::TimeOfDay>,
+
+    /// Take the TWAP of a centered moving average over this many samples (odd), reporting the unsmoothed TWAP too
+    #[arg(long, value_name = "N", conflicts_with_all = ["streaming_aggregate", "weekday_filter", "time_of_day_filter",
+        "min_reserve_treat_as_gap", "warmup_samples", "compare_pools", "index"])]
+    smooth: Option<usize>,
+
+    /// Treat samples where either reserve is below this many whole tokens as gaps, left out of the TWAP's weighting
+    #[arg(long, value_name = "F", conflicts_with_all = ["streaming_aggregate", "concentrated", "warmup_samples",
+        "weekday_filter", "time_of_day_filter", "twap_of_twaps", "cross", "compare_pools", "index", "pool_sequence"])]
+    min_reserve_treat_as_gap: Option<f64>,
+
+    /// Bisect sampled intervals whose price moved more than this percent, to sample densely only where the price moves
+    #[arg(long, value_name = "PERCENT", conflicts_with_all = ["streaming_aggregate", "resume", "samples_from_events",
+        "swaps_per_sample", "daily_boundaries", "block_hashes", "reserves_at_end_only", "cross", "compare_pools", "index",
+        "pool_sequence", "from_csv"])]
+    adaptive: Option<f64>,
+
+    /// Most samples --adaptive may reach, including the initial --samples (default: 4 × --samples)
+    #[arg(long, value_name = "N", requires = "adaptive")]
+    adaptive_max_samples: Option<u64>,
+
+    /// Sample at the first block of each period of this length (e.g. 1h, 1d), with periods aligned to
+    /// midnight in --output-timezone
+    #[arg(long, value_name = "INTERVAL", value_parser = parse_interval, conflicts_with_all = ["daily_boundaries",
+        "samples_from_events", "swaps_per_sample", "sample_jitter", "warmup_samples", "spacing", "block_hashes",
+        "reserves_at_end_only", "cross", "compare_pools", "index", "pool_sequence", "from_csv"])]
+    sample_at_open: Option<u64>,
+
+    /// Skip sampling and average the prices at the window's start and end blocks, as a rough estimate
+    #[arg(long, conflicts_with_all = ["cross", "from_csv", "streaming_aggregate", "samples_from_events", "daily_boundaries", "oracle_feed"])]
+    reserves_at_end_only: bool,
+
+    /// Read this many extra samples before the window to weight the interval leading into its first sample
+    #[arg(long, default_value = "0", conflicts_with_all = ["cross", "from_csv", "streaming_aggregate", "daily_boundaries", "reserves_at_end_only"])]
+    warmup_samples: u64,
+
+    /// How to compute the TWAP: sample spot prices, or read the pool's observe() oracle
+    #[arg(long, value_enum, default_value_t = Method::Sample, conflicts_with_all = ["cross", "from_csv"])]
+    method: Method,
+
+    /// Snap printed and exported prices to a multiple of this tick size (e.g. 0.0001)
+    #[arg(long)]
+    round_to: Option<f64>,
+
+    /// Cap printed and exported prices into MIN,MAX so a corrupt sample can't blow out a chart; the TWAP uses the raw prices
+    #[arg(long, value_name = "MIN,MAX", value_parser = parse_price_range, conflicts_with_all = ["compare_pools", "index"])]
+    clamp_range: Option<(f64, f64)>,
+
+    /// Don't print progress or diagnostic lines, only the results
+    #[arg(short = 'q', long)]
+    quiet: bool,
+
+    /// Print only the spot price's deviation from the TWAP, in basis points
+    #[arg(long, conflicts_with_all = ["format", "reserves_at_end_only", "method"])]
+    bps_only: bool,
+
+    /// Also compute the TWAP from observe() and compare it with the sampled one, with timings
+    #[arg(long, conflicts_with_all = ["method", "cross", "from_csv", "reserves_at_end_only"])]
+    benchmark: bool,
+
+    /// Prompt for the pool address, RPC and days when --pool is missing and stdin is a terminal
+    #[arg(long)]
+    interactive: bool,
+
+    /// If the chain reorgs during collection, re-read the affected tail of samples instead of just warning
+    #[arg(long, conflicts_with = "streaming_aggregate")]
+    continue_on_reorg: bool,
+
+    /// Also report prices as integers scaled by this factor (default 1e18), computed exactly from reserves
+    #[arg(long, value_name = "SCALE", num_args = 0..=1, default_missing_value = "1000000000000000000",
+        value_parser = parse_u256, conflicts_with_all = ["quoter", "cross", "from_csv", "streaming_aggregate"])]
+    price_in_wei: Option<U256>,
+
+    /// Weight each interval by elapsed seconds (time) or by the number of blocks (blocks)
+    #[arg(long, value_enum, default_value_t = analytics::Weighting::Time,
+        conflicts_with_all = ["streaming_aggregate", "warmup_samples"])]
+    weight_by: analytics::Weighting,
+
+    /// Print only the summary statistics, leaving the per-sample series out of the output
+    #[arg(long)]
+    summary_only: bool,
+
+    /// Price an LP wrapper (Gamma Hypervisor, ICHI vault) from its getTotalAmounts() instead of a pool
+    #[arg(long, conflicts_with_all = ["pool", "cross", "concentrated", "quoter", "balances_fallback", "method", "benchmark"])]
+    wrapper: Option<String>,
+
+    /// For stable pools, also report the time-weighted execution price of swapping this much token0
+    #[arg(long, conflicts_with_all = ["cross", "from_csv", "streaming_aggregate", "concentrated", "quoter", "wrapper", "reserves_at_end_only"])]
+    ref_amount: Option<f64>,
+
+    /// Effective execution price at the end block for each of these token0 trade sizes (comma-separated)
+    #[arg(long, value_name = "SIZES", value_delimiter = ',',
+        conflicts_with_all = ["cross", "compare_pools", "index", "from_csv", "concentrated", "quoter", "wrapper"])]
+    impact_curve: Vec<f64>,
+
+    /// Read a handful of the planned sample blocks and report how many are readable, then exit
+    #[arg(long, conflicts_with_all = ["cross", "compare_pools", "from_csv", "reserves_at_end_only", "method", "bps_only", "benchmark"])]
+    probe: bool,
+
+    /// Stop collecting after this many seconds from startup and report a partial TWAP from the samples gathered
+    #[arg(long, value_name = "SECS")]
+    max_duration: Option<u64>,
+
+    /// Compare against a report saved from an earlier --format json run and print the changes
+    #[arg(long, value_name = "PREVIOUS.json")]
+    diff: Option<String>,
+
+    /// How sample blocks are spread across the window: evenly (linear) or denser toward the end (log)
+    #[arg(long, value_enum, default_value_t = Spacing::Linear, conflicts_with_all = ["daily_boundaries", "warmup_samples"])]
+    spacing: Spacing,
+
+    /// Sample exactly the blocks whose hashes are listed in this file (one per line), reading state by hash
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["cross", "compare_pools", "from_csv", "end_date", "end_block", "daily_boundaries",
+        "samples_from_events", "sample_jitter", "warmup_samples", "spacing", "reserves_at_end_only", "method"])]
+    block_hashes: Option<String>,
+
+    /// Keep final (non-tip) reserve reads in this JSON file and reuse them on later runs
+    #[arg(long, value_name = "FILE")]
+    reserves_cache: Option<String>,
+
+    /// Write every sample and interval of the TWAP computation to this JSON file
+    #[arg(long, value_name = "PATH", conflicts_with = "streaming_aggregate")]
+    audit_log: Option<String>,
+
+    /// Add a SHA-256 digest of the canonicalized JSON report, so downstream systems can check it wasn't altered
+    #[arg(long)]
+    hash: bool,
+
+    /// Under --hash, also digest the full per-sample series, even when --summary-only leaves it out
+    #[arg(long, requires = "hash")]
+    hash_samples: bool,
+
+    /// Print a hint about the price direction when a stable/stable or stable/ETH price looks inverted
+    #[arg(long)]
+    warn_on_symbol_mismatch: bool,
+
+    /// Let date-to-block searches stop at any block up to this many seconds before the target (0 = exact)
+    #[arg(long, value_name = "SECONDS", default_value = "0")]
+    block_tolerance: u64,
+
+    /// Check each estimated sample block's timestamp against the time it was meant to land on, and search for a
+    /// closer block only for samples more than this many seconds off
+    #[arg(long, value_name = "SECONDS", conflicts_with_all = ["block_hashes", "daily_boundaries", "sample_at_open",
+        "samples_from_events", "swaps_per_sample", "adaptive", "sample_jitter", "from_csv"])]
+    max_timestamp_error: Option<u64>,
+
+    /// Checkpoint collected samples to this JSON-lines file and, on a rerun, only fetch the missing blocks
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["streaming_aggregate", "from_csv", "cross", "compare_pools", "index", "pool_sequence"]
+    )]
+    resume: Option<String>,
+
+    /// Block tag to anchor the end of the window to when --end-date is not given
+    #[arg(long, value_enum, default_value_t = EndTag::Latest, conflicts_with_all = ["end_date", "end_block"])]
+    end: EndTag,
 }
 
-#[derive(Debug)]
-struct PricePoint {
-    timestamp: u64,
-    price: f64,
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Serve JSON reports over HTTP at /twap?pool=ADDR&days=N&samples=N, reusing the provider and caches
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+
+        /// Requests computed at once, each fetching up to --concurrency samples; further requests wait for a free slot
+        #[arg(long, default_value = "4")]
+        max_concurrent: usize,
+    },
+    /// Print the --pool's tokens, current reserves and current price, then exit without sampling
+    Info,
 }
 
-/// Find the block number closest to a given timestamp using binary search
-async fn find_block_at_timestamp(
-    provider: Arc<Provider<Http>>,
-    target_timestamp: u64,
-) -> Result<U64> {
-    let latest_block = provider.get_block_number().await?;
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+    Prometheus,
+    Markdown,
+    Csv,
+}
 
-    // Get latest block timestamp to check if target is in the future
-    let latest = provider.get_block(latest_block).await?.context("Latest block not found")?;
-    let latest_timestamp = latest.timestamp.as_u64();
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum JsonNumbers {
+    Number,
+    String,
+}
 
-    if target_timestamp >= latest_timestamp {
-        return Ok(latest_block);
-    }
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum EndTag {
+    Latest,
+    Safe,
+    Finalized,
+}
 
-    // Binary search for the block
-    let mut low = 1u64;
-    let mut high = latest_block.as_u64();
-    let mut best_block = latest_block.as_u64();
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Method {
+    /// Sample the spot price across the window
+    Sample,
+    /// Read the pool's tick-cumulative oracle, falling back to sampling if it's unavailable
+    Observe,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum PriceSource {
+    /// The reserves after the block, like any other sample
+    Last,
+    /// The mean execution price of the block's swaps
+    Average,
+    /// The median execution price of the block's swaps
+    Median,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Spacing {
+    /// Evenly spaced samples
+    Linear,
+    /// Logarithmically spaced samples, dense near the end of the window
+    Log,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OnError {
+    Abort,
+    Skip,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum PoolType {
+    Auto,
+    Volatile,
+    Stable,
+}
 
-    println!("🔍 Finding block at timestamp {}...", target_timestamp);
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum CircuitBreaker {
+    Pause,
+    Abort,
+}
+
+/// Find the first block at which the pool contract has code, using binary search
+async fn find_pool_creation_block(
+    provider: &Provider<Http>,
+    pool_address: Address,
+    latest_block: U64,
+) -> Result<U64> {
+    status!("🔍 Finding pool creation block...");
+
+    let mut low = 0u64;
+    let mut high = latest_block.as_u64();
 
-    while low <= high {
+    while low < high {
         let mid = (low + high) / 2;
 
-        let block = provider.get_block(U64::from(mid)).await?
-            .context(format!("Block {} not found", mid))?;
-        let block_timestamp = block.timestamp.as_u64();
+        let code = provider
+            .get_code(pool_address, Some(BlockId::Number(BlockNumber::Number(U64::from(mid)))))
+            .await
+            .context(format!("Failed to get pool code at block {}", mid))?;
 
-        if block_timestamp <= target_timestamp {
-            best_block = mid;
+        if code.is_empty() {
             low = mid + 1;
         } else {
-            high = mid - 1;
+            high = mid;
         }
     }
 
-    println!("✓ Found block {} for timestamp {}", best_block, target_timestamp);
-    Ok(U64::from(best_block))
+    status!("✓ Pool created at block {}", low);
+    Ok(U64::from(low))
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let args = Args::parse();
-
-    println!("🚀 Aerodrome TWAP Calculator");
-    println!("📍 Pool: {}", args.pool);
-    println!("⏰ Period: {} days", args.days);
-    println!("📊 Samples: {}", args.samples);
-    println!();
-
-    // Connect to Base network
-    let provider = Provider::<Http>::try_from(&args.rpc)
-        .context("Failed to connect to RPC")?;
-    let provider = Arc::new(provider);
+/// Resolve a block tag to a block number, falling back to latest if the chain doesn't support it
+async fn resolve_end_tag(provider: &Provider<Http>, tag: EndTag) -> Result<U64> {
+    let block_tag = match tag {
+        EndTag::Latest => {
+            return provider
+                .get_block_number()
+                .await
+                .map_err(|e| TwapError::RpcUnavailable(e.to_string()).into());
+        }
+        EndTag::Safe => BlockNumber::Safe,
+        EndTag::Finalized => BlockNumber::Finalized,
+    };
 
-    // Parse pool address
-    let pool_address: Address = args.pool.parse().context("Invalid pool address")?;
-    let pool = AerodromePool::new(pool_address, provider.clone());
+    match provider.get_block(block_tag).await {
+        Ok(Some(Block { number: Some(number), .. })) => {
+            status!("🔒 End block: {} ({:?})", number, tag);
+            Ok(number)
+        }
+        _ => {
+            status!("⚠️  RPC does not support the {:?} block tag, falling back to latest", tag);
+            provider.get_block_number().await.context("Failed to get current block")
+        }
+    }
+}
 
-    // Get token information
-    let token0_addr = pool.token_0().call().await.context("Failed to get token0")?;
-    let token1_addr = pool.token_1().call().await.context("Failed to get token1")?;
+/// Parse a YYYY-MM-DD date as midnight US Central Time
+fn parse_central_midnight(date_str: &str) -> Result<u64> {
+    let naive_date = NaiveDate::parse_from_str(date_str, "%Y-%m-%d")
+        .context(format!("Invalid date format '{}'. Expected YYYY-MM-DD", date_str))?;
 
-    let token0 = ERC20::new(token0_addr, provider.clone());
-    let token1 = ERC20::new(token1_addr, provider.clone());
+    let datetime = Central.from_local_datetime(
+        &naive_date.and_hms_opt(0, 0, 0).context("Invalid time")?
+    ).single().context("Ambiguous datetime")?;
 
-    let token0_decimals = token0.decimals().call().await.context("Failed to get token0 decimals")?;
-    let token1_decimals = token1.decimals().call().await.context("Failed to get token1 decimals")?;
-    let token0_symbol = token0.symbol().call().await.unwrap_or_else(|_| "UNKNOWN".to_string());
-    let token1_symbol = token1.symbol().call().await.unwrap_or_else(|_| "UNKNOWN".to_string());
+    Ok(datetime.timestamp() as u64)
+}
 
-    println!("📌 Token0: {} ({})", token0_symbol, token0_addr);
-    println!("📌 Token1: {} ({})", token1_symbol, token1_addr);
-    println!();
+/// Read a file of block hashes, one per line, and resolve each to its block number, ordered by
+/// number. Errors if a hash isn't a block this RPC knows; warns if a block was reorged out.
+async fn resolve_block_hashes(provider: &Provider<Http>, path: &str) -> Result<Vec<(U64, H256)>> {
+    let contents = std::fs::read_to_string(path).context(format!("Failed to read block hashes from {}", path))?;
 
-    // Determine the end block (either from end_date or current block)
-    let end_block = if let Some(date_str) = &args.end_date {
-        // Parse the date as midnight US Central Time
-        let naive_date = NaiveDate::parse_from_str(date_str, "%Y-%m-%d")
-            .context(format!("Invalid date format '{}'. Expected YYYY-MM-DD", date_str))?;
+    let mut pinned = Vec::new();
+    for line in contents.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        let hash: H256 = line.parse().context(format!("Invalid block hash '{}' in {}", line, path))?;
+        let block = provider
+            .get_block(hash)
+            .await
+            .context(format!("Failed to get block {:?}", hash))?
+            .context(format!("Block hash {:?} doesn't resolve to a block on this RPC", hash))?;
+        let number = block.number.context(format!("Block {:?} is still pending", hash))?;
 
-        let datetime = Central.from_local_datetime(
-            &naive_date.and_hms_opt(0, 0, 0).context("Invalid time")?
-        ).single().context("Ambiguous datetime")?;
+        if read_block_hash(provider, number).await? != Some(hash) {
+            status!("⚠️  Block {:?} ({}) is no longer on the canonical chain; reading its state anyway", hash, number);
+        }
+        pinned.push((number, hash));
+    }
+    if pinned.is_empty() {
+        anyhow::bail!("{} contains no block hashes", path);
+    }
 
-        let timestamp = datetime.timestamp() as u64;
+    pinned.sort();
+    pinned.dedup();
+    status!("📌 Pinned {} sample blocks by hash ({} – {})", pinned.len(), pinned[0].0, pinned[pinned.len() - 1].0);
+    status!();
+    Ok(pinned)
+}
 
-        println!("📅 End date: {} (midnight US Central = timestamp {})", date_str, timestamp);
+/// Hash of a block, to tell whether it was reorged out later
+async fn read_block_hash(provider: &Provider<Http>, block: U64) -> Result<Option<H256>> {
+    let found = provider.get_block(block).await.context("Failed to get block")?;
+    Ok(found.and_then(|b| b.hash))
+}
 
-        // Find the block at this timestamp
-        find_block_at_timestamp(provider.clone(), timestamp).await?
-    } else {
-        provider.get_block_number().await.context("Failed to get current block")?
-    };
+/// Warn that the end block changed hash during collection, and under --continue-on-reorg
+/// re-read the samples close enough to the end to have been affected
+async fn recover_from_reorg(
+    args: &Args,
+    provider: &Provider<Http>,
+    sampler: &sampler::Sampler,
+    blocks: &[U64],
+    end_block: U64,
+    price_points: &mut Vec<PricePoint>,
+) -> Result<()> {
+    let affected = |block: u64| block + REORG_DEPTH >= end_block.as_u64();
 
-    // Calculate time period
-    let seconds_per_day = 86400u64;
-    let total_seconds = args.days * seconds_per_day;
-    let interval_seconds = total_seconds / args.samples;
+    status!("⚠️  Chain reorged during collection: block {} changed hash", end_block);
+    if !args.continue_on_reorg {
+        status!("   Samples within {} blocks of the end may be stale. Pass --continue-on-reorg to re-read them", REORG_DEPTH);
+        status!();
+        return Ok(());
+    }
 
-    // Base has ~2 second block time on average
-    let blocks_per_second = 0.5f64;
-    let blocks_per_interval = (interval_seconds as f64 * blocks_per_second) as u64;
+    let tail: Vec<U64> = blocks.iter().copied().filter(|b| affected(b.as_u64())).collect();
+    status!("🔁 Re-reading {} samples within {} blocks of the end", tail.len(), REORG_DEPTH);
+    price_points.retain(|p| !affected(p.block));
+    price_points.extend(collect_price_points(args, provider, sampler, &tail).await?);
+    Ok(())
+}
 
-    println!("⏱️  Collecting price data...");
+/// The price change within `block`, if the move since the previous sample is over `threshold_pct`
+/// and at least that much of it happened in the block itself. Reads the block before to tell.
+async fn single_block_change(
+    retry: &retry::RetryPolicy,
+    sampler: &sampler::Sampler,
+    block: U64,
+    previous_price: f64,
+    price: f64,
+    threshold_pct: f64,
+) -> Option<f64> {
+    let change_pct = |from: f64| (price - from) / from * 100.0;
+    if change_pct(previous_price).abs() <= threshold_pct {
+        return None;
+    }
+    let before = retry.run(|| sampler.read_price(block - 1)).await.ok().flatten()?;
+    Some(change_pct(before.price)).filter(|change| change.abs() > threshold_pct)
+}
 
-    let mut price_points = Vec::new();
-    let mut total_weighted_price = 0.0f64;
-    let mut total_time = 0u64;
+/// Most blocks --max-timestamp-error reads while walking one sample toward its intended time
+const MAX_CORRECTION_STEPS: usize = 8;
 
-    for i in 0..args.samples {
-        let blocks_back = (args.samples - i) * blocks_per_interval;
-        let target_block = if blocks_back > end_block.as_u64() {
-            U64::from(1) // Genesis block if we go too far back
-        } else {
-            end_block - blocks_back
+/// Walk a drifting sample block toward its intended time, stepping by the remaining error over the
+/// block time, until it's within --max-timestamp-error or the steps run out. Keeps the closest
+/// block seen, never past `last_block`.
+async fn correct_sample_block(
+    args: &Args,
+    retry: &retry::RetryPolicy,
+    provider: &Provider<Http>,
+    (block, timestamp): (U64, u64),
+    intended: u64,
+    last_block: U64,
+) -> Result<(U64, u64)> {
+    let max_error = args.max_timestamp_error.unwrap_or_default();
+    let block_time = args.block_time.unwrap_or(2.0);
+    let (mut best, mut current) = ((block, timestamp), (block, timestamp));
+    for _ in 0..MAX_CORRECTION_STEPS {
+        if current.1.abs_diff(intended) <= max_error {
+            break;
+        }
+        let remaining = intended as f64 - current.1 as f64;
+        let step = match (remaining / block_time).round() as i64 {
+            0 => remaining.signum() as i64,
+            step => step,
         };
+        let next = U64::from(current.0.as_u64().saturating_add_signed(step).clamp(1, last_block.as_u64()));
+        if next == current.0 {
+            break;
+        }
+        current = (next, retry.run(|| read_block_timestamp(provider, next)).await?);
+        if current.1.abs_diff(intended) < best.1.abs_diff(intended) {
+            best = current;
+        }
+    }
+    Ok(best)
+}
 
-        // Get block timestamp
-        let block = provider
-            .get_block(target_block)
-            .await
-            .context("Failed to get block")?
-            .context("Block not found")?;
+/// One planned block's reads, before the checks that need the samples in order
+struct Fetched {
+    block: U64,
+    timestamp: u64,
+    sample: Option<sampler::Sample>,
+    substitution: Option<report::Substitution>,
+    /// Seconds the planned block was off its intended time, when --max-timestamp-error moved it
+    timestamp_error: Option<u64>,
+}
 
-        let timestamp = block.timestamp.as_u64();
+/// Read a planned block's timestamp (unless already known) and price, moving it closer to its
+/// intended time under --max-timestamp-error and onto a nearby block under --sample-retry-different-block
+async fn fetch_sample(
+    args: &Args,
+    retry: &retry::RetryPolicy,
+    provider: &Provider<Http>,
+    sampler: &sampler::Sampler,
+    (block, timestamp): (U64, Option<u64>),
+    anchor: Option<(U64, u64)>,
+    last_block: U64,
+) -> Result<Fetched> {
+    let timestamp = match timestamp {
+        Some(timestamp) => timestamp,
+        None => retry.run(|| read_block_timestamp(provider, block)).await?,
+    };
 
-        // Get reserves at this block
-        let (reserve0, reserve1, _) = pool
-            .get_reserves()
-            .block(BlockId::Number(BlockNumber::Number(target_block)))
-            .call()
-            .await
-            .context(format!("Failed to get reserves at block {}", target_block))?;
-
-        // Calculate price (token1 per token0)
-        if reserve0 > 0 {
-            let reserve0_f64 = reserve0 as f64 / 10f64.powi(token0_decimals as i32);
-            let reserve1_f64 = reserve1 as f64 / 10f64.powi(token1_decimals as i32);
-            let price = reserve1_f64 / reserve0_f64;
-
-            price_points.push(PricePoint {
-                timestamp,
-                price,
-            });
-
-            // Calculate time weight for TWAP
-            if i > 0 {
-                let prev_idx = (i - 1) as usize;
-                let time_diff = timestamp - price_points[prev_idx].timestamp;
-                let weighted_price = price_points[prev_idx].price * time_diff as f64;
-                total_weighted_price += weighted_price;
-                total_time += time_diff;
+    let ((block, timestamp), timestamp_error) = match (args.max_timestamp_error, anchor) {
+        (Some(max_error), Some((anchor_block, anchor_timestamp))) => {
+            let block_time = args.block_time.unwrap_or(2.0);
+            let behind = (anchor_block.saturating_sub(block).as_u64() as f64 * block_time) as u64;
+            let intended = anchor_timestamp.saturating_sub(behind);
+            let error = timestamp.abs_diff(intended);
+            if error <= max_error {
+                ((block, timestamp), None)
+            } else {
+                (correct_sample_block(args, retry, provider, (block, timestamp), intended, anchor_block).await?, Some(error))
             }
+        }
+        _ => ((block, timestamp), None),
+    };
+
+    let read = retry.run(|| sampler.read_price(block)).await;
+    let (block, timestamp, sample, substitution) = match (read, args.sample_retry_different_block) {
+        (Ok(sample), _) => (block, timestamp, sample, None),
+        (Err(e), Some(max_offset)) => {
+            let Some((substitute, substitute_timestamp, sample)) =
+                nearest_readable_block(provider, sampler, block, max_offset, last_block).await
+            else {
+                return Err(e.context(format!("no readable block within {} of it", max_offset)));
+            };
+            let timestamp_offset = substitute_timestamp as i64 - timestamp as i64;
+            status!();
+            status!("🔁 Block {} is unreadable ({:#}); sampled block {} instead ({:+}s)", block, e, substitute, timestamp_offset);
+            let substitution = report::Substitution { requested_block: block.as_u64(), timestamp_offset };
+            (substitute, substitute_timestamp, sample, Some(substitution))
+        }
+        (Err(e), None) => return Err(e),
+    };
+
+    Ok(Fetched { block, timestamp, sample, substitution, timestamp_error })
+}
+
+/// The nearest block within `max_offset` of an unreadable one whose timestamp and price read,
+/// trying the earlier side first at each distance and never passing `last_block`
+async fn nearest_readable_block(
+    provider: &Provider<Http>,
+    sampler: &sampler::Sampler,
+    block: U64,
+    max_offset: u64,
+    last_block: U64,
+) -> Option<(U64, u64, Option<sampler::Sample>)> {
+    for offset in 1..=max_offset {
+        let earlier = block.as_u64().checked_sub(offset).map(U64::from);
+        let later = Some(block + offset).filter(|later| *later <= last_block);
+        for candidate in [earlier, later].into_iter().flatten() {
+            let (Ok(timestamp), Ok(sample)) = (read_block_timestamp(provider, candidate).await, sampler.read_price(candidate).await) else {
+                continue;
+            };
+            return Some((candidate, timestamp, sample));
+        }
+    }
+    None
+}
 
-            if (i + 1) % 10 == 0 || i == args.samples - 1 {
-                print!("\r✓ Collected {}/{} samples", i + 1, args.samples);
-                use std::io::Write;
-                std::io::stdout().flush().unwrap();
+/// Act on a tripped --max-price-change-per-block. Pausing asks on a terminal and aborts otherwise,
+/// since nobody is there to answer.
+fn trip_circuit_breaker(breaker: Option<CircuitBreaker>, block: U64) -> Result<()> {
+    let abort = || anyhow::anyhow!("Circuit breaker tripped at block {}; stopping for investigation", block);
+    match breaker {
+        None => Ok(()),
+        Some(CircuitBreaker::Abort) => Err(abort()),
+        Some(CircuitBreaker::Pause) if !std::io::stdin().is_terminal() => Err(abort()),
+        Some(CircuitBreaker::Pause) => {
+            let go_on = prompt::ask("   Keep collecting? (y/n)", Some("n"), |answer| match answer {
+                "y" | "Y" | "yes" => Ok(true),
+                "n" | "N" | "no" => Ok(false),
+                _ => anyhow::bail!("answer y or n"),
+            })?;
+            if go_on {
+                Ok(())
+            } else {
+                Err(abort())
             }
         }
     }
+}
+
+/// Propagate a sample failure under `--on-error abort`, or log it under `--on-error skip`
+fn skip_or_abort(on_error: OnError, block: U64, error: anyhow::Error) -> Result<()> {
+    match on_error {
+        OnError::Abort => Err(error.context(format!("Sample at block {} failed", block))),
+        OnError::Skip => {
+            status!();
+            status!("⚠️  Skipping block {}: {:#}", block, error);
+            Ok(())
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<ExitCode> {
+    match run(Args::parse()).await {
+        Ok(code) => Ok(code),
+        Err(e) => match e.downcast_ref::<TwapError>() {
+            Some(twap_error) => {
+                eprintln!("❌ {:#}", e);
+                Ok(ExitCode::from(twap_error.exit_code()))
+            }
+            None => Err(e),
+        },
+    }
+}
 
-    println!();
-    println!();
+async fn run(mut args: Args) -> Result<ExitCode> {
+    let deadline = args.max_duration.map(|secs| tokio::time::Instant::now() + Duration::from_secs(secs));
 
-    if price_points.is_empty() {
-        anyhow::bail!("No price data collected");
+    if args.json_schema {
+        println!("{}", report::json_schema());
+        return Ok(ExitCode::SUCCESS);
     }
 
-    // Calculate final TWAP
-    let twap = if total_time > 0 {
-        total_weighted_price / total_time as f64
-    } else {
-        price_points.last().unwrap().price
-    };
-
-    // Calculate current price (spot price)
-    let current_price = price_points.last().unwrap().price;
-
-    // Calculate min and max prices
-    let min_price = price_points.iter().map(|p| p.price).fold(f64::INFINITY, f64::min);
-    let max_price = price_points.iter().map(|p| p.price).fold(f64::NEG_INFINITY, f64::max);
-
-    // Results
-    println!("📈 RESULTS");
-    println!("═══════════════════════════════════════");
-    println!("🎯 {}-Day TWAP: {:.8} {} per {}",
-        args.days, twap, token1_symbol, token0_symbol);
-    println!("💵 Current Price: {:.8} {} per {}",
-        current_price, token1_symbol, token0_symbol);
-    println!("📊 Min Price: {:.8}", min_price);
-    println!("📊 Max Price: {:.8}", max_price);
-    println!("📉 Price Range: {:.2}%",
-        ((max_price - min_price) / min_price * 100.0));
-    println!("📍 Deviation from TWAP: {:.2}%",
-        ((current_price - twap) / twap * 100.0));
-    println!("═══════════════════════════════════════");
+    STATUS_TO_STDERR.store(args.format != OutputFormat::Text || args.bps_only, Ordering::Relaxed);
+    QUIET.store(args.quiet, Ordering::Relaxed);
+    OUTPUT_TIMEZONE.get_or_init(|| args.output_timezone);
+    // Under --quote-decimals and --price-precision-auto it depends on the quote token or the TWAP,
+    // so print_results sets it once that's known
+    if args.quote_decimals.is_none() && args.price_precision_auto.is_none() {
+        PRICE_DISPLAY.get_or_init(|| (args.decimals, args.rounding));
+    }
 
-    Ok(())
+    match args.command.clone() {
+        Some(Command::Serve { listen, max_concurrent }) => return run_serve(args, &listen, max_concurrent).await,
+        Some(Command::Info) => return run_info(&args).await,
+        None => {}
+    }
+
+    if let Some(path) = &args.from_csv {
+        return run_from_csv(&args, path);
+    }
+
+    if args.interactive && args.pool.is_none() && args.wrapper.is_none() && args.cross.is_none()
+        && args.compare_pools.is_none() && args.index.is_none() && args.pool_sequence.is_none() && args.list_pools.is_none()
+    {
+        prompt_missing_args(&mut args)?;
+    }
+
+    read_piped_args(&mut args)?;
+    validate_args(&args)?;
+
+    if let Some(path) = &args.export_config {
+        export_config(&args, path)?;
+        status!("💾 Wrote the effective settings to {}", path);
+        status!();
+    }
+
+    if args.validate {
+        return run_validate(&args);
+    }
+
+    if let Some(token) = &args.list_pools {
+        return run_list_pools(&args, token).await;
+    }
+
+    status!("🚀 Aerodrome TWAP Calculator");
+    match (&args.pool, &args.wrapper, &args.cross) {
+        (Some(pool), _, _) => match pool.parse::<Address>() {
+            Ok(address) => status!("📍 Pool: {}", report::checksum(&address)),
+            Err(_) => status!("📍 Pool: {}", pool),
+        },
+        (None, Some(wrapper), _) => status!("🎁 Wrapper: {}", wrapper),
+        (None, None, Some(cross)) => status!("🔀 Cross: {}", cross),
+        (None, None, None) => match (&args.compare_pools, &args.index, &args.pool_sequence) {
+            (Some(pools), _, _) => status!("⚖️  Comparing pools: {}", pools),
+            (None, Some(pools), _) => status!("🧺 Index: {}", pools),
+            (None, None, Some(sequence)) => status!("🔗 Pool sequence: {}", sequence),
+            (None, None, None) => anyhow::bail!("--pool is required"),
+        },
+    }
+    status!("⏰ Period: {} days", args.days);
+    status!("📊 Samples: {}", args.samples);
+    if let Some(block_time) = args.block_time {
+        status!("🧱 Block time: {}s", block_time);
+    }
+    // Modes that pick their own blocks have no fixed step to report
+    if !picks_own_blocks(&args) {
+        let spacing = if args.spacing == Spacing::Log { " on average" } else { "" };
+        status!("🔬 Resolution: a sample every {} (~{} blocks){}",
+            format_duration(args.days * SECONDS_PER_DAY / args.samples), blocks_per_interval(&args), spacing);
+    }
+    status!();
+
+    // Connect to Base network
+    let provider = connect_provider(&args).await?;
+
+    let mut token_cache = metadata::TokenCache::new(provider.clone());
+    let reserves_cache = Arc::new(cache::ReservesCache::open(&provider, args.reserves_cache.as_deref(), REORG_DEPTH).await?);
+
+    if let Some(interval) = args.repeat {
+        return run_repeat(&args, provider, token_cache, reserves_cache, interval).await;
+    }
+
+    if let Some(cross) = &args.cross {
+        return run_cross(&args, provider, &mut token_cache, &reserves_cache, cross).await;
+    }
+
+    if let Some(pools) = &args.compare_pools {
+        return run_compare_pools(&args, provider, &mut token_cache, &reserves_cache, pools).await;
+    }
+
+    if let Some(spec) = &args.index {
+        return run_index(&args, provider, &mut token_cache, &reserves_cache, spec).await;
+    }
+
+    if let Some(sequence) = &args.pool_sequence {
+        return run_pool_sequence(&args, provider, &mut token_cache, &reserves_cache, sequence).await;
+    }
+
+    // Parse pool address
+    // A wrapper exposes token0()/token1() like a pool, so it stands in for one from here on
+    let pool_address: Address = args
+        .pool
+        .as_deref()
+        .or(args.wrapper.as_deref())
+        .unwrap_or_default()
+        .parse()
+        .context("Invalid pool address")?;
+    let tokens = match (&args.token0, &args.token1) {
+        (Some(token0), Some(token1)) => Some((
+            token0.parse().context("Invalid --token0 address")?,
+            token1.parse().context("Invalid --token1 address")?,
+        )),
+        _ => None,
+    };
+    let pool = load_pool(&provider, &mut token_cache, pool_address, tokens, (args.token0_decimals, args.token1_decimals)).await?;
+
+    if args.show_gauge {
+        show_gauge(&args, &provider, pool_address).await?;
+    }
+
+    if args.strict_decimals {
+        check_pool_decimals(&provider, &pool).await;
+    }
+
+    // Overridden tokens are exactly where the reserve order can silently be wrong
+    if args.check_reserve_order || (tokens.is_some() && !args.concentrated && args.wrapper.is_none()) {
+        check_reserve_order(&provider, &pool).await;
+    }
+
+    // Pinned hashes replace the planned blocks, and the newest of them ends the window
+    let pinned = match &args.block_hashes {
+        Some(path) => Some(resolve_block_hashes(&provider, path).await?),
+        None => None,
+    };
+    let end_block = match pinned.as_ref().and_then(|pinned| pinned.last()) {
+        Some(&(block, _)) => block,
+        None => match resumed_end_block(&args, pool_address)? {
+            Some(block) => block,
+            None => resolve_end_block(&args, &provider).await?,
+        },
+    };
+    let blocks_per_interval = blocks_per_interval(&args);
+
+    if let Some(max_age) = args.require_fresh_end {
+        check_fresh_end(&args, &provider, pool_address, end_block, max_age).await?;
+    }
+
+    if args.method == Method::Observe {
+        let window_seconds = args.days * SECONDS_PER_DAY;
+        status!("🔭 Reading observe() tick cumulatives over {}s...", window_seconds);
+        match oracle::observe_twap(&provider, pool.address, (pool.token0.decimals, pool.token1.decimals), end_block, window_seconds).await {
+            Ok((mean_tick, twap)) => {
+                status!();
+                let observed = report::ObservedTwap::new(pool, args.days, end_block.as_u64(), mean_tick, twap);
+                match args.format {
+                    OutputFormat::Text => observed.print_text(),
+                    OutputFormat::Json => println!("{}", to_json(&observed, &args)?),
+                    OutputFormat::Prometheus | OutputFormat::Markdown | OutputFormat::Csv => anyhow::bail!("--method observe only supports --format text and json"),
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+            Err(e) => {
+                status!("⚠️  observe() unavailable ({:#}), falling back to sampling", e);
+                status!();
+            }
+        }
+    }
+
+    if args.ref_amount.is_some() {
+        let stable = AerodromePool::new(pool_address, provider.clone())
+            .stable()
+            .call()
+            .await
+            .context("Failed to read stable(); --ref-amount needs an Aerodrome stable pool")?;
+        if !stable {
+            anyhow::bail!("--ref-amount needs a stable pool, but {} is volatile", report::checksum(&pool_address));
+        }
+    }
+
+    // The history check probes the requested start; only then is the start clamped to the pool's creation
+    let sampled = args.samples + args.warmup_samples;
+    let requested_window = resolve_window(end_block, sampled, blocks_per_interval, U64::one());
+    let window_start_estimate = match pinned.as_ref().and_then(|pinned| pinned.first()) {
+        Some(&(block, _)) => block,
+        None => requested_window.start_block,
+    };
+    // A probe reports the cutoff itself instead of failing on it
+    if !args.probe {
+        if let Err(e) = check_history_available(&args, &provider, pool_address, window_start_estimate, end_block).await {
+            match (&args.subgraph, e.downcast_ref::<TwapError>()) {
+                (Some(url), Some(TwapError::ArchiveRequired(_))) => {
+                    status!("⚠️  {:#}", e);
+                    return run_subgraph_fallback(&args, &provider, url, pool, end_block).await;
+                }
+                _ => return Err(e),
+            }
+        }
+    }
+    // Pinned blocks and probes are read exactly as planned
+    let window = if pinned.is_none() && !args.probe {
+        clamp_window_to_pool(&args, &provider, pool_address, requested_window).await?
+    } else {
+        requested_window
+    };
+
+    let mut sampler = build_sampler(&args, &provider, &pool, &reserves_cache).await?;
+    let is_stable = sampler.stable();
+    if is_stable && args.price_in_wei.is_some() {
+        anyhow::bail!("--price-in-wei uses the constant-product price, which doesn't hold on the stable pool {}", report::checksum(&pool_address));
+    }
+    if let Some(pinned) = &pinned {
+        sampler = sampler.with_block_hashes(pinned.iter().copied().collect());
+    }
+    if args.reserves_at_end_only {
+        let start_block = plan_sample_blocks(end_block, args.samples, window.blocks_per_interval)
+            .first()
+            .copied()
+            .unwrap_or(end_block);
+        return run_quick_estimate(&args, &provider, &sampler, pool, start_block, end_block).await;
+    }
+
+    // Plain runs are sampled by the library's compute_twap; the rest plan and collect their own blocks
+    let library_run = pinned.is_none() && samples_like_library(&args);
+    let mut blocks = if library_run {
+        Vec::new()
+    } else if let Some(pinned) = &pinned {
+        pinned.iter().map(|&(block, _)| block).collect()
+    } else if args.daily_boundaries {
+        daily_sample_blocks(&args, &provider, end_block).await?
+    } else if let Some(period) = args.sample_at_open {
+        period_open_blocks(&args, &provider, end_block, period).await?
+    } else {
+        plan_spaced_blocks(&args, &provider, &window, sampled).await?
+    };
+    if let Some(max_offset) = args.sample_jitter {
+        let planned = blocks.len();
+        let mean_offset = jitter_sample_blocks(&mut blocks, max_offset, end_block, args.seed);
+        status!("🎲 Jittered sample blocks by up to ±{} blocks (mean |offset| {:.1})", max_offset, mean_offset);
+        if blocks.len() < planned {
+            status!("   {} samples jittered onto the same block as another and were merged", planned - blocks.len());
+        }
+        status!();
+    }
+    if args.probe {
+        return run_probe(&args, &sampler, &blocks).await;
+    }
+
+    // Samples before this block only establish the leading interval
+    let window_start = match library_run {
+        true => U64::zero(),
+        false => blocks.get(args.warmup_samples as usize).copied().unwrap_or(end_block),
+    };
+
+    // Intra-block prices that replace the post-block reserves price under --price-source
+    let mut swap_prices = HashMap::new();
+    if args.samples_from_events {
+        (blocks, swap_prices) = swap_sample_blocks(&args, &provider, &pool, &blocks, end_block).await?;
+    }
+    if let Some(per_sample) = args.swaps_per_sample {
+        blocks = swap_count_sample_blocks(&args, &provider, pool_address, &blocks, end_block, per_sample).await?;
+    }
+
+    // Re-checked after collection to catch a reorg of the window's tail during a long run
+    let end_hash = read_block_hash(&provider, end_block).await?;
+
+    let sampling_started = Instant::now();
+    let mut completed;
+    let mut session = None;
+    let mut gaps = None;
+    let mut report = if args.streaming_aggregate {
+        let mut stream = analytics::StreamingTwap::new(pool.token0.decimals, pool.token1.decimals);
+        completed = collect_until(deadline, collect_samples(&args, &provider, &sampler, &blocks, |mut point| {
+            if let Some(&price) = swap_prices.get(&point.block) {
+                point.price = price;
+            }
+            stream.push(point)
+        }))
+        .await?;
+        if read_block_hash(&provider, end_block).await? != end_hash {
+            status!("⚠️  Chain reorged during collection: block {} changed hash", end_block);
+            status!("   Samples within {} blocks of the end may be stale; streamed samples can't be re-read", REORG_DEPTH);
+            status!();
+        }
+        if stream.count == 0 {
+            return Err(TwapError::NoData.into());
+        }
+
+        let realized_variance = args.realized_variance.then(|| stream.realized_variance(args.annualization_days)).flatten();
+        let mut report = report::TwapReport::from_stream(pool_address, pool.token0, pool.token1, args.days, args.samples, stream);
+        report.realized_variance = realized_variance;
+        report
+    } else {
+        let mut checkpoint = match &args.resume {
+            Some(path) => Some(checkpoint::Checkpoint::open(path, checkpoint::Header {
+                pool: pool_address,
+                days: args.days,
+                samples: args.samples,
+                end_block: end_block.as_u64(),
+            })?),
+            None => None,
+        };
+        let mut price_points = checkpoint.as_mut().map(|c| std::mem::take(&mut c.points)).unwrap_or_default();
+        let remaining: Vec<U64> = if price_points.is_empty() {
+            blocks.clone()
+        } else {
+            let done: std::collections::HashSet<u64> = price_points.iter().map(|p| p.block).collect();
+            let remaining: Vec<U64> = blocks.iter().copied().filter(|block| !done.contains(&block.as_u64())).collect();
+            status!("♻️  Resumed {} samples from checkpoint, {} blocks left to fetch", price_points.len(), remaining.len());
+            status!();
+            remaining
+        };
+        completed = if library_run {
+            (price_points, blocks) = sample_with_library(&args, &provider, &pool, &window, is_stable, &reserves_cache).await?;
+            true
+        } else {
+            collect_until(
+                deadline,
+                collect_samples(&args, &provider, &sampler, &remaining, |point| {
+                    if let Some(checkpoint) = checkpoint.as_mut() {
+                        checkpoint.record(&point);
+                    }
+                    price_points.push(point)
+                }),
+            )
+            .await?
+        };
+        price_points.sort_by_key(|p| p.block);
+        if let (Some(threshold), true) = (args.adaptive, completed) {
+            completed = collect_until(deadline, refine_adaptively(&args, &provider, &sampler, &mut price_points, threshold)).await?;
+        }
+        if read_block_hash(&provider, end_block).await? != end_hash {
+            recover_from_reorg(&args, &provider, &sampler, &blocks, end_block, &mut price_points).await?;
+        }
+        for point in &mut price_points {
+            if let Some(&price) = swap_prices.get(&point.block) {
+                point.price = price;
+            }
+        }
+        let warmup_count = price_points.partition_point(|p| p.block < window_start.as_u64());
+        let leading = price_points.drain(..warmup_count).next_back();
+        if price_points.is_empty() {
+            return Err(TwapError::NoData.into());
+        }
+
+        if args.detect_rebases {
+            warn_moves_without_swaps(&args, &provider, pool_address, &price_points).await?;
+        }
+        if args.verify_reserves_sum {
+            verify_reserves_within_supply(&provider, &pool, &price_points).await;
+        }
+        let mut price_points = check_pool_health(&args, price_points);
+        let (token0, token1, inversion) = auto_invert(&args, &mut price_points, pool.token0, pool.token1);
+        let (price_points, filtered) = session_filter(&args, price_points)?;
+        session = filtered;
+        let (price_points, filtered) = reserve_gaps(&args, price_points, &token0, &token1)?;
+        gaps = filtered;
+
+        let mut report = report::TwapReport::new(
+            pool_address,
+            token0,
+            token1,
+            args.days,
+            args.samples,
+            price_points,
+        );
+        report.auto_inverted = inversion;
+        report.pool_name = pool.name.clone();
+        if let Some(leading) = leading {
+            status!("🔥 Weighted the leading interval from warmup block {} ({} warmup samples read)", leading.block, warmup_count);
+            status!();
+            report.include_leading_interval(&leading);
+        }
+        report
+    };
+
+    let sampling_seconds = sampling_started.elapsed().as_secs_f64();
+    reserves_cache.save()?;
+    report.end_block = Some(end_block.as_u64());
+    if let Some(effective_days) = window.clamped_days {
+        report.clamped_window = Some(report::ClampedWindow {
+            start_block: window.start_block.as_u64(),
+            requested_days: args.days,
+            effective_days,
+            interval_blocks: window.blocks_per_interval,
+        });
+    }
+    if !completed {
+        report.mark_partial(window_start.as_u64(), end_block.as_u64());
+    }
+
+    // Streamed reports are already time-weighted and no longer have the samples to reweight
+    if args.weight_by != analytics::Weighting::Time {
+        report.set_weighting(args.weight_by);
+    }
+    if let Some(coverage) = session {
+        report.set_session(coverage);
+    }
+    if let Some(gaps) = gaps {
+        report.set_reserve_gaps(gaps);
+    }
+
+    if let Some(scale) = args.price_in_wei {
+        report.set_fixed_point(scale)?;
+    }
+
+    if let Some(amount) = args.ref_amount {
+        report.set_execution_price(amount)?;
+    }
+
+    if !args.impact_curve.is_empty() {
+        report.set_impact_curve(&args.impact_curve, is_stable)?;
+    }
+
+    if args.benchmark {
+        status!("⏱️  Benchmarking against observe()...");
+        let observe_started = Instant::now();
+        let observed = oracle::observe_twap(&provider, report.pool, (report.token0.decimals, report.token1.decimals), end_block, args.days * SECONDS_PER_DAY).await;
+        let observe_seconds = observe_started.elapsed().as_secs_f64();
+        if let Err(e) = &observed {
+            status!("⚠️  observe() unavailable: {:#}", e);
+        }
+        status!();
+        report.set_benchmark(sampling_seconds, observe_seconds, observed.ok().map(|(_, twap)| twap));
+    }
+
+    let baseline_block = match (args.baseline_block, &args.baseline_date) {
+        (Some(block), _) => Some(U64::from(block)),
+        (None, Some(date_str)) => {
+            let timestamp = parse_central_midnight(date_str)?;
+            Some(find_block_at_timestamp(provider.clone(), timestamp, args.block_tolerance).await?)
+        }
+        (None, None) => None,
+    };
+    if let Some(block) = baseline_block {
+        let baseline = sampler
+            .read_price(block)
+            .await?
+            .context(format!("Pool had no reserves at baseline block {}", block))?;
+        report.set_baseline(block.as_u64(), baseline.price);
+    }
+
+    if let Some(feed) = &args.oracle_feed {
+        let feed: Address = feed.parse().context("Invalid oracle feed address")?;
+        status!("🔮 Reading price feed {}...", report::checksum(&feed));
+        let series = oracle::feed_series(provider.clone(), feed, &report.price_points, args.oracle_invert).await?;
+        status!();
+        report.set_oracle(feed, analytics::compute_twap(&series));
+    }
+
+    if args.accumulator_check {
+        check_accumulators(&provider, &mut report).await;
+    }
+    if let Some(threshold_pct) = args.spot_divergence_check {
+        check_spot_divergence(&provider, &mut report, threshold_pct).await;
+    }
+
+    print_results(&args, report)
+}
+
+/// Read the pool's reserve accumulators at the first and last sample and record the TWAP they
+/// imply next to the sampled one. Pools without currentCumulativePrices() only get a warning.
+async fn check_accumulators(provider: &Arc<Provider<Http>>, report: &mut report::TwapReport) {
+    let (Some((start_block, _)), Some(end_block)) = (report.first_sample, report.price_points.last().map(|p| p.block)) else {
+        return;
+    };
+    status!("🧮 Reading reserve accumulators at blocks {} and {}...", start_block, end_block);
+    let start = oracle::reserve_accumulators(provider, report.pool, start_block).await;
+    let end = oracle::reserve_accumulators(provider, report.pool, end_block).await;
+    match (start, end) {
+        (Ok(start), Ok(end)) => {
+            if !report.set_accumulator_check(start, end) {
+                status!("⚠️  The accumulators didn't advance between the two blocks, so they imply no TWAP");
+            }
+        }
+        (Err(e), _) | (_, Err(e)) => status!("⚠️  Couldn't read the pool's reserve accumulators: {:#}", e),
+    }
+    status!();
+}
+
+/// Read the accumulators over the last sample's block, when the pool's reserves before its swaps
+/// were in effect, and compare the price they imply with the sample's post-block reserves
+async fn check_spot_divergence(provider: &Arc<Provider<Http>>, report: &mut report::TwapReport, threshold_pct: f64) {
+    let Some(block) = report.price_points.last().map(|p| p.block) else {
+        return;
+    };
+    status!("🧮 Comparing block {}'s reserves with its accumulators...", block);
+    let before = oracle::reserve_accumulators(provider, report.pool, block.saturating_sub(1)).await;
+    let after = oracle::reserve_accumulators(provider, report.pool, block).await;
+    match (before, after) {
+        (Ok(before), Ok(after)) => match report.set_spot_divergence(before, after, threshold_pct) {
+            Some(check) if check.diverged => {
+                status!("🚨 WARNING: block {}'s reserve price {:.8} is {:+.2}% from the accumulator-implied {:.8}",
+                    block, check.spot_price, check.divergence_pct, check.accumulator_price);
+                status!("   The block traded (or was manipulated) right at the window's end");
+            }
+            Some(check) => status!("✅ Block {}'s reserve price is within {}% of the accumulator-implied price ({:+.2}%)",
+                block, threshold_pct, check.divergence_pct),
+            None => status!("⚠️  Couldn't compare block {}: it has no reserves or the accumulators didn't advance", block),
+        },
+        (Err(e), _) | (_, Err(e)) => status!("⚠️  Couldn't read the pool's reserve accumulators: {:#}", e),
+    }
+    status!();
+}
+
+/// An RPC URL cut to its scheme, host and port, since paths and query strings often carry API keys
+fn endpoint_label(rpc: &str) -> String {
+    match reqwest::Url::parse(rpc) {
+        Ok(url) => match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{}://{}:{}", url.scheme(), host, port),
+            (Some(host), None) => format!("{}://{}", url.scheme(), host),
+            _ => url.scheme().to_string(),
+        },
+        Err(_) => "invalid-url".to_string(),
+    }
+}
+
+/// Longest the startup connectivity check waits for the RPC endpoint to answer
+const RPC_PROBE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Build the RPC provider, routing through `--proxy` if given. Without the flag, reqwest picks
+/// up HTTPS_PROXY/HTTP_PROXY (and NO_PROXY) from the environment; the flag overrides them.
+///
+/// Asks for the latest block before returning, so a bad URL fails up front with a message that
+/// says whether the endpoint couldn't be reached or answered with an error.
+async fn connect_provider(args: &Args) -> Result<Arc<Provider<Http>>> {
+    let url = reqwest::Url::parse(&args.rpc).context("Failed to connect to RPC: invalid URL")?;
+
+    let mut client = reqwest::Client::builder().default_headers(parse_headers(&args.headers)?);
+    if let Some(proxy) = &args.proxy {
+        let proxy = reqwest::Proxy::all(proxy).context(format!("Invalid proxy URL '{}'", proxy))?;
+        client = client.proxy(proxy);
+    }
+    if args.force_http1 {
+        client = client.http1_only();
+    } else if args.force_http2 {
+        client = client.http2_prior_knowledge();
+    }
+    let client = client.build().context("Failed to build the HTTP client")?;
+
+    let provider = Arc::new(Provider::new(Http::new_with_client(url, client)));
+    probe_rpc(&provider, &args.rpc).await?;
+    Ok(provider)
+}
+
+/// Check the endpoint answers `eth_blockNumber`. A failed request (DNS, refused connection, TLS,
+/// timeout) is `RpcUnreachable`; a JSON-RPC error or a response that isn't JSON-RPC at all,
+/// like an HTML error page, is `RpcRejected`.
+async fn probe_rpc(provider: &Provider<Http>, rpc: &str) -> Result<()> {
+    let endpoint = endpoint_label(rpc);
+    let error = match tokio::time::timeout(RPC_PROBE_TIMEOUT, provider.get_block_number()).await {
+        Ok(Ok(_)) => return Ok(()),
+        Err(_) => TwapError::RpcUnreachable(endpoint, format!("no response within {}s", RPC_PROBE_TIMEOUT.as_secs())),
+        Ok(Err(ProviderError::HTTPError(e))) => TwapError::RpcUnreachable(endpoint, anyhow::Error::new(e).root_cause().to_string()),
+        Ok(Err(e)) => match RpcError::as_error_response(&e) {
+            Some(response) => TwapError::RpcRejected(endpoint, response.message.clone()),
+            None => TwapError::RpcRejected(endpoint, e.to_string()),
+        },
+    };
+    Err(error.into())
+}
+
+/// Parse repeated `--header "Name: Value"` options. Values are never echoed back in errors,
+/// since they're usually API keys.
+fn parse_headers(headers: &[String]) -> Result<reqwest::header::HeaderMap> {
+    let mut map = reqwest::header::HeaderMap::new();
+    for header in headers {
+        let (name, value) = header
+            .split_once(':')
+            .context("Invalid --header. Expected \"Name: Value\"")?;
+        let name = reqwest::header::HeaderName::from_bytes(name.trim().as_bytes())
+            .context(format!("Invalid --header name '{}'", name.trim()))?;
+        let mut value = reqwest::header::HeaderValue::from_str(value.trim())
+            .context(format!("Invalid --header value for '{}'", name))?;
+        value.set_sensitive(true);
+        map.append(name, value);
+    }
+    Ok(map)
+}
+
+/// Ask for the pool, RPC and window on the terminal when --pool was left out
+fn prompt_missing_args(args: &mut Args) -> Result<()> {
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!("--pool is required (--interactive only prompts when stdin is a terminal)");
+    }
+
+    let pool = prompt::ask("Pool address", None, |answer| {
+        answer.parse::<Address>().context("Not a valid address")
+    })?;
+    args.pool = Some(format!("{:?}", pool));
+    args.rpc = prompt::ask("RPC URL", Some(&args.rpc), |answer| {
+        reqwest::Url::parse(answer).context("Not a valid URL")?;
+        Ok(answer.to_string())
+    })?;
+    args.days = prompt::ask("Days", Some(&args.days.to_string()), |answer| {
+        match answer.parse::<u64>() {
+            Ok(days) if days > 0 => Ok(days),
+            _ => anyhow::bail!("Enter a whole number of days, at least 1"),
+        }
+    })?;
+    eprintln!();
+    Ok(())
+}
+
+/// Parse a decimal integer argument into a U256
+fn parse_u256(value: &str) -> Result<U256, String> {
+    U256::from_dec_str(value).map_err(|e| format!("not a non-negative integer: {}", e))
+}
+
+/// Parse a `MIN,MAX` price range, with MIN below MAX
+fn parse_price_range(value: &str) -> Result<(f64, f64), String> {
+    let (min, max) = value.split_once(',').ok_or_else(|| "expected MIN,MAX".to_string())?;
+    let parse = |bound: &str| match bound.trim().parse::<f64>() {
+        Ok(price) if price >= 0.0 && price.is_finite() => Ok(price),
+        _ => Err(format!("'{}' is not a non-negative price", bound.trim())),
+    };
+    let (min, max) = (parse(min)?, parse(max)?);
+    if min >= max {
+        return Err(format!("MIN {} must be below MAX {}", min, max));
+    }
+    Ok((min, max))
+}
+
+/// Parse an interval like `90s`, `15m`, `4h` or `1d` (bare numbers are seconds) into seconds
+fn parse_interval(value: &str) -> Result<u64, String> {
+    let (number, unit_seconds) = match value.char_indices().last() {
+        Some((i, 's')) => (&value[..i], 1),
+        Some((i, 'm')) => (&value[..i], 60),
+        Some((i, 'h')) => (&value[..i], 3600),
+        Some((i, 'd')) => (&value[..i], SECONDS_PER_DAY),
+        _ => (value, 1),
+    };
+    match number.parse::<u64>() {
+        Ok(count) if count > 0 => Ok(count * unit_seconds),
+        _ => Err("expected a positive interval like 15m, 1h or 1d".to_string()),
+    }
+}
+
+/// Replace a `-` given for --end-block or --end-date with the first line of stdin, so a pipeline
+/// can hand over the value another tool printed
+fn read_piped_args(args: &mut Args) -> Result<()> {
+    for (flag, value) in [("--end-block", &mut args.end_block), ("--end-date", &mut args.end_date)] {
+        if value.as_deref() != Some("-") {
+            continue;
+        }
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line).context(format!("Failed to read {} from stdin", flag))?;
+        let line = line.trim();
+        if line.is_empty() {
+            anyhow::bail!("{} - expects a value on stdin, but stdin was empty", flag);
+        }
+        *value = Some(line.to_string());
+    }
+    Ok(())
+}
+
+/// Write every argument's effective value, given or defaulted, as TOML keyed by its long flag
+/// name. Values piped in with `-` are written as they were read from stdin.
+fn export_config(args: &Args, path: &str) -> Result<()> {
+    use clap::{ArgAction, CommandFactory};
+
+    // Re-read the command line for the values as given; Args has no way back to them
+    let command = Args::command();
+    let matches = command.clone().get_matches_from(std::env::args_os());
+    let mut table = toml::Table::new();
+    for arg in command.get_arguments() {
+        let (Some(name), Some(raw)) = (arg.get_long(), matches.get_raw(arg.get_id().as_str())) else {
+            continue;
+        };
+        let values: Vec<toml::Value> = raw.map(|value| config_value(&value.to_string_lossy(), arg.get_action())).collect();
+        let value = match arg.get_action() {
+            ArgAction::Append => toml::Value::Array(values),
+            _ => match values.into_iter().next() {
+                Some(value) => value,
+                None => continue,
+            },
+        };
+        table.insert(name.to_string(), value);
+    }
+    for (name, value) in [("end-block", &args.end_block), ("end-date", &args.end_date)] {
+        if let Some(value) = value {
+            table.insert(name.to_string(), toml::Value::String(value.clone()));
+        }
+    }
+
+    let contents = toml::to_string(&table).context("Failed to serialize the settings")?;
+    std::fs::write(path, contents).context(format!("Failed to write settings to {}", path))
+}
+
+/// A setting as a TOML value: booleans for switches, numbers where the value is one, otherwise a string
+fn config_value(raw: &str, action: &clap::ArgAction) -> toml::Value {
+    match action {
+        clap::ArgAction::SetTrue | clap::ArgAction::SetFalse => toml::Value::Boolean(raw == "true"),
+        _ => match (raw.parse::<i64>(), raw.parse::<f64>()) {
+            (Ok(integer), _) => toml::Value::Integer(integer),
+            (_, Ok(float)) if float.is_finite() => toml::Value::Float(float),
+            _ => toml::Value::String(raw.to_string()),
+        },
+    }
+}
+
+/// Whether the sampling mode chooses its own blocks rather than spacing them at a fixed step
+fn picks_own_blocks(args: &Args) -> bool {
+    args.daily_boundaries || args.sample_at_open.is_some() || args.block_hashes.is_some()
+        || args.samples_from_events || args.swaps_per_sample.is_some()
+}
+
+/// Under --validate, parse what the run would parse (addresses, the end date) and print the
+/// resolved configuration, without connecting to the RPC
+fn run_validate(args: &Args) -> Result<ExitCode> {
+    let (mode, target) = if let Some(pool) = args.pool.as_deref().or(args.wrapper.as_deref()) {
+        let address: Address = pool.parse().context("Invalid pool address")?;
+        (if args.pool.is_some() { "pool" } else { "wrapper" }, report::checksum(&address))
+    } else if let Some(cross) = &args.cross {
+        let (pool_a, pool_b) = parse_cross(cross)?;
+        ("cross", format!("{},{}", report::checksum(&pool_a), report::checksum(&pool_b)))
+    } else if let Some(pools) = &args.compare_pools {
+        let addresses = parse_compare_pools(pools)?;
+        ("compare_pools", addresses.iter().map(report::checksum).collect::<Vec<_>>().join(","))
+    } else if let Some(spec) = &args.index {
+        parse_index_spec(spec)?;
+        ("index", spec.clone())
+    } else if let Some(sequence) = &args.pool_sequence {
+        parse_pool_sequence(sequence)?;
+        ("pool_sequence", sequence.clone())
+    } else if let Some(token) = &args.list_pools {
+        let token: Address = token.parse().context("Invalid token address")?;
+        args.factory.parse::<Address>().context("Invalid factory address")?;
+        ("list_pools", report::checksum(&token))
+    } else {
+        anyhow::bail!("--pool is required");
+    };
+
+    let end = match (&args.end_block, &args.end_date) {
+        (Some(block), _) => format!("block {}", block),
+        (None, Some(date)) => format!("{} (midnight US Central, timestamp {})", date, parse_central_midnight(date)?),
+        (None, None) => format!("the {} block", value_name(args.end)),
+    };
+    let interval = (!picks_own_blocks(args)).then(|| (args.days * SECONDS_PER_DAY / args.samples, blocks_per_interval(args)));
+
+    let config = report::ResolvedConfig {
+        mode,
+        target,
+        rpc: endpoint_label(&args.rpc),
+        days: args.days,
+        samples: args.samples,
+        warmup_samples: args.warmup_samples,
+        interval_seconds: interval.map(|(seconds, _)| seconds),
+        interval_blocks: interval.map(|(_, blocks)| blocks),
+        end,
+        weighting: args.weight_by,
+        output_timezone: args.output_timezone.to_string(),
+        format: value_name(args.format),
+    };
+    match args.format {
+        OutputFormat::Text => config.print_text(),
+        OutputFormat::Json => println!("{}", to_json(&config, args)?),
+        OutputFormat::Prometheus | OutputFormat::Markdown | OutputFormat::Csv => anyhow::bail!("--validate only supports --format text and json"),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// A value-enum argument as it's spelled on the command line
+fn value_name(value: impl ValueEnum) -> String {
+    value.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default()
+}
+
+/// Reject argument combinations that can't produce a meaningful run, before any RPC calls
+fn validate_args(args: &Args) -> Result<()> {
+    // Also guards the division by --samples when spacing the sample blocks
+    if args.samples < 2 {
+        anyhow::bail!("--samples must be at least 2, since a time-weighted average needs at least one interval between samples");
+    }
+
+    if args.samples + args.warmup_samples > args.max_samples {
+        // One block read and one price read per sample
+        let rpc_calls = (args.samples + args.warmup_samples).saturating_mul(2);
+        anyhow::bail!(
+            "--samples {} (plus {} warmup) exceeds the cap of {} (about {} RPC calls). Pass --max-samples to raise it",
+            args.samples, args.warmup_samples, args.max_samples, rpc_calls
+        );
+    }
+
+    if args.max_timestamp_error.is_some() && args.spacing != Spacing::Log {
+        anyhow::bail!("--max-timestamp-error corrects estimated sample blocks, so it needs --spacing log; evenly spaced samples are already found from their timestamps");
+    }
+
+    // Pinned block hashes define their own window, so --days is only a label there
+    if args.block_hashes.is_none() {
+        if args.days == 0 {
+            anyhow::bail!("--days must be at least 1; a zero-day window puts every sample at the same block");
+        }
+        if blocks_per_interval(args) == 0 && !args.daily_boundaries && args.sample_at_open.is_none() {
+            anyhow::bail!(
+                "--samples {} over {} days is less than one block apart, so samples would repeat blocks. Use fewer samples or more days",
+                args.samples, args.days
+            );
+        }
+    }
+
+    if args.days > args.max_window_days {
+        anyhow::bail!(
+            "--days {} exceeds the cap of {} days. Pass --max-window-days to raise it (this needs an archive RPC)",
+            args.days, args.max_window_days
+        );
+    }
+
+    if let Some(tick) = args.round_to {
+        if !(tick > 0.0 && tick.is_finite()) {
+            anyhow::bail!("--round-to must be a positive tick size");
+        }
+    }
+
+    if let Some(amount) = args.ref_amount {
+        if !(amount > 0.0 && amount.is_finite()) {
+            anyhow::bail!("--ref-amount must be a positive amount of token0");
+        }
+    }
+
+    if args.exclude_suspected && args.jump_threshold.is_none() && args.head_room.is_none() {
+        anyhow::bail!("--exclude-suspected needs --jump-threshold or --head-room to flag samples");
+    }
+
+    if let Some(block) = &args.end_block {
+        block.parse::<u64>().map_err(|_| anyhow::anyhow!("--end-block must be a block number, not '{}'", block))?;
+    }
+
+    if !args.metrics.is_empty() && !matches!(args.format, OutputFormat::Text | OutputFormat::Json) {
+        anyhow::bail!("--metrics only applies to --format text and json");
+    }
+
+    if args.format == OutputFormat::Csv && args.streaming_aggregate {
+        anyhow::bail!("--format csv prints the samples, which --streaming-aggregate doesn't keep");
+    }
+
+    if args.min_reserve_treat_as_gap.is_some() && args.weight_by == analytics::Weighting::Blocks {
+        anyhow::bail!("--min-reserve-treat-as-gap leaves out time, so it needs --weight-by time");
+    }
+
+    if args.twap_of_twaps.is_some() && args.weight_by == analytics::Weighting::Blocks {
+        anyhow::bail!("--twap-of-twaps weights its sub-periods by time, so it needs --weight-by time");
+    }
+
+    if session_description(args).is_some() && args.weight_by == analytics::Weighting::Blocks {
+        anyhow::bail!("--weekday-filter and --time-of-day-filter average over wall-clock time, so they need --weight-by time");
+    }
+
+    if let Some(threshold) = args.adaptive {
+        if !(threshold > 0.0 && threshold.is_finite()) {
+            anyhow::bail!("--adaptive must be a positive percentage");
+        }
+    }
+
+    if args.swaps_per_sample == Some(0) {
+        anyhow::bail!("--swaps-per-sample must be at least 1");
+    }
+
+    if let Some(price) = args.reference_price {
+        if !(price > 0.0 && price.is_finite()) {
+            anyhow::bail!("--reference-price must be a positive price");
+        }
+    }
+
+    if let Some(window) = args.smooth {
+        if window < 3 || window % 2 == 0 {
+            anyhow::bail!("--smooth must be an odd number of samples, at least 3, so the window centers on each sample");
+        }
+    }
+
+    if let Some(threshold) = args.min_reserve_treat_as_gap {
+        if !(threshold > 0.0 && threshold.is_finite()) {
+            anyhow::bail!("--min-reserve-treat-as-gap must be a positive amount of tokens");
+        }
+    }
+
+    if args.price_precision_auto.is_some_and(|digits| !(1..=17).contains(&digits)) {
+        anyhow::bail!("--price-precision-auto must be between 1 and 17 significant digits");
+    }
+
+    if let Some(threshold_pct) = args.max_price_change_per_block {
+        if !(threshold_pct > 0.0 && threshold_pct.is_finite()) {
+            anyhow::bail!("--max-price-change-per-block must be a positive percentage");
+        }
+    }
+
+    if let Some(threshold_pct) = args.spot_divergence_check {
+        if !(threshold_pct > 0.0 && threshold_pct.is_finite()) {
+            anyhow::bail!("--spot-divergence-check must be a positive percentage");
+        }
+    }
+
+    if let Some(threshold) = args.pair_inverse_threshold {
+        if !(threshold > 0.0 && threshold.is_finite()) {
+            anyhow::bail!("--pair-inverse-threshold must be a positive price");
+        }
+    }
+
+    if let Some(target_pct) = args.manipulation_cost {
+        if !(target_pct > 0.0 && target_pct.is_finite()) {
+            anyhow::bail!("--manipulation-cost must be a positive percentage");
+        }
+    }
+
+    if args.impact_curve.iter().any(|&size| !(size > 0.0 && size.is_finite())) {
+        anyhow::bail!("--impact-curve sizes must be positive amounts of token0");
+    }
+
+    if let Some(expected) = args.assert_twap {
+        if !(expected > 0.0 && expected.is_finite()) {
+            anyhow::bail!("--assert-twap must be a positive price");
+        }
+        if !(args.assert_tolerance >= 0.0 && args.assert_tolerance.is_finite()) {
+            anyhow::bail!("--assert-tolerance must be a non-negative percent");
+        }
+    }
+
+    if let Some(block_time) = args.block_time {
+        if !(block_time > 0.0 && block_time.is_finite()) {
+            anyhow::bail!("--block-time must be a positive number of seconds");
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the pool's gauge and whether it's alive. Lookup failures only warn, since forks and
+/// non-Aerodrome pools have no Voter to ask.
+async fn show_gauge(args: &Args, provider: &Arc<Provider<Http>>, pool_address: Address) -> Result<()> {
+    let voter_address: Address = args.voter.parse().context("Invalid voter address")?;
+    let voter = AerodromeVoter::new(voter_address, provider.clone());
+
+    match voter.gauges(pool_address).call().await {
+        Ok(gauge) if gauge.is_zero() => status!("🏛️  Gauge: none (pool is not gauged, so it earns no emissions)"),
+        Ok(gauge) => match voter.is_alive(gauge).call().await {
+            Ok(true) => status!("🏛️  Gauge: {} (alive)", report::checksum(&gauge)),
+            Ok(false) => status!("🏛️  Gauge: {} (killed, no longer earns emissions)", report::checksum(&gauge)),
+            Err(_) => status!("🏛️  Gauge: {}", report::checksum(&gauge)),
+        },
+        Err(e) => status!("⚠️  Couldn't look up the gauge on Voter {}: {}", report::checksum(&voter_address), e),
+    }
+    status!();
+    Ok(())
+}
+
+/// Relative difference between a reserve and the balance it should track
+fn relative_gap(reserve: u128, balance: U256) -> f64 {
+    let balance = pricing::u256_to_f64(balance);
+    (reserve as f64 - balance).abs() / balance.max(1.0)
+}
+
+/// Warn if getReserves() looks swapped relative to token0/token1: each reserve should be close to
+/// the pool's balance of the matching token, and two stablecoins should price near 1
+async fn check_reserve_order(provider: &Arc<Provider<Http>>, pool: &PoolInfo) {
+    let reserves = AerodromePool::new(pool.address, provider.clone()).get_reserves().call().await;
+    let balance0 = ERC20::new(pool.token0.address, provider.clone()).balance_of(pool.address).call().await;
+    let balance1 = ERC20::new(pool.token1.address, provider.clone()).balance_of(pool.address).call().await;
+    let (Ok((reserve0, reserve1, _)), Ok(balance0), Ok(balance1)) = (reserves, balance0, balance1) else {
+        status!("ℹ️  Couldn't read reserves and balances to check the reserve order");
+        status!();
+        return;
+    };
+
+    let in_order = relative_gap(reserve0, balance0) + relative_gap(reserve1, balance1);
+    let swapped = relative_gap(reserve0, balance1) + relative_gap(reserve1, balance0);
+    if swapped < in_order && in_order > 0.1 {
+        status!("⚠️  WARNING: reserve0 matches the pool's {} balance and reserve1 its {} balance", pool.token1.symbol, pool.token0.symbol);
+        status!("   The reserves look swapped relative to token0/token1; check the --token0/--token1 order");
+    } else {
+        status!("✅ Reserve order matches the pool's token balances");
+    }
+
+    let is_stable = |symbol: &str| STABLE_SYMBOLS.contains(&symbol.to_uppercase().as_str());
+    if is_stable(&pool.token0.symbol) && is_stable(&pool.token1.symbol) {
+        if let Ok(Some(price)) = pricing::reserves_to_price(reserve0, reserve1, pool.token0.decimals, pool.token1.decimals) {
+            if !(0.5..=2.0).contains(&price) {
+                status!("⚠️  WARNING: {}/{} are both stablecoins but price at {:.8}; check the token order and decimals",
+                    pool.token0.symbol, pool.token1.symbol, price);
+            }
+        }
+    }
+    status!();
+}
+
+/// Decimals Aerodrome (and most Uniswap V2-style) LP tokens use
+const LP_TOKEN_DECIMALS: u8 = 18;
+
+/// Compare the pool's own decimals() with what an LP token should report. Prices are always
+/// scaled by the token decimals; this only flags pools where the getter could be mistaken for them.
+async fn check_pool_decimals(provider: &Arc<Provider<Http>>, pool: &PoolInfo) {
+    match AerodromePool::new(pool.address, provider.clone()).decimals().call().await {
+        Ok(decimals) if decimals == LP_TOKEN_DECIMALS => {
+            status!("✅ Pool decimals() is {}, as expected for an LP token", decimals);
+        }
+        Ok(decimals) => {
+            status!("⚠️  WARNING: pool decimals() is {}, not the usual {} for an LP token", decimals, LP_TOKEN_DECIMALS);
+            if decimals == pool.token0.decimals || decimals == pool.token1.decimals {
+                status!("   It matches a token's decimals, so it may be a wrapper or a non-standard pool");
+            }
+            status!("   Prices are scaled by the token decimals ({} / {}), never the pool's", pool.token0.decimals, pool.token1.decimals);
+        }
+        Err(_) => status!("ℹ️  Pool has no decimals() getter; prices are scaled by the token decimals"),
+    }
+    status!();
+}
+
+/// Determine the end block (from end_block, end_date or the requested block tag)
+async fn resolve_end_block(args: &Args, provider: &Arc<Provider<Http>>) -> Result<U64> {
+    if let Some(block) = &args.end_block {
+        let block: u64 = block.parse().context(format!("Invalid --end-block '{}'", block))?;
+        let latest = provider.get_block_number().await.map_err(|e| TwapError::RpcUnavailable(e.to_string()))?;
+        if block > latest.as_u64() {
+            anyhow::bail!("--end-block {} is past the chain head at block {}", block, latest);
+        }
+        status!("🧱 End block: {}", block);
+        Ok(U64::from(block))
+    } else if let Some(date_str) = &args.end_date {
+        let timestamp = parse_central_midnight(date_str)?;
+
+        status!("📅 End date: {} (midnight US Central = timestamp {})", date_str, timestamp);
+
+        // Find the block at this timestamp
+        find_block_at_timestamp(provider.clone(), timestamp, args.block_tolerance).await
+    } else {
+        resolve_end_tag(provider, args.end).await
+    }
+}
+
+/// The end block recorded in an existing --resume checkpoint for this pool, so a rerun against
+/// a moving tag like `latest` plans the same blocks. An explicit --end-date or --end-block is always re-resolved.
+fn resumed_end_block(args: &Args, pool_address: Address) -> Result<Option<U64>> {
+    let Some(path) = &args.resume else {
+        return Ok(None);
+    };
+    if args.end_date.is_some() || args.end_block.is_some() {
+        return Ok(None);
+    }
+    Ok(checkpoint::read_header(path)?
+        .filter(|header| header.pool == pool_address)
+        .map(|header| {
+            status!("♻️  Resuming the window ending at block {} from {}", header.end_block, path);
+            U64::from(header.end_block)
+        }))
+}
+
+/// Estimated number of blocks between consecutive samples
+fn blocks_per_interval(args: &Args) -> u64 {
+    // Calculate time period
+    let total_seconds = args.days * SECONDS_PER_DAY;
+    let interval_seconds = total_seconds / args.samples;
+
+    // Base has ~2 second block time on average
+    let blocks_per_second = args.block_time.map_or(0.5f64, |block_time| 1.0 / block_time);
+    (interval_seconds as f64 * blocks_per_second) as u64
+}
+
+/// Sample blocks spaced logarithmically: the k-th sample from the end sits total^(k/samples) blocks
+/// before `end_block`, where total is the window's length in blocks, and the last is `end_block`
+/// itself. Dense near the end, sparse toward the start, which begins at the same block as the
+/// even plan.
+fn plan_log_sample_blocks(end_block: U64, samples: u64, blocks_per_interval: u64) -> Vec<U64> {
+    let total_blocks = (samples * blocks_per_interval) as f64;
+    let mut blocks: Vec<U64> = (0..=samples)
+        .map(|i| {
+            if i == samples {
+                return end_block;
+            }
+            let blocks_back = total_blocks.powf((samples - i) as f64 / samples as f64).round() as u64;
+            if blocks_back >= end_block.as_u64() {
+                U64::from(1) // Genesis block if we go too far back
+            } else {
+                end_block - blocks_back
+            }
+        })
+        .collect();
+    // The densest samples can round to the same block
+    blocks.dedup();
+    blocks
+}
+
+/// Sample blocks for the window, spaced as --spacing asks. Even spacing finds the block at each
+/// sample's time, except under --sample-jitter, which offsets the samples by blocks anyway.
+async fn plan_spaced_blocks(args: &Args, provider: &Arc<Provider<Http>>, window: &Window, samples: u64) -> Result<Vec<U64>> {
+    Ok(match args.spacing {
+        Spacing::Linear if args.sample_jitter.is_none() => timestamp_sample_blocks(args, provider, window, samples).await?,
+        Spacing::Linear => plan_sample_blocks(window.end_block, samples, window.blocks_per_interval),
+        Spacing::Log => plan_log_sample_blocks(window.end_block, samples, window.blocks_per_interval),
+    })
+}
+
+/// Shift each block by a random offset in [-max_offset, max_offset], keeping the plan ordered
+/// and inside [1, end_block]. Blocks that land on the same block are merged. Returns the mean
+/// absolute offset applied.
+fn jitter_sample_blocks(blocks: &mut Vec<U64>, max_offset: u64, end_block: U64, seed: Option<u64>) -> f64 {
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+
+    let mut total_offset = 0u64;
+    for block in blocks.iter_mut() {
+        let offset = rng.gen_range(-(max_offset as i64)..=max_offset as i64);
+        let jittered = block.as_u64().saturating_add_signed(offset).clamp(1, end_block.as_u64());
+        total_offset += jittered.abs_diff(block.as_u64());
+        *block = U64::from(jittered);
+    }
+    let mean_offset = total_offset as f64 / blocks.len().max(1) as f64;
+    blocks.sort();
+    blocks.dedup();
+    mean_offset
+}
+
+/// The block at each 00:00 UTC in the window, oldest first
+async fn daily_sample_blocks(args: &Args, provider: &Arc<Provider<Http>>, end_block: U64) -> Result<Vec<U64>> {
+    let end_timestamp = read_block_timestamp(provider, end_block)
+        .await
+        .context(format!("Failed to read end block {}", end_block))?;
+    let last_midnight = end_timestamp - end_timestamp % SECONDS_PER_DAY;
+
+    status!("🌙 Sampling at 00:00 UTC for {} days ending {}", args.days, format_timestamp(last_midnight));
+
+    let mut blocks = Vec::new();
+    for days_back in (0..args.days).rev() {
+        let midnight = last_midnight - days_back * SECONDS_PER_DAY;
+        blocks.push(find_block_at_timestamp(provider.clone(), midnight, args.block_tolerance).await?);
+    }
+
+    status!();
+    Ok(blocks)
+}
+
+/// Open of every period of `period` seconds in [from_timestamp, to_timestamp]. Periods are laid out
+/// from each local midnight in `timezone`; whole-day periods step by calendar day, so a DST change
+/// shifts neither.
+fn period_starts(timezone: Tz, period: u64, from_timestamp: u64, to_timestamp: u64) -> Vec<u64> {
+    let local_midnight = |date: NaiveDate| {
+        timezone
+            .from_local_datetime(&date.and_hms_opt(0, 0, 0).unwrap_or_default())
+            .earliest()
+            .map_or(0, |dt| dt.timestamp().max(0) as u64)
+    };
+    let Some(first) = chrono::DateTime::from_timestamp(from_timestamp as i64, 0) else {
+        return Vec::new();
+    };
+    let mut date = first.with_timezone(&timezone).date_naive();
+    let day_step = (period / SECONDS_PER_DAY).max(1);
+
+    let in_window = |start: &u64| (from_timestamp..=to_timestamp).contains(start);
+    let mut starts = Vec::new();
+    loop {
+        let midnight = local_midnight(date);
+        if midnight > to_timestamp {
+            return starts;
+        }
+        if period >= SECONDS_PER_DAY {
+            starts.extend(Some(midnight).filter(in_window));
+        } else {
+            let next_midnight = local_midnight(date + chrono::Days::new(1));
+            starts.extend((midnight..next_midnight).step_by(period as usize).filter(in_window));
+        }
+        date = date + chrono::Days::new(day_step);
+    }
+}
+
+/// The first block on or after the start of each period in the window
+async fn period_open_blocks(args: &Args, provider: &Arc<Provider<Http>>, end_block: U64, period: u64) -> Result<Vec<U64>> {
+    let end_timestamp = read_block_timestamp(provider, end_block)
+        .await
+        .context(format!("Failed to read end block {}", end_block))?;
+    let window_start = end_timestamp.saturating_sub(args.days * SECONDS_PER_DAY);
+    let starts = period_starts(args.output_timezone, period, window_start, end_timestamp);
+
+    if starts.len() as u64 > args.max_samples {
+        anyhow::bail!(
+            "--sample-at-open gives {} periods, more than --max-samples {}. Use a longer period or raise the cap",
+            starts.len(), args.max_samples
+        );
+    }
+    status!("🔔 Sampling at the open of {} periods of {}s ({})", starts.len(), period, args.output_timezone);
+
+    let mut blocks: Vec<U64> = Vec::new();
+    for start in starts {
+        // The search lands at or before the open; the block after it is the first one inside the period
+        let mut block = find_block_at_timestamp(provider.clone(), start, 0).await?;
+        if read_block_timestamp(provider, block).await? < start {
+            block += U64::one();
+        }
+        if block <= end_block && blocks.last() != Some(&block) {
+            blocks.push(block);
+        }
+    }
+
+    status!();
+    Ok(blocks)
+}
+
+/// The block at or before each of `sampled` evenly spaced intervals' boundaries, from the
+/// window's start up to its end block, spread over the window as clamped to the pool's creation.
+/// Times that land in the same block are merged.
+async fn timestamp_sample_blocks(args: &Args, provider: &Arc<Provider<Http>>, window: &Window, sampled: u64) -> Result<Vec<U64>> {
+    let retry = retry::RetryPolicy::new(args.retries, args.seed);
+    let end_timestamp = retry.run(|| read_block_timestamp(provider, window.end_block)).await?;
+    let interval = if window.clamped {
+        let start_timestamp = retry.run(|| read_block_timestamp(provider, window.start_block)).await?;
+        end_timestamp.saturating_sub(start_timestamp) / sampled.max(1)
+    } else {
+        args.days * SECONDS_PER_DAY / args.samples
+    };
+
+    status!("🕰️  Finding the block at each of {} sample times, {} apart...", sampled + 1, format_duration(interval));
+    let end = (window.end_block, end_timestamp);
+    let found = plan_timestamp_blocks(provider, &retry, end, sampled, interval, args.block_time.unwrap_or(2.0)).await?;
+    let blocks: Vec<U64> = found.into_iter().map(|(block, _)| block).collect();
+    if (blocks.len() as u64) < sampled + 1 {
+        status!("   {} sample times fell in the same block as another and were merged", sampled + 1 - blocks.len() as u64);
+    }
+    status!();
+    Ok(blocks)
+}
+
+/// Replace the evenly spaced plan with the blocks where the pool actually swapped,
+/// keeping the plan if the window has no swaps. Also returns each swap block's price from its
+/// swaps when --price-source asks for one.
+async fn swap_sample_blocks(
+    args: &Args,
+    provider: &Provider<Http>,
+    pool: &PoolInfo,
+    planned: &[U64],
+    end_block: U64,
+) -> Result<(Vec<U64>, HashMap<u64, f64>)> {
+    let start_block = planned.first().copied().unwrap_or(end_block);
+    status!("🔎 Scanning Swap events in blocks {}-{}...", start_block, end_block);
+
+    let logs = events::fetch_swap_logs(provider, pool.address, start_block, end_block, args.log_chunk_blocks).await?;
+    let blocks = events::log_blocks(&logs);
+
+    if blocks.is_empty() {
+        status!("⚠️  No Swap events found, falling back to uniform sampling");
+        status!();
+        return Ok((planned.to_vec(), HashMap::new()));
+    }
+
+    if blocks.len() as u64 > args.max_samples {
+        anyhow::bail!(
+            "Window has {} swap blocks, more than --max-samples {}. Narrow the window or raise the cap",
+            blocks.len(), args.max_samples
+        );
+    }
+
+    status!("✓ Found {} swaps in {} blocks", logs.len(), blocks.len());
+
+    let mut swap_prices = HashMap::new();
+    if args.price_source != PriceSource::Last {
+        for (block, mut prices) in events::swap_prices_by_block(&logs, pool.token0.decimals, pool.token1.decimals) {
+            let price = match args.price_source {
+                PriceSource::Median => {
+                    prices.sort_by(f64::total_cmp);
+                    let mid = prices.len() / 2;
+                    if prices.len() % 2 == 0 {
+                        (prices[mid - 1] + prices[mid]) / 2.0
+                    } else {
+                        prices[mid]
+                    }
+                }
+                _ => prices.iter().sum::<f64>() / prices.len() as f64,
+            };
+            swap_prices.insert(block, price);
+        }
+        status!("🧮 Priced {} of the blocks from their swaps (best-effort; the rest use post-block reserves)", swap_prices.len());
+    }
+    status!();
+    Ok((blocks, swap_prices))
+}
+
+/// Replace the plan with the blocks where the window's cumulative swap count reaches each multiple
+/// of `per_sample`, keeping the plan if the window has too few swaps
+async fn swap_count_sample_blocks(
+    args: &Args,
+    provider: &Provider<Http>,
+    pool_address: Address,
+    planned: &[U64],
+    end_block: U64,
+    per_sample: u64,
+) -> Result<Vec<U64>> {
+    let start_block = planned.first().copied().unwrap_or(end_block);
+    status!("🔎 Scanning Swap events in blocks {}-{} to sample every {} swaps...", start_block, end_block, per_sample);
+
+    let logs = events::fetch_swap_logs(provider, pool_address, start_block, end_block, args.log_chunk_blocks).await?;
+    let blocks = events::swap_count_blocks(&logs, per_sample);
+
+    if blocks.len() < 2 {
+        status!("⚠️  Only {} swaps in the window, too few for a sample every {}; falling back to uniform sampling", logs.len(), per_sample);
+        status!();
+        return Ok(planned.to_vec());
+    }
+
+    if blocks.len() as u64 > args.max_samples {
+        anyhow::bail!(
+            "Window has {} swaps, {} samples at one per {} swaps, more than --max-samples {}. Raise --swaps-per-sample or the cap",
+            logs.len(), blocks.len(), per_sample, args.max_samples
+        );
+    }
+
+    status!("✓ Found {} swaps, sampling at {} blocks", logs.len(), blocks.len());
+    status!();
+    Ok(blocks)
+}
+
+/// Build the report from a subgraph's hourly prices over the window, for RPCs without its history
+async fn run_subgraph_fallback(args: &Args, provider: &Provider<Http>, url: &str, pool: PoolInfo, end_block: U64) -> Result<ExitCode> {
+    let end_timestamp = read_block_timestamp(provider, end_block).await?;
+    let start_timestamp = end_timestamp.saturating_sub(args.days * SECONDS_PER_DAY);
+    status!("🛰️  Falling back to hourly prices from subgraph {}...", url);
+    let prices = subgraph::fetch_hourly_prices(url, pool.address, start_timestamp, end_timestamp).await?;
+    if prices.is_empty() {
+        return Err(TwapError::NoData.into());
+    }
+    status!("✓ Read {} hourly prices", prices.len());
+    status!();
+
+    // The subgraph has no block numbers, so they're estimated back from the end block
+    let block_time = args.block_time.unwrap_or(2.0);
+    let price_points = prices
+        .into_iter()
+        .map(|(timestamp, price)| PricePoint {
+            block: end_block.as_u64().saturating_sub((end_timestamp.saturating_sub(timestamp) as f64 / block_time) as u64),
+            timestamp,
+            price,
+            ..Default::default()
+        })
+        .collect();
+    let price_points = check_pool_health(args, price_points);
+
+    let mut report = report::TwapReport::new(pool.address, pool.token0, pool.token1, args.days, args.samples, price_points);
+    report.pool_name = pool.name;
+    report.set_weighting(args.weight_by);
+    report.end_block = Some(end_block.as_u64());
+    report.subgraph = Some(url.to_string());
+    print_results(args, report)
+}
+
+/// Whether the RPC still has the pool's state at a block, i.e. answers historical reads there
+async fn has_state_at(provider: &Provider<Http>, pool_address: Address, block: U64) -> Result<bool> {
+    match provider.get_balance(pool_address, Some(block.into())).await {
+        Ok(_) => Ok(true),
+        Err(e) => match error::historical_read(e, "state", block.as_u64()).downcast::<TwapError>() {
+            Ok(TwapError::ArchiveRequired(_)) => Ok(false),
+            Ok(other) => Err(other.into()),
+            Err(e) => Err(e),
+        },
+    }
+}
+
+/// Probe the RPC for state at the window's start before sampling, so a pruned node fails up
+/// front with the longest window it can serve instead of partway through collection
+async fn check_history_available(
+    args: &Args,
+    provider: &Provider<Http>,
+    pool_address: Address,
+    start_block: U64,
+    end_block: U64,
+) -> Result<()> {
+    if has_state_at(provider, pool_address, start_block).await? {
+        return Ok(());
+    }
+
+    // Binary search for the oldest block the node still has state for
+    status!("🔍 RPC has no state at block {}, probing how much history it keeps...", start_block);
+    let (mut low, mut high) = (start_block.as_u64(), end_block.as_u64());
+    while high - low > 1 {
+        let mid = low + (high - low) / 2;
+        if has_state_at(provider, pool_address, U64::from(mid)).await? {
+            high = mid;
+        } else {
+            low = mid;
+        }
+    }
+
+    let block_time = args.block_time.unwrap_or(2.0);
+    let available_days = ((end_block.as_u64() - high) as f64 * block_time / SECONDS_PER_DAY as f64).floor();
+    let suggestion = if available_days >= 1.0 {
+        format!("rerun with --days {} or less, or use an archive node", available_days)
+    } else {
+        "it keeps less than a day of state; use an archive node".to_string()
+    };
+    Err(anyhow::Error::from(TwapError::ArchiveRequired(start_block.as_u64())).context(format!(
+        "This RPC only has state back to block {} (about {} days before the end block); {}",
+        high, available_days, suggestion
+    )))
+}
+
+/// Check the end block's reserves were updated by a trade within `max_age` seconds of it, so the
+/// current price isn't a frozen one. Errors, or only warns under --stale-end-warn.
+async fn check_fresh_end(args: &Args, provider: &Arc<Provider<Http>>, pool_address: Address, end_block: U64, max_age: u64) -> Result<()> {
+    let (_, _, last_updated) = AerodromePool::new(pool_address, provider.clone())
+        .get_reserves()
+        .block(end_block)
+        .call()
+        .await
+        .map_err(|e| error::historical_read(e, "getReserves()", end_block.as_u64()))?;
+    let end_timestamp = read_block_timestamp(provider, end_block).await?;
+    let age = end_timestamp.saturating_sub(last_updated as u64);
+
+    if age <= max_age {
+        status!("✅ End reserves last updated {}s before block {}", age, end_block);
+        status!();
+        return Ok(());
+    }
+
+    let message = format!(
+        "The pool's reserves at end block {} were last updated {}s earlier ({}), more than --require-fresh-end {}s; the current price is stale",
+        end_block, age, format_timestamp(last_updated as u64), max_age
+    );
+    if !args.stale_end_warn {
+        anyhow::bail!(message);
+    }
+    status!("⚠️  WARNING: {}", message);
+    status!();
+    Ok(())
+}
+
+/// The block range a run samples and the spacing between its samples
+#[derive(Clone, Copy, Debug)]
+struct Window {
+    start_block: U64,
+    end_block: U64,
+    blocks_per_interval: u64,
+    /// Whether the requested --days reached back past `earliest_block` and was cut short
+    clamped: bool,
+    /// Days from the clamped start to the end, from their block timestamps
+    clamped_days: Option<f64>,
+}
+
+/// The window of `samples` intervals of `blocks_per_interval` blocks ending at `end_block`.
+///
+/// If that would start before `earliest_block` (pool creation, or genesis at block 1), the start
+/// is clamped there and the samples are spread evenly over the shorter window instead.
+fn resolve_window(end_block: U64, samples: u64, blocks_per_interval: u64, earliest_block: U64) -> Window {
+    let earliest_block = earliest_block.max(U64::one());
+    let requested_blocks = samples.saturating_mul(blocks_per_interval);
+    match end_block.as_u64().checked_sub(requested_blocks) {
+        Some(start) if start >= earliest_block.as_u64() => Window {
+            start_block: U64::from(start),
+            end_block,
+            blocks_per_interval,
+            clamped: false,
+            clamped_days: None,
+        },
+        _ => Window {
+            start_block: earliest_block.min(end_block),
+            end_block,
+            blocks_per_interval: (end_block.saturating_sub(earliest_block).as_u64() / samples.max(1)).max(1),
+            clamped: true,
+            clamped_days: None,
+        },
+    }
+}
+
+/// Clamp the window's start to the pool's creation block if it reaches back before it, erroring
+/// instead under --strict-window. Only searches for the creation block when the pool has no code
+/// at the requested start.
+async fn clamp_window_to_pool(args: &Args, provider: &Arc<Provider<Http>>, pool_address: Address, window: Window) -> Result<Window> {
+    let creation_block = match args.pool_created_block {
+        Some(block) => check_creation_block(provider, pool_address, block).await?,
+        None => {
+            let code = provider
+                .get_code(pool_address, Some(window.start_block.into()))
+                .await
+                .context(format!("Failed to get pool code at block {}", window.start_block))?;
+            if !code.is_empty() && !window.clamped {
+                return Ok(window);
+            }
+            find_pool_creation_block(provider, pool_address, window.end_block).await?
+        }
+    };
+    let sampled = args.samples + args.warmup_samples;
+    let mut clamped = resolve_window(window.end_block, sampled, blocks_per_interval(args), creation_block);
+    if !clamped.clamped {
+        status!();
+        return Ok(clamped);
+    }
+
+    let created_at = read_block_timestamp(provider, creation_block).await?;
+    let end_timestamp = read_block_timestamp(provider, window.end_block).await?;
+    let age_days = end_timestamp.saturating_sub(created_at) as f64 / SECONDS_PER_DAY as f64;
+    clamped.clamped_days = Some(age_days);
+    if args.strict_window {
+        anyhow::bail!(
+            "Requested {}-day window starts before the pool was created at block {} ({}); the pool is only {:.1} days old",
+            args.days, creation_block, format_timestamp(created_at), age_days
+        );
+    }
+    status!("⚠️  The {}-day window starts before the pool was created at block {} ({})", args.days, creation_block, format_timestamp(created_at));
+    status!("   Sampling blocks {}-{} instead, about {:.1} days, every {} blocks", clamped.start_block, clamped.end_block, age_days, clamped.blocks_per_interval);
+    status!();
+    Ok(clamped)
+}
+
+/// Confirm a --pool-created-block hint: getReserves has to answer at the block and revert just before it
+async fn check_creation_block(provider: &Arc<Provider<Http>>, pool_address: Address, block: u64) -> Result<U64> {
+    let pool = AerodromePool::new(pool_address, provider.clone());
+    pool.get_reserves()
+        .block(block)
+        .call()
+        .await
+        .map_err(|e| error::historical_read(e, "getReserves()", block))
+        .context(format!("--pool-created-block {} is wrong: the pool doesn't answer getReserves there yet", block))?;
+    if block > 0 && pool.get_reserves().block(block - 1).call().await.is_ok() {
+        anyhow::bail!("--pool-created-block {} is wrong: the pool already answers getReserves at block {}", block, block - 1);
+    }
+    status!("✅ Pool created at block {} (from --pool-created-block)", block);
+    Ok(U64::from(block))
+}
+
+/// Set up price reads for a pool according to the pricing flags
+async fn build_sampler(
+    args: &Args,
+    provider: &Arc<Provider<Http>>,
+    pool: &PoolInfo,
+    reserves_cache: &Arc<cache::ReservesCache>,
+) -> Result<sampler::Sampler> {
+    let mut sampler = sampler::Sampler::new(
+        provider.clone(),
+        pool.address,
+        &pool.token0,
+        &pool.token1,
+        args.concentrated,
+        args.balances_fallback,
+    )
+    .with_reserves_cache(reserves_cache.clone());
+    // Pools without stable() are priced with the constant-product formula
+    let stable = match args.pool_type {
+        PoolType::Volatile => false,
+        PoolType::Stable => true,
+        PoolType::Auto if args.concentrated || args.wrapper.is_some() => false,
+        PoolType::Auto => is_stable_pool(provider, pool.address).await,
+    };
+    if stable {
+        sampler = sampler.with_stable_curve();
+        status!("⚖️  {}/{} is a stable pool; pricing from the x³y + xy³ = k curve's marginal price", pool.token0.symbol, pool.token1.symbol);
+        status!();
+    }
+    if let Some(caller) = &args.from_address {
+        let caller: Address = caller.parse().context("Invalid --from-address")?;
+        sampler = sampler.with_caller(caller);
+        status!("📨 Reading prices as {}", report::checksum(&caller));
+        status!();
+    }
+    if args.wrapper.is_some() {
+        sampler = sampler.with_wrapper().await?;
+        status!("🎁 Pricing from the wrapper's getTotalAmounts()");
+        status!("   This is the ratio of the wrapper's holdings, which matches the market price only while its position is value-balanced");
+        status!();
+    }
+    if let Some(quoter) = &args.quoter {
+        let quoter_address: Address = quoter.parse().context("Invalid quoter address")?;
+        sampler = sampler.with_quoter(quoter_address, args.quote_amount)?;
+        status!("💱 Quoting {} {} on {}", args.quote_amount, pool.token0.symbol, report::checksum(&quoter_address));
+        status!();
+    }
+    Ok(sampler)
+}
+
+/// Run a collection, stopping it at `deadline` if there is one. Returns whether it finished;
+/// samples already passed to its sink are kept either way.
+async fn collect_until(deadline: Option<tokio::time::Instant>, collection: impl Future<Output = Result<()>>) -> Result<bool> {
+    let Some(deadline) = deadline else {
+        return collection.await.map(|_| true);
+    };
+
+    match tokio::time::timeout_at(deadline, collection).await {
+        Ok(result) => result.map(|_| true),
+        Err(_) => {
+            status!();
+            status!();
+            status!("⏰ --max-duration reached, stopping collection and computing a partial TWAP");
+            status!();
+            Ok(false)
+        }
+    }
+}
+
+/// Under --adaptive, repeatedly sample the midpoint of every interval whose price moved more than
+/// `threshold_pct`, biggest moves first, until no interval qualifies, the moving intervals are a
+/// single block wide, or the sample cap is reached. The TWAP weights each interval by its own
+/// length, so the uneven spacing this leaves doesn't bias it.
+async fn refine_adaptively(
+    args: &Args,
+    provider: &Provider<Http>,
+    sampler: &sampler::Sampler,
+    price_points: &mut Vec<PricePoint>,
+    threshold_pct: f64,
+) -> Result<()> {
+    let cap = args.adaptive_max_samples.unwrap_or(args.samples.saturating_mul(4)).min(args.max_samples) as usize;
+    let initial = price_points.len();
+
+    for round in 1.. {
+        let mut moving: Vec<(f64, u64)> = price_points
+            .windows(2)
+            .filter(|pair| pair[1].block - pair[0].block > 1)
+            .map(|pair| (((pair[1].price - pair[0].price) / pair[0].price).abs() * 100.0, (pair[0].block + pair[1].block) / 2))
+            .filter(|&(change_pct, _)| change_pct > threshold_pct)
+            .collect();
+        moving.sort_by(|a, b| b.0.total_cmp(&a.0));
+        moving.truncate(cap.saturating_sub(price_points.len()));
+        if moving.is_empty() {
+            break;
+        }
+
+        let mut midpoints: Vec<U64> = moving.iter().map(|&(_, block)| U64::from(block)).collect();
+        midpoints.sort();
+        status!("🪓 Adaptive round {}: bisecting {} intervals that moved more than {}%", round, midpoints.len(), threshold_pct);
+        let before = price_points.len();
+        collect_samples(args, provider, sampler, &midpoints, |point| price_points.push(point)).await?;
+        price_points.sort_by_key(|p| p.block);
+        if price_points.len() == before {
+            break;
+        }
+    }
+
+    status!("🪓 Adaptive sampling added {} samples to the initial {}", price_points.len() - initial, initial);
+    status!();
+    Ok(())
+}
+
+/// Read the pool's price at each planned block
+async fn collect_price_points(
+    args: &Args,
+    provider: &Provider<Http>,
+    sampler: &sampler::Sampler,
+    blocks: &[U64],
+) -> Result<Vec<PricePoint>> {
+    let mut price_points = Vec::new();
+    collect_samples(args, provider, sampler, blocks, |point| price_points.push(point)).await?;
+    Ok(price_points)
+}
+
+/// Redraw the collection progress line every 10 samples and at the last one
+fn print_collected(done: usize, total: usize) {
+    // Progress is only redrawn in place on an interactive text run
+    if (done.is_multiple_of(10) || done == total) && !STATUS_TO_STDERR.load(Ordering::Relaxed) && !QUIET.load(Ordering::Relaxed) {
+        print!("\r✓ Collected {}/{} samples", done, total);
+        use std::io::Write;
+        std::io::stdout().flush().unwrap();
+    }
+}
+
+/// Report the sampled blocks where the pool had no reserves, failing under --strict-reserves
+fn warn_empty_blocks(args: &Args, empty_blocks: &[U64]) -> Result<()> {
+    if let (Some(first), Some(last)) = (empty_blocks.first(), empty_blocks.last()) {
+        let message = format!(
+            "The pool had zero reserves at {} sampled blocks (first {}, last {}), leaving gaps in the series",
+            empty_blocks.len(), first, last
+        );
+        if args.strict_reserves {
+            anyhow::bail!("{}; rerun without --strict-reserves to skip them", message);
+        }
+        status!("⚠️  {}; those samples were skipped", message);
+        status!();
+    }
+    Ok(())
+}
+
+/// Whether a run samples the way the library's `compute_twap` does: evenly spaced sample times
+/// priced from reserves, with none of the planning, collection or pricing flags only the binary handles
+fn samples_like_library(args: &Args) -> bool {
+    args.block_hashes.is_none()
+        && !args.daily_boundaries
+        && args.sample_at_open.is_none()
+        && args.spacing == Spacing::Linear
+        && args.sample_jitter.is_none()
+        && args.warmup_samples == 0
+        && !args.probe
+        && !args.samples_from_events
+        && args.swaps_per_sample.is_none()
+        && !args.streaming_aggregate
+        && args.resume.is_none()
+        && args.adaptive.is_none()
+        && args.max_duration.is_none()
+        && args.max_timestamp_error.is_none()
+        && args.sample_retry_different_block.is_none()
+        && args.min_gap_seconds.is_none()
+        && args.head_room.is_none()
+        && args.max_price_change_per_block.is_none()
+        && !args.concentrated
+        && !args.balances_fallback
+        && args.from_address.is_none()
+        && args.wrapper.is_none()
+        && args.quoter.is_none()
+}
+
+/// Collect a plain run's samples with the library's `compute_twap`, returning them with every block it sampled
+async fn sample_with_library(
+    args: &Args,
+    provider: &Arc<Provider<Http>>,
+    pool: &PoolInfo,
+    window: &Window,
+    stable: bool,
+    reserves_cache: &Arc<cache::ReservesCache>,
+) -> Result<(Vec<PricePoint>, Vec<U64>)> {
+    let mut config = TwapConfig::new(pool.address);
+    config.days = args.days;
+    config.samples = args.samples;
+    config.end_block = Some(window.end_block);
+    config.start_block = window.clamped.then_some(window.start_block);
+    config.pool_info = Some(pool.clone());
+    config.stable = Some(stable);
+    config.retries = args.retries;
+    config.seed = args.seed;
+    config.concurrency = args.concurrency as usize;
+    config.skip_failed = args.on_error == OnError::Skip;
+    config.seconds_per_block = args.block_time.unwrap_or(2.0);
+    config.reserves_cache = Some(reserves_cache.clone());
+    config.progress = Some(print_progress);
+    let result = aerodrome_twap::compute_twap(provider.clone(), &config).await?;
+
+    status!();
+    status!();
+    if !result.failed_blocks.is_empty() {
+        status!("⚠️  Skipped {} samples that failed to load", result.failed_blocks.len());
+        status!();
+    }
+    let empty_blocks: Vec<U64> = result.empty_blocks.iter().copied().map(U64::from).collect();
+    warn_empty_blocks(args, &empty_blocks)?;
+
+    let mut blocks: Vec<U64> = result.price_points.iter().map(|p| U64::from(p.block)).chain(empty_blocks).collect();
+    blocks.extend(result.failed_blocks.iter().copied().map(U64::from));
+    blocks.sort();
+    let mut price_points = result.price_points;
+    if args.record_endpoint {
+        let endpoint = endpoint_label(&args.rpc);
+        for point in &mut price_points {
+            point.endpoint = Some(endpoint.clone());
+        }
+    }
+    Ok((price_points, blocks))
+}
+
+/// Print `compute_twap`'s progress with the same lines as the binary's own planning and collection
+fn print_progress(progress: &TwapProgress) {
+    match progress {
+        TwapProgress::Planning { samples, interval_seconds } => {
+            status!("🕰️  Finding the block at each of {} sample times, {} apart...", samples, format_duration(*interval_seconds));
+        }
+        TwapProgress::Planned { merged, .. } => {
+            if *merged > 0 {
+                status!("   {} sample times fell in the same block as another and were merged", merged);
+            }
+            status!();
+            status!("⏱️  Collecting price data...");
+        }
+        TwapProgress::Sampled { done, total } => print_collected(*done, *total),
+        TwapProgress::Skipped { block, error } => {
+            status!();
+            status!("⚠️  Skipping block {}: {}", block, error);
+        }
+    }
+}
+
+/// Read the pool's price at each planned block, handing each sample to `sink` in block order
+async fn collect_samples(
+    args: &Args,
+    provider: &Provider<Http>,
+    sampler: &sampler::Sampler,
+    blocks: &[U64],
+    mut sink: impl FnMut(PricePoint),
+) -> Result<()> {
+    status!("⏱️  Collecting price data...");
+
+    let retry = retry::RetryPolicy::new(args.retries, args.seed);
+
+    let mut failed_samples = 0u64;
+    let mut near_max_reserves = 0u64;
+    let mut empty_blocks: Vec<U64> = Vec::new();
+    let endpoint = args.record_endpoint.then(|| endpoint_label(&args.rpc));
+    let mut last_price: Option<f64> = None;
+    let mut substituted_samples = 0u64;
+    let last_block = blocks.last().copied().unwrap_or_default();
+    // Intended times count back from the last planned block at the assumed block time, as the plan did
+    let anchor = match (args.max_timestamp_error, blocks.last()) {
+        (Some(_), Some(&block)) => Some((block, retry.run(|| read_block_timestamp(provider, block)).await?)),
+        _ => None,
+    };
+    let (mut corrected_samples, mut worst_error) = (0u64, 0u64);
+    let mut tripped_blocks: Vec<u64> = Vec::new();
+
+    let (planned, skipped_min_gap) = match args.min_gap_seconds {
+        Some(min_gap) => drop_close_blocks(args, &retry, provider, blocks, min_gap).await?,
+        None => (blocks.iter().map(|&block| (block, None)).collect(), 0),
+    };
+    let blocks: Vec<U64> = planned.iter().map(|&(block, _)| block).collect();
+
+    // Reads run --concurrency at a time; `buffered` hands them back in block order, since the checks
+    // below compare each sample with the one before it
+    let mut fetches = stream::iter(planned)
+        .map(|planned| fetch_sample(args, &retry, provider, sampler, planned, anchor, last_block))
+        .buffered(args.concurrency as usize)
+        .enumerate();
+
+    while let Some((i, fetched)) = fetches.next().await {
+        let Fetched { block: target_block, timestamp, sample, substitution, timestamp_error } = match fetched {
+            Ok(fetched) => fetched,
+            Err(e) => {
+                skip_or_abort(args.on_error, blocks[i], e)?;
+                failed_samples += 1;
+                continue;
+            }
+        };
+        if let Some(error) = timestamp_error {
+            worst_error = worst_error.max(error);
+            corrected_samples += 1;
+        }
+        if substitution.is_some() {
+            substituted_samples += 1;
+        }
+
+        let Some(sample) = sample else {
+            empty_blocks.push(target_block);
+            continue;
+        };
+        if let Some(bits) = args.head_room {
+            if near_uint112_max(&sample, bits) {
+                status!("⚠️  Block {}'s reserves ({:?} / {:?}) are within 2^{} of the uint112 maximum",
+                    target_block, sample.reserve0, sample.reserve1, bits);
+                near_max_reserves += 1;
+                if args.exclude_suspected {
+                    continue;
+                }
+            }
+        }
+
+        if let (Some(threshold), Some(previous)) = (args.max_price_change_per_block, last_price) {
+            if let Some(change_pct) = single_block_change(&retry, sampler, target_block, previous, sample.price, threshold).await {
+                status!();
+                status!("🚨 Block {}: the price moved {:+.2}% within the block, to {:.8}", target_block, change_pct, sample.price);
+                tripped_blocks.push(target_block.as_u64());
+                trip_circuit_breaker(args.circuit_breaker, target_block)?;
+            }
+        }
+
+        last_price = Some(sample.price);
+        sink(PricePoint {
+            block: target_block.as_u64(),
+            timestamp,
+            price: sample.price,
+            reserve0: sample.reserve0,
+            reserve1: sample.reserve1,
+            sqrt_price_x96: sample.sqrt_price_x96,
+            tick: sample.tick,
+            endpoint: endpoint.clone(),
+            substitution,
+        });
+
+        print_collected(i + 1, blocks.len());
+    }
+
+    status!();
+    status!();
+
+    if let (Some(threshold), false) = (args.max_price_change_per_block, tripped_blocks.is_empty()) {
+        let blocks: Vec<String> = tripped_blocks.iter().map(|block| block.to_string()).collect();
+        status!("🚨 {} samples moved more than {}% within their block: {}", tripped_blocks.len(), threshold, blocks.join(", "));
+        status!("   A one-block move that size is usually bad data or manipulation; check them before relying on the TWAP");
+        status!();
+    }
+
+    if let (Some(max_error), true) = (args.max_timestamp_error, corrected_samples > 0) {
+        status!("🎯 Searched for closer blocks for {} samples more than {}s from their intended time (worst {}s off)",
+            corrected_samples, max_error, worst_error);
+        status!();
+    }
+
+    if substituted_samples > 0 {
+        status!("🔁 Sampled an adjacent block for {} unreadable blocks; the report lists them", substituted_samples);
+        status!();
+    }
+
+    if failed_samples > 0 {
+        status!("⚠️  Skipped {} samples that failed to load", failed_samples);
+        status!();
+    }
+
+    warn_empty_blocks(args, &empty_blocks)?;
+
+    if near_max_reserves > 0 {
+        let action = if args.exclude_suspected { "excluded from the TWAP" } else { "kept; --exclude-suspected drops them" };
+        status!("🚨 {} samples had reserves near the uint112 maximum ({})", near_max_reserves, action);
+        status!();
+    }
+
+    if skipped_min_gap > 0 {
+        status!("⏭️  Skipped {} samples less than {}s after the previous one", skipped_min_gap, args.min_gap_seconds.unwrap_or_default());
+        status!();
+    }
+
+    Ok(())
+}
+
+/// Drop the planned blocks less than `min_gap` seconds after the last one kept, reading only their
+/// timestamps so skipped samples cost no price read. Returns the kept blocks with their
+/// timestamps (None where the read failed, left for the sample fetch to report) and how many
+/// were dropped.
+async fn drop_close_blocks(
+    args: &Args,
+    retry: &retry::RetryPolicy,
+    provider: &Provider<Http>,
+    blocks: &[U64],
+    min_gap: u64,
+) -> Result<(Vec<(U64, Option<u64>)>, u64)> {
+    let timestamps: Vec<Option<u64>> = stream::iter(blocks.iter().copied())
+        .map(|block| async move { retry.run(|| read_block_timestamp(provider, block)).await.ok() })
+        .buffered(args.concurrency as usize)
+        .collect()
+        .await;
+
+    let (mut kept, mut last_kept) = (Vec::with_capacity(blocks.len()), None);
+    for (&block, timestamp) in blocks.iter().zip(timestamps) {
+        if let (Some(timestamp), Some(previous)) = (timestamp, last_kept) {
+            if timestamp.saturating_sub(previous) < min_gap {
+                continue;
+            }
+        }
+        last_kept = timestamp.or(last_kept);
+        kept.push((block, timestamp));
+    }
+    let skipped = (blocks.len() - kept.len()) as u64;
+    Ok((kept, skipped))
+}
+
+/// Whether either reserve is within a factor of 2^bits of uint112's maximum, where the pool stores them
+fn near_uint112_max(sample: &sampler::Sample, bits: u32) -> bool {
+    let bound = (u128::MAX >> 16) >> bits;
+    [sample.reserve0, sample.reserve1].into_iter().flatten().any(|reserve| reserve > bound)
+}
+
+/// Price the window's first and last block only and report their mean as a rough TWAP
+async fn run_quick_estimate(
+    args: &Args,
+    provider: &Provider<Http>,
+    sampler: &sampler::Sampler,
+    pool: PoolInfo,
+    start_block: U64,
+    end_block: U64,
+) -> Result<ExitCode> {
+    status!("⚡ Reading prices at blocks {} and {} only...", start_block, end_block);
+    status!();
+
+    let mut points = Vec::new();
+    for block in [start_block, end_block] {
+        let sample = sampler
+            .read_price(block)
+            .await?
+            .context(format!("Pool had no reserves at block {}", block))?;
+        points.push(PricePoint {
+            block: block.as_u64(),
+            timestamp: read_block_timestamp(provider, block).await?,
+            price: sample.price,
+            reserve0: sample.reserve0,
+            reserve1: sample.reserve1,
+            sqrt_price_x96: sample.sqrt_price_x96,
+            tick: sample.tick,
+            endpoint: None,
+            substitution: None,
+        });
+    }
+    let end = points.pop().unwrap_or_default();
+    let start = points.pop().unwrap_or_default();
+
+    let estimate = report::QuickEstimate::new(pool.address, pool.token0, pool.token1, start, end);
+    match args.format {
+        OutputFormat::Text => estimate.print_text(),
+        OutputFormat::Json => println!("{}", to_json(&estimate, args)?),
+        OutputFormat::Prometheus | OutputFormat::Markdown | OutputFormat::Csv => anyhow::bail!("--reserves-at-end-only only supports --format text and json"),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The two pool addresses of a --cross spec
+fn parse_cross(cross: &str) -> Result<(Address, Address)> {
+    let (pool_a, pool_b) = cross
+        .split_once(',')
+        .context("--cross expects two pool addresses separated by a comma")?;
+    let pool_a: Address = pool_a.trim().parse().context("Invalid first --cross pool address")?;
+    let pool_b: Address = pool_b.trim().parse().context("Invalid second --cross pool address")?;
+    Ok((pool_a, pool_b))
+}
+
+/// Triangulate the price of the two non-shared tokens of two pools over the same window
+async fn run_cross(
+    args: &Args,
+    provider: Arc<Provider<Http>>,
+    token_cache: &mut metadata::TokenCache,
+    reserves_cache: &Arc<cache::ReservesCache>,
+    cross: &str,
+) -> Result<ExitCode> {
+    let (pool_a, pool_b) = parse_cross(cross)?;
+
+    let pool_a = load_pool(&provider, token_cache, pool_a, None, (None, None)).await?;
+    let pool_b = load_pool(&provider, token_cache, pool_b, None, (None, None)).await?;
+    let legs = analytics::CrossLegs::new(
+        (pool_a.token0.address, pool_a.token1.address),
+        (pool_b.token0.address, pool_b.token1.address),
+    )
+    .context("--cross pools must share exactly one token")?;
+
+    let token_info = |address: Address| {
+        [&pool_a.token0, &pool_a.token1, &pool_b.token0, &pool_b.token1]
+            .into_iter()
+            .find(|token| token.address == address)
+            .cloned()
+            .expect("token belongs to one of the pools")
+    };
+    let (base, quote, via) = (token_info(legs.base), token_info(legs.quote), token_info(legs.via));
+    status!("🔀 Pricing {} in {} via {}", base.symbol, quote.symbol, via.symbol);
+    status!();
+
+    let end_block = resolve_end_block(args, &provider).await?;
+    let window = resolve_window(end_block, args.samples, blocks_per_interval(args), U64::one());
+    let blocks = plan_spaced_blocks(args, &provider, &window, args.samples).await?;
+
+    // Both pools are sampled at the same blocks so their timestamps line up exactly
+    let points_a = collect_price_points(args, &provider, &build_sampler(args, &provider, &pool_a, reserves_cache).await?, &blocks).await?;
+    let points_b = collect_price_points(args, &provider, &build_sampler(args, &provider, &pool_b, reserves_cache).await?, &blocks).await?;
+
+    reserves_cache.save()?;
+
+    let price_points = legs.combine(&points_a, &points_b);
+    if price_points.is_empty() {
+        anyhow::bail!("No blocks where both pools had price data");
+    }
+
+    let price_points = check_pool_health(args, price_points);
+
+    let mut report = report::TwapReport::new(
+        Address::zero(),
+        base,
+        quote,
+        args.days,
+        args.samples,
+        price_points,
+    );
+    report.cross = Some(report::CrossInfo {
+        pool_a: pool_a.address,
+        pool_b: pool_b.address,
+        via,
+        pool_a_twap: analytics::weighted_twap(&points_a, args.weight_by),
+        pool_b_twap: analytics::weighted_twap(&points_b, args.weight_by),
+    });
+    report.set_weighting(args.weight_by);
+    report.end_block = Some(end_block.as_u64());
+
+    print_results(args, report)
+}
+
+/// Parse `POOL[:WEIGHT],...`, with equal weights where none are given, normalized to sum to 1
+fn parse_index_spec(spec: &str) -> Result<Vec<(Address, f64)>> {
+    let mut components = Vec::new();
+    for part in spec.split(',').map(str::trim) {
+        let (pool, weight) = match part.split_once(':') {
+            Some((pool, weight)) => {
+                let weight: f64 = weight.trim().parse().context(format!("Invalid --index weight '{}'", weight.trim()))?;
+                if !(weight > 0.0 && weight.is_finite()) {
+                    anyhow::bail!("--index weights must be positive");
+                }
+                (pool, weight)
+            }
+            None => (part, 1.0),
+        };
+        let pool: Address = pool.trim().parse().context(format!("Invalid --index pool address '{}'", pool.trim()))?;
+        components.push((pool, weight));
+    }
+    if components.len() < 2 {
+        anyhow::bail!("--index needs at least two pools separated by commas");
+    }
+
+    let total: f64 = components.iter().map(|&(_, weight)| weight).sum();
+    Ok(components.into_iter().map(|(pool, weight)| (pool, weight / total)).collect())
+}
+
+/// Sample a basket of pools over one window and report each pool's and the weighted index's
+/// performance relative to the window start
+async fn run_index(
+    args: &Args,
+    provider: Arc<Provider<Http>>,
+    token_cache: &mut metadata::TokenCache,
+    reserves_cache: &Arc<cache::ReservesCache>,
+    spec: &str,
+) -> Result<ExitCode> {
+    let weights = parse_index_spec(spec)?;
+
+    // Every pool is sampled at the same blocks so the normalized series line up
+    let end_block = resolve_end_block(args, &provider).await?;
+    let window = resolve_window(end_block, args.samples, blocks_per_interval(args), U64::one());
+    let blocks = plan_spaced_blocks(args, &provider, &window, args.samples).await?;
+
+    let mut collected = Vec::with_capacity(weights.len());
+    for &(address, weight) in &weights {
+        let pool = load_pool(&provider, token_cache, address, None, (None, None)).await?;
+        status!("🧺 Sampling {} ({}/{})...", report::checksum(&pool.address), pool.token1.symbol, pool.token0.symbol);
+        let sampler = build_sampler(args, &provider, &pool, reserves_cache).await?;
+        let price_points = collect_price_points(args, &provider, &sampler, &blocks).await?;
+        if price_points.is_empty() {
+            return Err(TwapError::NoData.into());
+        }
+        collected.push((pool, weight, price_points));
+    }
+    reserves_cache.save()?;
+
+    let components: Vec<(f64, &[PricePoint])> =
+        collected.iter().map(|(_, weight, points)| (*weight, points.as_slice())).collect();
+    let series = analytics::composite_index(&components);
+    if series.is_empty() {
+        anyhow::bail!("No blocks where every --index pool had price data");
+    }
+
+    let components = collected
+        .iter()
+        .map(|(pool, weight, points)| {
+            let start_price = points[0].price;
+            let twap = analytics::weighted_twap(points, args.weight_by);
+            report::IndexComponent {
+                pool: pool.address,
+                token0: pool.token0.clone(),
+                token1: pool.token1.clone(),
+                weight: *weight,
+                start_price,
+                twap,
+                normalized_twap: twap / start_price,
+                normalized_current: points[points.len() - 1].price / start_price,
+            }
+        })
+        .collect();
+    let index = report::IndexReport {
+        days: args.days,
+        components,
+        index_twap: analytics::weighted_twap(&series, args.weight_by),
+        index_current: series.last().map_or(1.0, |p| p.price),
+        series,
+    };
+
+    match args.format {
+        OutputFormat::Text => index.print_text(),
+        OutputFormat::Json => println!("{}", to_json(&index, args)?),
+        OutputFormat::Prometheus | OutputFormat::Markdown | OutputFormat::Csv => anyhow::bail!("--index only supports --format text and json"),
+    }
+    if let Some(path) = &args.csv {
+        export::write_index_csv(path, &index.series)?;
+        status!();
+        status!("💾 Wrote {} index points to {}", index.series.len(), path);
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The pool addresses of a --compare-pools list, at least two
+fn parse_compare_pools(pools: &str) -> Result<Vec<Address>> {
+    let addresses = pools
+        .split(',')
+        .map(|pool| pool.trim().parse::<Address>().context(format!("Invalid --compare-pools address '{}'", pool.trim())))
+        .collect::<Result<Vec<_>>>()?;
+    if addresses.len() < 2 {
+        anyhow::bail!("--compare-pools needs at least two pool addresses separated by commas");
+    }
+    Ok(addresses)
+}
+
+/// Compute the TWAP of one pair on several pools over the same window and compare them
+async fn run_compare_pools(
+    args: &Args,
+    provider: Arc<Provider<Http>>,
+    token_cache: &mut metadata::TokenCache,
+    reserves_cache: &Arc<cache::ReservesCache>,
+    pools: &str,
+) -> Result<ExitCode> {
+    let addresses = parse_compare_pools(pools)?;
+
+    let mut loaded = Vec::with_capacity(addresses.len());
+    for address in addresses {
+        loaded.push(load_pool(&provider, token_cache, address, None, (args.token0_decimals, args.token1_decimals)).await?);
+    }
+    let (base, quote) = (loaded[0].token0.clone(), loaded[0].token1.clone());
+
+    // Every pool is sampled at the same blocks so the TWAPs cover exactly the same window
+    let end_block = resolve_end_block(args, &provider).await?;
+    let window = resolve_window(end_block, args.samples, blocks_per_interval(args), U64::one());
+    let blocks = plan_spaced_blocks(args, &provider, &window, args.samples).await?;
+
+    let mut compared: Vec<report::ComparedPool> = Vec::with_capacity(loaded.len());
+    for pool in loaded {
+        let inverted = match (pool.token0.address, pool.token1.address) {
+            (token0, token1) if token0 == base.address && token1 == quote.address => false,
+            (token0, token1) if token0 == quote.address && token1 == base.address => true,
+            _ => anyhow::bail!(
+                "Pool {} is {}/{}, not the same pair as the first pool ({}/{})",
+                report::checksum(&pool.address), pool.token0.symbol, pool.token1.symbol, base.symbol, quote.symbol
+            ),
+        };
+
+        status!("⚖️  Sampling {}...", report::checksum(&pool.address));
+        let sampler = build_sampler(args, &provider, &pool, reserves_cache).await?;
+        let mut price_points = collect_price_points(args, &provider, &sampler, &blocks).await?;
+        if price_points.is_empty() {
+            return Err(TwapError::NoData.into());
+        }
+        // Quote the reversed pools in the first pool's direction
+        if inverted {
+            invert_points(&mut price_points);
+        }
+        let price_points = check_pool_health(args, price_points);
+
+        let mut report = report::TwapReport::new(pool.address, base.clone(), quote.clone(), args.days, args.samples, price_points);
+        report.set_weighting(args.weight_by);
+        if let Some(tick) = args.round_to {
+            report.snap_prices(tick);
+        }
+
+        let reference_twap = compared.first().map_or(report.twap, |first| first.twap);
+        compared.push(report::ComparedPool {
+            pool: pool.address,
+            base: base.clone(),
+            quote: quote.clone(),
+            collected_samples: report.collected_samples,
+            twap: report.twap,
+            current_price: report.current_price,
+            liquidity_k: report.liquidity.as_ref().map(|liquidity| liquidity.current_k),
+            spread_pct: (report.twap - reference_twap) / reference_twap * 100.0,
+        });
+    }
+
+    reserves_cache.save()?;
+
+    match args.format {
+        OutputFormat::Text => report::print_comparison(args.days, &compared),
+        OutputFormat::Json => println!("{}", to_json(&compared, args)?),
+        OutputFormat::Prometheus | OutputFormat::Markdown | OutputFormat::Csv => anyhow::bail!("--compare-pools only supports --format text and json"),
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Parse `ADDR@BLOCK,...` into pools and the block each takes over from, in ascending block order
+fn parse_pool_sequence(spec: &str) -> Result<Vec<(Address, u64)>> {
+    let mut segments: Vec<(Address, u64)> = Vec::new();
+    for part in spec.split(',').map(str::trim) {
+        let (pool, block) = part
+            .split_once('@')
+            .context(format!("--pool-sequence entry '{}' should be ADDR@BLOCK", part))?;
+        let pool: Address = pool.trim().parse().context(format!("Invalid --pool-sequence pool address '{}'", pool.trim()))?;
+        let block: u64 = block.trim().parse().context(format!("Invalid --pool-sequence block '{}'", block.trim()))?;
+        if let Some(&(_, previous)) = segments.last() {
+            if block <= previous {
+                anyhow::bail!("--pool-sequence blocks must be strictly increasing ({} follows {})", block, previous);
+            }
+        }
+        segments.push((pool, block));
+    }
+    if segments.len() < 2 {
+        anyhow::bail!("--pool-sequence needs at least two ADDR@BLOCK entries separated by commas");
+    }
+    Ok(segments)
+}
+
+/// Sample each pool of a migration only over the blocks it was authoritative for, and compute
+/// one TWAP over the stitched series, quoted in the first pool's direction
+async fn run_pool_sequence(
+    args: &Args,
+    provider: Arc<Provider<Http>>,
+    token_cache: &mut metadata::TokenCache,
+    reserves_cache: &Arc<cache::ReservesCache>,
+    sequence: &str,
+) -> Result<ExitCode> {
+    let segments = parse_pool_sequence(sequence)?;
+    let mut loaded = Vec::with_capacity(segments.len());
+    for &(address, _) in &segments {
+        loaded.push(load_pool(&provider, token_cache, address, None, (args.token0_decimals, args.token1_decimals)).await?);
+    }
+    let (base, quote) = (loaded[0].token0.clone(), loaded[0].token1.clone());
+
+    let end_block = resolve_end_block(args, &provider).await?;
+    let window = resolve_window(end_block, args.samples, blocks_per_interval(args), U64::one());
+    let blocks = plan_spaced_blocks(args, &provider, &window, args.samples).await?;
+    if let (Some(first), Some(&(_, takes_over))) = (blocks.first(), segments.first()) {
+        if first.as_u64() < takes_over {
+            status!("⚠️  The window starts at block {}, before the first pool takes over at {}; it covers those samples too", first, takes_over);
+        }
+    }
+
+    let mut price_points = Vec::with_capacity(blocks.len());
+    let mut stitched = Vec::with_capacity(segments.len());
+    for (i, pool) in loaded.iter().enumerate() {
+        let from_block = segments[i].1;
+        let until_block = segments.get(i + 1).map_or(u64::MAX, |&(_, block)| block);
+        let segment_blocks: Vec<U64> = blocks
+            .iter()
+            .copied()
+            .filter(|block| (i == 0 || block.as_u64() >= from_block) && block.as_u64() < until_block)
+            .collect();
+
+        let inverted = pool.token0.address == quote.address && pool.token1.address == base.address;
+        if inverted {
+            status!("⚠️  Pool {} has the tokens in reverse order; its prices are inverted to {} per {}",
+                report::checksum(&pool.address), quote.symbol, base.symbol);
+        } else if pool.token0.address != base.address || pool.token1.address != quote.address {
+            status!("⚠️  Pool {} is {}/{}, not the same pair as the first pool ({}/{}); stitching it anyway",
+                report::checksum(&pool.address), pool.token0.symbol, pool.token1.symbol, base.symbol, quote.symbol);
+        }
+        let (decimals0, decimals1) = if inverted {
+            (pool.token1.decimals, pool.token0.decimals)
+        } else {
+            (pool.token0.decimals, pool.token1.decimals)
+        };
+        if (decimals0, decimals1) != (base.decimals, quote.decimals) {
+            status!("⚠️  Pool {} has token decimals {}/{}, but the first pool has {}/{}",
+                report::checksum(&pool.address), decimals0, decimals1, base.decimals, quote.decimals);
+        }
+
+        status!("🔗 Sampling {} for {} blocks from block {}...", report::checksum(&pool.address), segment_blocks.len(), from_block);
+        let sampler = build_sampler(args, &provider, pool, reserves_cache).await?;
+        let mut points = collect_price_points(args, &provider, &sampler, &segment_blocks).await?;
+        if inverted {
+            invert_points(&mut points);
+        }
+        stitched.push(report::PoolSegment {
+            pool: pool.address,
+            from_block,
+            samples: points.len(),
+        });
+        price_points.extend(points);
+    }
+    status!();
+
+    reserves_cache.save()?;
+
+    if price_points.is_empty() {
+        return Err(TwapError::NoData.into());
+    }
+    let price_points = check_pool_health(args, price_points);
+
+    // The report is keyed to the pool that's authoritative now
+    let current_pool = loaded.last().map_or(Address::zero(), |pool| pool.address);
+    let mut report = report::TwapReport::new(current_pool, base, quote, args.days, args.samples, price_points);
+    report.pool_sequence = Some(stitched);
+    report.set_weighting(args.weight_by);
+    report.end_block = Some(end_block.as_u64());
+
+    print_results(args, report)
+}
+
+/// Answer HTTP report requests until killed. The other flags apply to every request as defaults,
+/// and each request can override the pool, days and samples.
+async fn run_serve(args: Args, listen: &str, max_concurrent: usize) -> Result<ExitCode> {
+    // stdout isn't the channel here, so keep it free of progress output
+    STATUS_TO_STDERR.store(true, Ordering::Relaxed);
+
+    let provider = connect_provider(&args).await?;
+    let token_cache = Arc::new(tokio::sync::Mutex::new(metadata::TokenCache::new(provider.clone())));
+    let reserves_cache = Arc::new(cache::ReservesCache::open(&provider, args.reserves_cache.as_deref(), REORG_DEPTH).await?);
+    let defaults = Arc::new(args);
+
+    serve::serve(listen, max_concurrent, move |query| {
+        let (defaults, provider, token_cache, reserves_cache) =
+            (defaults.clone(), provider.clone(), token_cache.clone(), reserves_cache.clone());
+        async move {
+            let args = request_args(&defaults, &query)?;
+            let report = compute_report(&args, &provider, &token_cache, &reserves_cache).await?;
+            to_json(&report, &args)
+        }
+    })
+    .await?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Poll: compute the report the way `serve` does every `interval` seconds, printing each one
+/// after a timestamp line, until Ctrl-C. A failed iteration is reported and the next one runs.
+async fn run_repeat(
+    args: &Args,
+    provider: Arc<Provider<Http>>,
+    token_cache: metadata::TokenCache,
+    reserves_cache: Arc<cache::ReservesCache>,
+    interval: u64,
+) -> Result<ExitCode> {
+    let token_cache = tokio::sync::Mutex::new(token_cache);
+    let mut ticker = tokio::time::interval(Duration::from_secs(interval));
+    ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    status!("🔁 Recomputing every {} (Ctrl-C to stop)", format_duration(interval));
+
+    loop {
+        let iteration = async {
+            ticker.tick().await;
+            status!();
+            status!("🕒 {}", format_timestamp(chrono::Utc::now().timestamp() as u64));
+            let report = compute_report(args, &provider, &token_cache, &reserves_cache).await?;
+            let (end_block, window_start) = (report.end_block.unwrap_or_default(), report.first_sample.map_or(0, |(block, _)| block));
+            print_results(args, report)?;
+            reserves_cache.advance(end_block, REORG_DEPTH, window_start);
+            anyhow::Ok(())
+        };
+        tokio::select! {
+            result = iteration => {
+                if let Err(e) = result {
+                    status!("⚠️  This run failed, trying again in {}: {:#}", format_duration(interval), e);
+                }
+            }
+            _ = tokio::signal::ctrl_c() => {
+                status!();
+                status!("👋 Stopped");
+                return Ok(ExitCode::SUCCESS);
+            }
+        }
+    }
+}
+
+/// Read the pool's token metadata and its price at the chain head, without sampling a window
+async fn run_info(args: &Args) -> Result<ExitCode> {
+    let pool_address: Address = args.pool.as_deref().context("info needs --pool")?.parse().context("Invalid pool address")?;
+    let provider = connect_provider(args).await?;
+    let mut token_cache = metadata::TokenCache::new(provider.clone());
+    let reserves_cache = Arc::new(cache::ReservesCache::open(&provider, None, REORG_DEPTH).await?);
+
+    let pool = load_pool(&provider, &mut token_cache, pool_address, None, (args.token0_decimals, args.token1_decimals)).await?;
+    let sampler = build_sampler(args, &provider, &pool, &reserves_cache).await?;
+    let block = provider.get_block_number().await.map_err(|e| TwapError::RpcUnavailable(e.to_string()))?;
+    let sample = sampler.read_price(block).await?.context(format!("Pool had no reserves at block {}", block))?;
+
+    let info = report::PoolSnapshot {
+        pool: pool_address,
+        name: pool.name,
+        token0: pool.token0,
+        token1: pool.token1,
+        block: block.as_u64(),
+        reserve0: sample.reserve0,
+        reserve1: sample.reserve1,
+        price: sample.price,
+    };
+    match args.format {
+        OutputFormat::Text => info.print_text(),
+        OutputFormat::Json => println!("{}", to_json(&info, args)?),
+        OutputFormat::Prometheus | OutputFormat::Markdown | OutputFormat::Csv => anyhow::bail!("info only supports --format text and json"),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// The server's defaults with a request's `pool`, `days` and `samples` applied
+fn request_args(defaults: &Args, query: &HashMap<String, String>) -> Result<Args> {
+    let bad_request = |message: String| anyhow::Error::from(serve::BadRequest(message));
+    let mut args = defaults.clone();
+    // A creation block only holds for the one pool it was looked up for
+    args.pool_created_block = None;
+    args.pool = Some(query.get("pool").cloned().ok_or_else(|| bad_request("missing the pool parameter".to_string()))?);
+    for (name, value) in [("days", &mut args.days), ("samples", &mut args.samples)] {
+        if let Some(raw) = query.get(name) {
+            *value = raw.parse().map_err(|_| bad_request(format!("{} must be a positive integer, not '{}'", name, raw)))?;
+        }
+    }
+    validate_args(&args).map_err(|e| bad_request(e.to_string()))?;
+    Ok(args)
+}
+
+/// Sample one pool over the window and build its report, without printing anything. The plain
+/// uniformly sampled path of a normal run, for the server.
+async fn compute_report(
+    args: &Args,
+    provider: &Arc<Provider<Http>>,
+    token_cache: &tokio::sync::Mutex<metadata::TokenCache>,
+    reserves_cache: &Arc<cache::ReservesCache>,
+) -> Result<report::TwapReport> {
+    let pool_address: Address = args
+        .pool
+        .as_deref()
+        .unwrap_or_default()
+        .parse()
+        .map_err(|_| serve::BadRequest(format!("invalid pool address '{}'", args.pool.as_deref().unwrap_or_default())))?;
+    let decimals_override = (args.token0_decimals, args.token1_decimals);
+    // Look up tokens in a copy so other requests aren't held up behind this pool's metadata reads
+    let mut tokens = token_cache.lock().await.clone();
+    let pool = load_pool(provider, &mut tokens, pool_address, None, decimals_override).await?;
+    token_cache.lock().await.merge(tokens);
+
+    let end_block = resolve_end_block(args, provider).await?;
+    let window = resolve_window(end_block, args.samples, blocks_per_interval(args), U64::one());
+    let window = clamp_window_to_pool(args, provider, pool_address, window).await?;
+    let sampler = build_sampler(args, provider, &pool, reserves_cache).await?;
+    let price_points = if samples_like_library(args) {
+        sample_with_library(args, provider, &pool, &window, sampler.stable(), reserves_cache).await?.0
+    } else {
+        let blocks = plan_spaced_blocks(args, provider, &window, args.samples).await?;
+        collect_price_points(args, provider, &sampler, &blocks).await?
+    };
+    reserves_cache.save()?;
+    if price_points.is_empty() {
+        return Err(TwapError::NoData.into());
+    }
+    let price_points = check_pool_health(args, price_points);
+
+    let mut report = report::TwapReport::new(pool_address, pool.token0, pool.token1, args.days, args.samples, price_points);
+    report.pool_name = pool.name;
+    report.set_weighting(args.weight_by);
+    report.end_block = Some(end_block.as_u64());
+    if let Some(effective_days) = window.clamped_days {
+        report.clamped_window = Some(report::ClampedWindow {
+            start_block: window.start_block.as_u64(),
+            requested_days: args.days,
+            effective_days,
+            interval_blocks: window.blocks_per_interval,
+        });
+    }
+    report.methodology = methodology(args, &report);
+    Ok(report)
+}
+
+/// Number of planned blocks `--probe` tries to read
+const PROBE_COUNT: usize = 10;
+
+/// Try to read the price at a spread of the planned blocks, without retries, and report how
+/// many worked and where readable history starts
+async fn run_probe(args: &Args, sampler: &sampler::Sampler, blocks: &[U64]) -> Result<ExitCode> {
+    let step = blocks.len().div_ceil(PROBE_COUNT).max(1);
+    let mut probed: Vec<U64> = blocks.iter().step_by(step).copied().collect();
+    if let Some(&last) = blocks.last() {
+        if probed.last() != Some(&last) {
+            probed.push(last);
+        }
+    }
+
+    status!("🩺 Probing {} of {} planned blocks...", probed.len(), blocks.len());
+    let mut results = Vec::with_capacity(probed.len());
+    for &block in &probed {
+        let readable = match sampler.read_price(block).await {
+            Ok(_) => true,
+            Err(e) => {
+                status!("   Block {}: {:#}", block, e);
+                false
+            }
+        };
+        results.push(report::ProbedBlock { block: block.as_u64(), readable });
+    }
+    status!();
+
+    let probe = report::ProbeReport::new(blocks.len(), results);
+    match args.format {
+        OutputFormat::Json => println!("{}", to_json(&probe, args)?),
+        _ => probe.print_text(),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Print the pools containing a token
+async fn run_list_pools(args: &Args, token: &str) -> Result<ExitCode> {
+    let token: Address = token.parse().context("Invalid token address")?;
+    let factory: Address = args.factory.parse().context("Invalid factory address")?;
+
+    let provider = connect_provider(args).await?;
+    let mut token_cache = metadata::TokenCache::new(provider.clone());
+    let symbol = token_cache.get(token).await?.symbol;
+
+    status!("🔎 Scanning factory {} for {} pools...", report::checksum(&factory), symbol);
+    let listings = discovery::list_pools(
+        &provider,
+        &mut token_cache,
+        factory,
+        token,
+        U64::from(args.factory_start_block),
+        args.log_chunk_blocks,
+    )
+    .await?;
+    status!();
+
+    match args.format {
+        OutputFormat::Json => println!("{}", to_json(&listings, args)?),
+        _ => {
+            println!("🏊 {} pools containing {}", listings.len(), symbol);
+            for listing in &listings {
+                println!(
+                    "   {}  {}/{}  {}  reserves {:.4} {} / {:.4} {}",
+                    report::checksum(&listing.pool),
+                    symbol,
+                    listing.paired_symbol,
+                    if listing.stable { "stable" } else { "volatile" },
+                    listing.token_reserve,
+                    symbol,
+                    listing.paired_reserve,
+                    listing.paired_symbol,
+                );
+            }
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Compute the TWAP from previously exported samples without touching the chain
+fn run_from_csv(args: &Args, path: &str) -> Result<ExitCode> {
+    let pool_address = match &args.pool {
+        Some(pool) => pool.parse().context("Invalid pool address")?,
+        None => Address::zero(),
+    };
+
+    let price_points = export::read_csv(path)?;
+    if price_points.is_empty() {
+        anyhow::bail!("No price data in {}", path);
+    }
+
+    status!("📂 Loaded {} samples from {}", price_points.len(), path);
+    status!();
+
+    let mut price_points = check_pool_health(args, price_points);
+    let (token0, token1, inversion) =
+        auto_invert(args, &mut price_points, report::TokenInfo::unknown("TOKEN0"), report::TokenInfo::unknown("TOKEN1"));
+    let (price_points, session) = session_filter(args, price_points)?;
+    let (price_points, gaps) = reserve_gaps(args, price_points, &token0, &token1)?;
+
+    let mut report = report::TwapReport::new(
+        pool_address,
+        token0,
+        token1,
+        args.days,
+        price_points.len() as u64,
+        price_points,
+    );
+    report.auto_inverted = inversion;
+    report.set_weighting(args.weight_by);
+    if let Some(coverage) = session {
+        report.set_session(coverage);
+    }
+    if let Some(gaps) = gaps {
+        report.set_reserve_gaps(gaps);
+    }
+
+    print_results(args, report)
+}
+
+/// Quote samples as token0 per token1: invert the price and swap the reserves. The sqrt price and
+/// tick only describe the pool's own direction, so they're dropped.
+fn invert_points(points: &mut [PricePoint]) {
+    for point in points {
+        point.price = 1.0 / point.price;
+        std::mem::swap(&mut point.reserve0, &mut point.reserve1);
+        point.sqrt_price_x96 = None;
+        point.tick = None;
+    }
+}
+
+/// Under --pair-inverse-threshold, flip the pair when its latest raw price (token1 per token0) is
+/// below the threshold, returning the tokens in the direction chosen
+fn auto_invert(
+    args: &Args,
+    points: &mut [PricePoint],
+    token0: report::TokenInfo,
+    token1: report::TokenInfo,
+) -> (report::TokenInfo, report::TokenInfo, Option<report::AutoInversion>) {
+    let (Some(threshold), Some(raw_spot_price)) = (args.pair_inverse_threshold, points.last().map(|p| p.price)) else {
+        return (token0, token1, None);
+    };
+    if raw_spot_price >= threshold {
+        status!("🔄 Quoting {} per {}: the spot price {} is at or above --pair-inverse-threshold {}",
+            token1.symbol, token0.symbol, report::format_price(raw_spot_price), threshold);
+        status!();
+        return (token0, token1, None);
+    }
+    invert_points(points);
+    status!("🔄 Auto-inverted: quoting {} per {}, since the {} per {} spot price {} is below --pair-inverse-threshold {}",
+        token0.symbol, token1.symbol, token1.symbol, token0.symbol, report::format_price(raw_spot_price), threshold);
+    status!();
+    (token1, token0, Some(report::AutoInversion { threshold, raw_spot_price }))
+}
+
+/// Under --min-reserve-treat-as-gap, drop the samples where either reserve is below the threshold
+/// in whole tokens and take the TWAP over the rest of the time only, instead of letting a drained
+/// pool's price count or the sample before it stand in for the gap
+fn reserve_gaps(
+    args: &Args,
+    mut price_points: Vec<PricePoint>,
+    token0: &report::TokenInfo,
+    token1: &report::TokenInfo,
+) -> Result<(Vec<PricePoint>, Option<report::ReserveGaps>)> {
+    let Some(threshold) = args.min_reserve_treat_as_gap else {
+        return Ok((price_points, None));
+    };
+    let whole = |reserve: u128, decimals: u8| reserve as f64 / 10f64.powi(decimals as i32);
+    let is_gap = |point: &PricePoint| match (point.reserve0, point.reserve1) {
+        (Some(reserve0), Some(reserve1)) => whole(reserve0, token0.decimals) < threshold || whole(reserve1, token1.decimals) < threshold,
+        _ => false,
+    };
+
+    let (twap, gap_seconds) = analytics::twap_skipping_gaps(&price_points, is_gap);
+    let twap = twap.context(format!("Every sample's reserves are below --min-reserve-treat-as-gap {}", threshold))?;
+    let window_seconds = match (price_points.first(), price_points.last()) {
+        (Some(first), Some(last)) => last.timestamp - first.timestamp,
+        _ => 0,
+    };
+    let before = price_points.len();
+    price_points.retain(|point| !is_gap(point));
+    let gap_samples = before - price_points.len();
+    if gap_samples == 0 {
+        return Ok((price_points, None));
+    }
+
+    status!("🕳️  Treated {} samples with reserves below {} tokens as gaps, {} of the window",
+        gap_samples, threshold, format_duration(gap_seconds));
+    status!();
+    Ok((price_points, Some(report::ReserveGaps { threshold, gap_samples, gap_seconds, window_seconds, twap })))
+}
+
+/// Under a session filter, drop the samples outside the session and compute the TWAP over the
+/// session's time from the full series, since a kept sample's price lasts until the next sample
+fn session_filter(args: &Args, mut price_points: Vec<PricePoint>) -> Result<(Vec<PricePoint>, Option<report::SessionCoverage>)> {
+    let Some(description) = session_description(args) else {
+        return Ok((price_points, None));
+    };
+    let filter = session::SessionFilter {
+        timezone: args.output_timezone,
+        weekdays: args.weekday_filter.clone(),
+        time_of_day: args.time_of_day_filter,
+    };
+
+    let (twap, included_seconds, window_seconds) = filter.twap(&price_points);
+    let twap = twap.context(format!("No time in the window falls in the session ({})", description))?;
+    let before = price_points.len();
+    price_points.retain(|point| filter.includes(point.timestamp));
+    if price_points.is_empty() {
+        anyhow::bail!("No samples fall in the session ({}); sample more densely", description);
+    }
+
+    let coverage = report::SessionCoverage {
+        filter: description,
+        included_seconds,
+        window_seconds,
+        excluded_samples: before - price_points.len(),
+        twap,
+    };
+    status!("🗓️  Kept {} of {} samples in {} ({:.1}% of the window)", price_points.len(), before, coverage.filter,
+        included_seconds as f64 / window_seconds.max(1) as f64 * 100.0);
+    status!();
+    Ok((price_points, Some(coverage)))
+}
+
+/// The session filter's days and hours, like `Mon,Tue 13:00-21:00`, or None without one
+fn session_description(args: &Args) -> Option<String> {
+    let days = args.weekday_filter.as_ref().map(|weekdays| {
+        weekdays.0.iter().map(|day| day.to_string()).collect::<Vec<_>>().join(",")
+    });
+    let hours = args.time_of_day_filter.map(|range| range.to_string());
+    match (days, hours) {
+        (Some(days), Some(hours)) => Some(format!("{} {}", days, hours)),
+        (days, hours) => days.or(hours),
+    }
+}
+
+/// Warn about samples whose reserve of a token exceeds that token's totalSupply() at the same block,
+/// which no real balance can do. Supply reads that fail only warn.
+async fn verify_reserves_within_supply(provider: &Arc<Provider<Http>>, pool: &PoolInfo, price_points: &[PricePoint]) {
+    status!("🧾 Checking reserves against each token's total supply...");
+    let tokens = [
+        (ERC20::new(pool.token0.address, provider.clone()), &pool.token0.symbol),
+        (ERC20::new(pool.token1.address, provider.clone()), &pool.token1.symbol),
+    ];
+    let mut violations = 0;
+    let mut unreadable = 0;
+    for point in price_points {
+        for ((token, symbol), reserve) in tokens.iter().zip([point.reserve0, point.reserve1]) {
+            let Some(reserve) = reserve else {
+                continue;
+            };
+            match token.total_supply().block(point.block).call().await {
+                Ok(supply) if U256::from(reserve) > supply => {
+                    status!("⚠️  Block {}: {} reserve {} exceeds its total supply {}", point.block, symbol, reserve, supply);
+                    violations += 1;
+                }
+                Ok(_) => {}
+                Err(_) => unreadable += 1,
+            }
+        }
+    }
+
+    if violations == 0 {
+        status!("✅ Every reserve is within its token's total supply");
+    } else {
+        status!("   {} reserves exceed total supply: a misread, or a rebasing or proxy token quirk", violations);
+    }
+    if unreadable > 0 {
+        status!("ℹ️  {} totalSupply() reads failed and were skipped", unreadable);
+    }
+    status!();
+}
+
+/// Flag sampled intervals where the price moved without any swap, the signature of a rebase
+async fn warn_moves_without_swaps(args: &Args, provider: &Provider<Http>, pool: Address, price_points: &[PricePoint]) -> Result<()> {
+    let (Some(first), Some(last)) = (price_points.first(), price_points.last()) else {
+        return Ok(());
+    };
+    status!("🔎 Checking blocks {}-{} for price moves without swaps...", first.block, last.block);
+    let logs = events::fetch_swap_logs(provider, pool, first.block.into(), last.block.into(), args.log_chunk_blocks).await?;
+
+    let moves = events::moves_without_swaps(price_points, &logs);
+    if moves.is_empty() {
+        status!("✅ Every price move between samples had a swap behind it");
+    } else {
+        status!("⚠️  {} intervals moved the price with no swap, likely a rebase and sync():", moves.len());
+        for &i in &moves {
+            let (previous, current) = (&price_points[i - 1], &price_points[i]);
+            status!("   Blocks {}-{}: {:.8} -> {:.8} ({:+.4}%)", previous.block + 1, current.block, previous.price, current.price,
+                (current.price - previous.price) / previous.price * 100.0);
+        }
+        status!("   The TWAP includes these moves; they reflect balance changes, not trades");
+    }
+    status!();
+    Ok(())
+}
+
+/// Flag one-sample price spikes that look like manipulation, optionally dropping them
+fn check_pool_health(args: &Args, mut price_points: Vec<PricePoint>) -> Vec<PricePoint> {
+    let Some(threshold) = args.jump_threshold else {
+        return price_points;
+    };
+
+    let spikes = analytics::detect_spikes(&price_points, threshold);
+    if spikes.is_empty() {
+        status!("✅ No suspected manipulation above {}%", threshold);
+        status!();
+        return price_points;
+    }
+
+    status!("🚨 {} suspected manipulation samples (>{}% from both neighbors):", spikes.len(), threshold);
+    for &i in &spikes {
+        let point = &price_points[i];
+        status!("   Block {} at {}: {:.8} (neighbors {:.8} / {:.8})",
+            point.block, format_timestamp(point.timestamp), point.price,
+            price_points[i - 1].price, price_points[i + 1].price);
+    }
+
+    if args.exclude_suspected {
+        let mut index = 0;
+        price_points.retain(|_| {
+            let keep = !spikes.contains(&index);
+            index += 1;
+            keep
+        });
+        status!("   Excluded them from the TWAP");
+    }
+    status!();
+
+    price_points
+}
+
+/// Load a report saved from an earlier `--format json` run
+fn load_previous_report(path: &str) -> Result<report::PreviousReport> {
+    let json = std::fs::read_to_string(path).context(format!("Failed to read previous report {}", path))?;
+    serde_json::from_str(&json).context(format!("{} is not a JSON report from this tool", path))
+}
+
+/// Describe how the report was produced, from the arguments that took effect
+fn methodology(args: &Args, report: &report::TwapReport) -> Option<report::Methodology> {
+    let (window_start_block, window_start_timestamp) = report.first_sample?;
+    let last = report.price_points.last()?;
+
+    let price_source = if args.from_csv.is_some() {
+        "csv"
+    } else if report.subgraph.is_some() {
+        "subgraph"
+    } else if args.concentrated {
+        "slot0"
+    } else if args.quoter.is_some() {
+        "quoter"
+    } else if args.wrapper.is_some() {
+        "lp_wrapper"
+    } else if args.samples_from_events && args.price_source == PriceSource::Average {
+        "swap_average"
+    } else if args.samples_from_events && args.price_source == PriceSource::Median {
+        "swap_median"
+    } else {
+        "reserves"
+    };
+    let sampling = if args.from_csv.is_some() {
+        "csv"
+    } else if args.block_hashes.is_some() {
+        "block_hashes"
+    } else if args.daily_boundaries {
+        "daily_boundaries"
+    } else if args.sample_at_open.is_some() {
+        "period_open"
+    } else if args.samples_from_events {
+        "swap_events"
+    } else if args.swaps_per_sample.is_some() {
+        "swap_count"
+    } else if args.adaptive.is_some() {
+        "adaptive"
+    } else if args.spacing == Spacing::Log {
+        "log_spaced"
+    } else {
+        "uniform"
+    };
+
+    let mut filtering = Vec::new();
+    if let (Some(threshold), true) = (args.jump_threshold, args.exclude_suspected) {
+        filtering.push(format!("excluded samples more than {}% from both neighbors", threshold));
+    }
+    if let (Some(bits), true) = (args.head_room, args.exclude_suspected) {
+        filtering.push(format!("excluded samples with reserves within 2^{} of the uint112 maximum", bits));
+    }
+    if let Some(session) = session_description(args) {
+        filtering.push(format!("averaged over {} only ({})", session, args.output_timezone));
+    }
+    if let Some(window) = args.smooth {
+        filtering.push(format!("averaged over a centered {}-sample moving average of the prices", window));
+    }
+    if let Some(threshold) = args.min_reserve_treat_as_gap {
+        filtering.push(format!("left out the time after samples with either reserve below {} tokens", threshold));
+    }
+    if args.on_error == OnError::Skip {
+        filtering.push("skipped samples that failed after retries".to_string());
+    }
+
+    Some(report::Methodology {
+        price_source,
+        balances_fallback: args.balances_fallback,
+        mean: "arithmetic",
+        weighting: report.weighting,
+        sampling,
+        samples: args.samples,
+        interval_blocks: (sampling == "uniform").then(|| {
+            report.clamped_window.as_ref().map_or_else(|| blocks_per_interval(args), |clamped| clamped.interval_blocks)
+        }),
+        jitter_blocks: args.sample_jitter,
+        warmup_samples: args.warmup_samples,
+        filtering,
+        round_to: args.round_to,
+        settings_file: args.export_config.clone(),
+        window_start_block,
+        window_start_timestamp,
+        window_end_block: last.block,
+        window_end_timestamp: last.timestamp,
+    })
+}
+
+/// Digest the report as --format json and --json-out write it, numbers formatted the same way,
+/// and under --hash-samples the full series
+fn report_integrity(args: &Args, report: &mut report::TwapReport) -> Result<report::Integrity> {
+    let digest = |json: String| -> Result<String> { Ok(export::sha256_hex(export::canonical_json(&json)?.as_bytes())) };
+
+    let samples_sha256 = if args.hash_samples { Some(digest(to_json(&report.price_points, args)?)?) } else { None };
+    let series = if args.summary_only { std::mem::take(&mut report.price_points) } else { Vec::new() };
+    let json = to_json(&*report, args);
+    report.price_points.extend(series);
+
+    Ok(report::Integrity {
+        algorithm: "sha256",
+        canonicalization: "object keys sorted, whitespace dropped, numbers and strings as written",
+        report_sha256: digest(json?)?,
+        samples_sha256,
+    })
+}
+
+/// Serialize a value as compact JSON, or indented when `--pretty` is set, rounding floats
+/// under `--json-compact-numbers`
+fn to_json<T: Serialize>(value: &T, args: &Args) -> Result<String> {
+    let Some(mode) = args.json_compact_numbers else {
+        let json = if args.pretty {
+            serde_json::to_string_pretty(value)?
+        } else {
+            serde_json::to_string(value)?
+        };
+        return Ok(json);
+    };
+
+    let as_strings = mode == JsonNumbers::String;
+    let digits = args.json_significant_digits;
+    if args.pretty {
+        write_json(value, export::CompactFloats { inner: PrettyFormatter::new(), digits, as_strings })
+    } else {
+        write_json(value, export::CompactFloats { inner: CompactFormatter, digits, as_strings })
+    }
+}
+
+fn write_json<T: Serialize, F: Formatter>(value: &T, formatter: F) -> Result<String> {
+    let mut json = Vec::new();
+    value.serialize(&mut serde_json::Serializer::with_formatter(&mut json, formatter))?;
+    Ok(String::from_utf8(json)?)
+}
+
+/// Turn a breached `--fail-if-deviation-exceeds` threshold into a distinct exit code
+fn check_deviation(args: &Args, report: &report::TwapReport) -> ExitCode {
+    match args.fail_if_deviation_exceeds {
+        Some(threshold) if report.deviation_pct.abs() > threshold => {
+            eprintln!(
+                "🚨 Deviation from TWAP {:.2}% exceeds the {}% threshold",
+                report.deviation_pct, threshold
+            );
+            ExitCode::from(EXIT_DEVIATION_EXCEEDED)
+        }
+        _ => ExitCode::SUCCESS,
+    }
+}
+
+/// Symbols of USD stablecoins and ETH (plus liquid staking tokens) common on Base
+const STABLE_SYMBOLS: &[&str] = &["USDC", "USDBC", "USDT", "DAI", "USDS", "USDE", "USD+", "DOLA", "GHO", "FRAX", "LUSD", "CRVUSD", "EUSD"];
+const ETH_SYMBOLS: &[&str] = &["WETH", "ETH", "CBETH", "WSTETH", "RETH", "WEETH", "EZETH"];
+
+/// One-line hint about which way the price is quoted, for stable/stable and stable/ETH pairs whose
+/// price is far enough from 1 that it's easy to read the wrong way round
+fn hint_price_direction(report: &report::TwapReport) {
+    let kind = |symbol: &str| {
+        let symbol = symbol.to_uppercase();
+        if STABLE_SYMBOLS.contains(&symbol.as_str()) {
+            Some("stable")
+        } else if ETH_SYMBOLS.contains(&symbol.as_str()) {
+            Some("eth")
+        } else {
+            None
+        }
+    };
+    let (base, quote) = (&report.token0.symbol, &report.token1.symbol);
+    let price = report.current_price;
+
+    match (kind(base), kind(quote)) {
+        (Some("stable"), Some("stable")) if !(0.5..=2.0).contains(&price) => {
+            status!("💡 {} and {} are both stablecoins but the price is {:.8} {} per {}; check the token decimals", base, quote, price, quote, base);
+        }
+        (Some(_), Some(_)) if !(0.01..=100.0).contains(&price) => {
+            status!("💡 Prices are {} per {}; the reciprocal is {:.8} {} per {}", quote, base, 1.0 / price, base, quote);
+        }
+        _ => {}
+    }
+}
+
+/// Compare the TWAP with --assert-twap, returning the failure exit code if it's out of tolerance
+fn check_twap_assertion(args: &Args, report: &report::TwapReport) -> Option<ExitCode> {
+    let expected = args.assert_twap?;
+    let difference_pct = (report.twap - expected) / expected * 100.0;
+    if difference_pct.abs() <= args.assert_tolerance {
+        status!("✅ TWAP {:.8} matches the expected {:.8} ({:+.4}%, tolerance {}%)", report.twap, expected, difference_pct, args.assert_tolerance);
+        return None;
+    }
+
+    eprintln!(
+        "🚨 TWAP assertion failed: actual {:.8}, expected {:.8} ({:+.4}%, tolerance {}%)",
+        report.twap, expected, difference_pct, args.assert_tolerance
+    );
+    Some(ExitCode::from(EXIT_ASSERTION_FAILED))
+}
+
+/// Enforce --fail-on-gap on the widest gap between consecutive samples, returning the failure
+/// exit code if it's over the limit
+fn check_sample_gap(args: &Args, report: &report::TwapReport) -> Option<ExitCode> {
+    let limit = args.fail_on_gap?;
+    let widest = report.price_points.windows(2).max_by_key(|pair| pair[1].timestamp - pair[0].timestamp)?;
+    let gap = widest[1].timestamp - widest[0].timestamp;
+    if gap <= limit {
+        status!("✅ Widest gap between samples is {} (limit {}s)", format_duration(gap), limit);
+        return None;
+    }
+
+    eprintln!(
+        "🚨 Coverage gap of {} between block {} ({}) and block {} ({}) exceeds --fail-on-gap {}s",
+        format_duration(gap), widest[0].block, format_timestamp(widest[0].timestamp),
+        widest[1].block, format_timestamp(widest[1].timestamp), limit
+    );
+    Some(ExitCode::from(EXIT_GAP_EXCEEDED))
+}
+
+/// Render the report in the requested format and write any requested exports
+fn print_results(args: &Args, mut report: report::TwapReport) -> Result<ExitCode> {
+    report.methodology = methodology(args, &report);
+
+    if let Some(extra) = args.quote_decimals {
+        PRICE_DISPLAY.get_or_init(|| (report.token1.decimals as usize + extra, args.rounding));
+    }
+    if let Some(digits) = args.price_precision_auto {
+        PRICE_DISPLAY.get_or_init(|| (report::significant_decimals(report.twap, digits), args.rounding));
+    }
+
+    if let Some(window) = args.smooth {
+        report.set_smoothing(window);
+    }
+    if args.block_span {
+        report.set_block_span(args.block_time.unwrap_or(2.0));
+    }
+
+    if let Some(path) = &args.diff {
+        let previous = load_previous_report(path)?;
+        if previous.pool != report.pool {
+            status!("⚠️  WARNING: {} is a report for pool {}, not {}", path, report::checksum(&previous.pool), report::checksum(&report.pool));
+            status!("   The changes below compare different pools");
+            status!();
+        }
+        report.set_diff(&previous);
+    }
+
+    if let Some(price) = args.reference_price {
+        report.set_reference_price(price);
+    }
+
+    // Streamed reports computed theirs while folding, since they no longer have the series
+    if args.realized_variance && report.realized_variance.is_none() {
+        report.realized_variance = analytics::realized_variance(&report.price_points, args.annualization_days);
+    }
+
+    if args.max_drawdown {
+        report.max_drawdown = analytics::max_drawdown(&report.price_points);
+    }
+
+    if let Some(target_pct) = args.manipulation_cost {
+        report.manipulation_cost =
+            analytics::manipulation_cost(&report.price_points, report.weighting, target_pct, report.token1.decimals);
+        if report.manipulation_cost.is_none() {
+            status!("⚠️  No samples carry reserves, so --manipulation-cost has nothing to estimate from");
+        }
+    }
+
+    if let Some(period) = args.twap_of_twaps {
+        report.twap_of_twaps = analytics::twap_of_twaps(&report.price_points, period);
+    }
+
+    // The deviation series is derived from the raw prices, before any --round-to snapping
+    let deviation_series = args
+        .deviation_series
+        .as_ref()
+        .map(|path| (path, analytics::deviation_series(&report.price_points)));
+    let spot_series = args
+        .compare_to_spot_series
+        .as_ref()
+        .zip(args.spot_series_window)
+        .map(|(path, window)| (path, analytics::spot_vs_twap_series(&report.price_points, window)));
+    let candles = args
+        .ohlc
+        .zip(args.ohlc_out.as_ref())
+        .map(|(interval, path)| (path, analytics::ohlc_candles(&report.price_points, interval)));
+
+    // Like the deviation series, the audit log records the unrounded computation
+    if let Some(path) = &args.audit_log {
+        let audit = analytics::twap_audit(&report.price_points, report.weighting);
+        if audit.twap != report.twap {
+            status!("⚠️  The reported TWAP includes a warmup interval the audit log doesn't cover ({:.8} vs {:.8})", report.twap, audit.twap);
+        }
+        let json = serde_json::to_string_pretty(&audit)?;
+        std::fs::write(path, json).context(format!("Failed to write audit log to {}", path))?;
+        status!("🧾 Wrote the TWAP computation ({} intervals) to {}", audit.intervals.len(), path);
+        status!();
+    }
+
+    if let Some(tick) = args.round_to {
+        report.snap_prices(tick);
+    }
+    if let Some((min, max)) = args.clamp_range {
+        report.clamp_prices(min, max);
+        if let Some(clamped) = report.clamped_prices.as_ref().filter(|clamped| clamped.samples > 0) {
+            status!("🗜️  Capped {} sample prices into [{}, {}] for display; the TWAP uses the raw prices", clamped.samples, min, max);
+            status!();
+        }
+    }
+
+    if args.hash {
+        report.integrity = Some(report_integrity(args, &mut report)?);
+    }
+
+    // Only JSON prints the series; under --summary-only it's held back there but still exported
+    let series = if args.summary_only && args.format == OutputFormat::Json {
+        std::mem::take(&mut report.price_points)
+    } else {
+        Vec::new()
+    };
+
+    if args.bps_only {
+        println!("{:.2}", report.deviation_pct * 100.0);
+    } else {
+        match args.format {
+            OutputFormat::Text if !args.metrics.is_empty() => report.print_metrics(&args.metrics),
+            OutputFormat::Json if !args.metrics.is_empty() => println!("{}", to_json(&report.metrics_view(&args.metrics), args)?),
+            OutputFormat::Text => report.print_text(),
+            OutputFormat::Json => println!("{}", to_json(&report, args)?),
+            OutputFormat::Prometheus => print!("{}", report.to_prometheus()),
+            OutputFormat::Markdown => print!("{}", report.to_markdown(!args.summary_only)),
+            OutputFormat::Csv => print!("{}", export::samples_csv(&report.price_points)),
+        }
+    }
+    report.price_points.extend(series);
+
+    if let Some(path) = &args.json_out {
+        let series = if args.summary_only { std::mem::take(&mut report.price_points) } else { Vec::new() };
+        let json = to_json(&report, args);
+        report.price_points.extend(series);
+        std::fs::write(path, json? + "\n").context(format!("Failed to write JSON report to {}", path))?;
+        status!();
+        status!("💾 Wrote the JSON report to {}", path);
+    }
+
+    if let Some(path) = &args.markdown_out {
+        std::fs::write(path, report.to_markdown(!args.summary_only)).context(format!("Failed to write Markdown report to {}", path))?;
+        status!();
+        status!("💾 Wrote the Markdown report to {}", path);
+    }
+
+    if args.warn_on_symbol_mismatch {
+        hint_price_direction(&report);
+    }
+
+    if let Some(path) = &args.csv {
+        export::write_csv(path, &report.price_points)?;
+        status!();
+        status!("💾 Wrote {} samples to {}", report.price_points.len(), path);
+    }
+
+    if let Some(path) = &args.parquet {
+        export::write_parquet(path, &report.price_points)?;
+        status!();
+        status!("💾 Wrote {} samples to {}", report.price_points.len(), path);
+    }
+
+    if let Some((path, series)) = deviation_series {
+        std::fs::write(path, analytics::deviation_series_csv(&series))
+            .context(format!("Failed to write deviation series to {}", path))?;
+
+        status!();
+        status!("📉 DEVIATION FROM TRAILING TWAP");
+        if let Some((max, min)) = analytics::deviation_extremes(&series) {
+            status!("🔺 Max Above: {:+.2}% at {}", max.deviation_pct, format_timestamp(max.timestamp));
+            status!("🔻 Max Below: {:+.2}% at {}", min.deviation_pct, format_timestamp(min.timestamp));
+        }
+        status!("💾 Wrote {} points to {}", series.len(), path);
+    }
+
+    if let Some((path, candles)) = candles {
+        let contents = if path.ends_with(".json") {
+            serde_json::to_string_pretty(&candles)?
+        } else {
+            analytics::ohlc_csv(&candles)
+        };
+        std::fs::write(path, contents).context(format!("Failed to write OHLC candles to {}", path))?;
+        status!();
+        status!("🕯️  Wrote {} candles to {}", candles.len(), path);
+    }
+
+    if let Some((path, series)) = spot_series {
+        let contents = if path.ends_with(".json") {
+            serde_json::to_string_pretty(&series)?
+        } else {
+            analytics::spot_vs_twap_csv(&series)
+        };
+        std::fs::write(path, contents).context(format!("Failed to write spot-vs-TWAP series to {}", path))?;
+        let crosses = series.iter().filter(|point| point.cross != 0).count();
+        status!();
+        status!("📈 Wrote {} spot-vs-TWAP points to {} ({} crossovers)", series.len(), path, crosses);
+    }
+
+    if let Some(code) = check_twap_assertion(args, &report) {
+        return Ok(code);
+    }
+    if let Some(code) = check_sample_gap(args, &report) {
+        return Ok(code);
+    }
+    Ok(check_deviation(args, &report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POOL: &str = "0xcDAC0d6c6C59727a65F871236188350531885C43";
+
+    fn parse(flags: &[&str]) -> Args {
+        Args::try_parse_from(["twap", "--pool", POOL].iter().chain(flags)).unwrap()
+    }
+
+    #[test]
+    fn zero_samples_fail_validation() {
+        let error = validate_args(&parse(&["--samples", "0"])).unwrap_err();
+        assert!(error.to_string().contains("--samples"), "{}", error);
+        assert!(validate_args(&parse(&["--samples", "1"])).is_err());
+        assert!(validate_args(&parse(&["--samples", "2"])).is_ok());
+    }
+
+    #[test]
+    fn jittered_blocks_that_collide_are_merged() {
+        // Ten blocks squeezed into [1, 3] have to share blocks
+        let plan: Vec<U64> = (1..=10u64).map(U64::from).collect();
+        let mut blocks = plan.clone();
+        jitter_sample_blocks(&mut blocks, 1_000, U64::from(3), Some(7));
+        assert!(blocks.len() <= 3);
+        assert!(blocks.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(blocks.iter().all(|block| (1..=3).contains(&block.as_u64())));
+
+        let mut again = plan;
+        jitter_sample_blocks(&mut again, 1_000, U64::from(3), Some(7));
+        assert_eq!(blocks, again);
+    }
+
+    #[test]
+    fn zero_days_fail_validation() {
+        let error = validate_args(&parse(&["--days", "0"])).unwrap_err();
+        assert!(error.to_string().contains("--days"), "{}", error);
+    }
+
+    #[test]
+    fn windows_set_by_blocks_need_no_days() {
+        // Pinned blocks set their own window, so --days is only a label there
+        assert!(validate_args(&parse(&["--days", "0", "--block-hashes", "hashes.txt"])).is_ok());
+        // An explicit end block still works with the default --days
+        assert!(validate_args(&parse(&["--end-block", "12345678"])).is_ok());
+    }
+
+    #[test]
+    fn plain_runs_sample_through_the_library() {
+        assert!(samples_like_library(&parse(&[])));
+        assert!(samples_like_library(&parse(&["--days", "1", "--samples", "24", "--on-error", "skip", "--seed", "3"])));
+        assert!(!samples_like_library(&parse(&["--sample-jitter", "5"])));
+        assert!(!samples_like_library(&parse(&["--spacing", "log"])));
+        assert!(!samples_like_library(&parse(&["--concentrated"])));
+    }
 }
@@ -0,0 +1,122 @@
+use anyhow::{Context, Result};
+use ethers::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::report::{self, TokenInfo};
+use crate::{ERC20Bytes32, ERC20};
+
+/// Token metadata memoized by address, so tokens shared between pools are only read once per run
+#[derive(Clone)]
+pub struct TokenCache {
+    provider: Arc<Provider<Http>>,
+    tokens: HashMap<Address, TokenInfo>,
+}
+
+impl TokenCache {
+    pub fn new(provider: Arc<Provider<Http>>) -> Self {
+        Self {
+            provider,
+            tokens: HashMap::new(),
+        }
+    }
+
+    /// Fetch a token's symbol and decimals, reusing an earlier lookup when there is one
+    pub async fn get(&mut self, address: Address) -> Result<TokenInfo> {
+        if let Some(info) = self.tokens.get(&address) {
+            return Ok(info.clone());
+        }
+
+        let token = ERC20::new(address, self.provider.clone());
+        let decimals = token
+            .decimals()
+            .call()
+            .await
+            .context(format!("Failed to get decimals for token {}", report::checksum(&address)))?;
+        let symbol = match token.symbol().call().await {
+            Ok(symbol) => symbol,
+            Err(_) => self.bytes32_symbol(address).await.unwrap_or_else(|| "UNKNOWN".to_string()),
+        };
+
+        let info = TokenInfo {
+            address,
+            symbol,
+            decimals,
+        };
+        self.tokens.insert(address, info.clone());
+        Ok(info)
+    }
+
+    /// Take in the lookups a copy of this cache made, keeping the entries already here
+    pub fn merge(&mut self, other: TokenCache) {
+        for (address, info) in other.tokens {
+            self.tokens.entry(address).or_insert(info);
+        }
+    }
+
+    /// Read a legacy bytes32 symbol, null-padded on the right. None unless it's non-empty UTF-8.
+    async fn bytes32_symbol(&self, address: Address) -> Option<String> {
+        let raw = ERC20Bytes32::new(address, self.provider.clone()).symbol().call().await.ok()?;
+        let end = raw.iter().position(|&byte| byte == 0).unwrap_or(raw.len());
+        let symbol = std::str::from_utf8(&raw[..end]).ok()?.trim();
+        (!symbol.is_empty()).then(|| symbol.to_string())
+    }
+}
+
+/// Getters that taxed tokens commonly expose. A nonzero value from any of them suggests the
+/// token takes a fee on transfer.
+const TAX_GETTERS: &[&str] = &[
+    "buyTax()",
+    "sellTax()",
+    "buyFee()",
+    "sellFee()",
+    "taxFee()",
+    "_taxFee()",
+    "transferFee()",
+    "_transferFee()",
+    "totalFees()",
+];
+
+/// Heuristically detect a fee-on-transfer token, returning the getter that gave it away.
+///
+/// Only finds tokens that publish their tax through one of `TAX_GETTERS`; tokens that hide it,
+/// or charge it only on some transfers, get through.
+pub async fn fee_on_transfer_getter(provider: &Provider<Http>, token: Address) -> Option<&'static str> {
+    for getter in TAX_GETTERS {
+        let selector = &ethers::utils::keccak256(getter.as_bytes())[..4];
+        let call = TransactionRequest::new().to(token).data(selector.to_vec());
+        let Ok(output) = provider.call(&call.into(), None).await else {
+            continue;
+        };
+        if output.len() == 32 && output.iter().any(|&byte| byte != 0) {
+            return Some(getter);
+        }
+    }
+    None
+}
+
+/// Symbols of rebasing tokens seen in Base pools
+const KNOWN_REBASING_SYMBOLS: &[&str] = &["USD+", "USDC+", "DAI+", "USDT+", "ETH+", "stETH", "AMPL", "sOHM"];
+
+/// Getters that rebasing token implementations expose: OUSD-style credits and Overnight's index
+const REBASE_GETTERS: &[&str] = &["rebasingCreditsPerToken()", "rebasingCredits()", "liquidityIndex()"];
+
+/// Heuristically detect a rebasing token, returning what gave it away: a known symbol or one of
+/// `REBASE_GETTERS` answering with a word.
+///
+/// Tokens under other symbols whose rebase logic uses different names get through.
+pub async fn rebasing_hint(provider: &Provider<Http>, token: &TokenInfo) -> Option<String> {
+    if KNOWN_REBASING_SYMBOLS.iter().any(|symbol| symbol.eq_ignore_ascii_case(&token.symbol)) {
+        return Some(format!("{} is a known rebasing token", token.symbol));
+    }
+    for getter in REBASE_GETTERS {
+        let selector = &ethers::utils::keccak256(getter.as_bytes())[..4];
+        let call = TransactionRequest::new().to(token.address).data(selector.to_vec());
+        if let Ok(output) = provider.call(&call.into(), None).await {
+            if output.len() == 32 {
+                return Some(format!("it answers {}", getter));
+            }
+        }
+    }
+    None
+}
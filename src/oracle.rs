@@ -0,0 +1,127 @@
+use anyhow::{Context, Result};
+use ethers::prelude::*;
+use std::sync::Arc;
+
+use crate::{pricing, AerodromePool, ChainlinkAggregator, PricePoint};
+
+/// A feed round as observed at a sampled block
+struct Round {
+    updated_at: u64,
+    answer: f64,
+}
+
+/// Read a price feed at each sample's block and interpolate it onto the sample timestamps.
+///
+/// The feed only reports a new answer once per round, so between two observed rounds the
+/// price is interpolated linearly by update time; outside them it's held flat.
+pub async fn feed_series(
+    provider: Arc<Provider<Http>>,
+    feed: Address,
+    price_points: &[PricePoint],
+    invert: bool,
+) -> Result<Vec<PricePoint>> {
+    let aggregator = ChainlinkAggregator::new(feed, provider);
+    let decimals = aggregator.decimals().call().await.context("Failed to get feed decimals")?;
+
+    let mut rounds: Vec<Round> = Vec::new();
+    for point in price_points {
+        let (_, answer, _, updated_at, _) = aggregator
+            .latest_round_data()
+            .block(BlockId::Number(BlockNumber::Number(U64::from(point.block))))
+            .call()
+            .await
+            .context(format!("Failed to read feed at block {}", point.block))?;
+
+        if answer <= I256::zero() {
+            anyhow::bail!("Feed returned a non-positive answer at block {}", point.block);
+        }
+
+        let updated_at = updated_at.as_u64();
+        if rounds.last().is_some_and(|r| r.updated_at == updated_at) {
+            continue;
+        }
+
+        let answer = pricing::u256_to_f64(answer.into_raw()) / 10f64.powi(decimals as i32);
+        rounds.push(Round {
+            updated_at,
+            answer: if invert { 1.0 / answer } else { answer },
+        });
+    }
+
+    Ok(price_points
+        .iter()
+        .map(|point| PricePoint {
+            block: point.block,
+            timestamp: point.timestamp,
+            price: interpolate(&rounds, point.timestamp),
+            ..Default::default()
+        })
+        .collect())
+}
+
+/// Feed price at `timestamp`, linear between the surrounding rounds
+fn interpolate(rounds: &[Round], timestamp: u64) -> f64 {
+    let next = rounds.partition_point(|r| r.updated_at <= timestamp);
+    match (next.checked_sub(1).map(|i| &rounds[i]), rounds.get(next)) {
+        (Some(prev), Some(next)) => {
+            let fraction = (timestamp - prev.updated_at) as f64 / (next.updated_at - prev.updated_at) as f64;
+            prev.answer + (next.answer - prev.answer) * fraction
+        }
+        (Some(round), None) | (None, Some(round)) => round.answer,
+        (None, None) => f64::NAN,
+    }
+}
+
+/// Exact on-chain TWAP from the pool's tick-cumulative oracle over the `window_seconds` ending at
+/// `end_block`, as (arithmetic mean tick, price).
+///
+/// Fails if the pool has no observe() or its observation history doesn't reach back that far.
+pub async fn observe_twap(
+    provider: &Arc<Provider<Http>>,
+    pool: Address,
+    (token0_decimals, token1_decimals): (u8, u8),
+    end_block: U64,
+    window_seconds: u64,
+) -> Result<(f64, f64)> {
+    let seconds_ago = u32::try_from(window_seconds).context("Window is too long for observe()")?;
+    let contract = AerodromePool::new(pool, provider.clone());
+
+    let (tick_cumulatives, _) = contract
+        .observe(vec![seconds_ago, 0])
+        .block(BlockId::Number(BlockNumber::Number(end_block)))
+        .call()
+        .await
+        .context("observe() call failed")?;
+    let [start, end] = tick_cumulatives[..] else {
+        anyhow::bail!("observe() returned {} cumulatives, expected 2", tick_cumulatives.len());
+    };
+
+    let mean_tick = (end - start) as f64 / window_seconds as f64;
+    Ok((mean_tick, pricing::tick_to_price(mean_tick, token0_decimals, token1_decimals)))
+}
+
+/// A pool's cumulative reserves as of a block, from currentCumulativePrices(), which accounts for
+/// the time since the last update
+pub struct Accumulators {
+    pub block: u64,
+    pub timestamp: u64,
+    pub reserve0_cumulative: U256,
+    pub reserve1_cumulative: U256,
+}
+
+/// Read the reserve accumulators Aerodrome pools keep for their own quote(). Fails on pools
+/// without currentCumulativePrices(), e.g. Uniswap V2 forks, whose accumulators are of price instead.
+pub async fn reserve_accumulators(provider: &Arc<Provider<Http>>, pool: Address, block: u64) -> Result<Accumulators> {
+    let (reserve0_cumulative, reserve1_cumulative, timestamp) = AerodromePool::new(pool, provider.clone())
+        .current_cumulative_prices()
+        .block(block)
+        .call()
+        .await
+        .context(format!("currentCumulativePrices() failed at block {}", block))?;
+    Ok(Accumulators {
+        block,
+        timestamp: timestamp.as_u64(),
+        reserve0_cumulative,
+        reserve1_cumulative,
+    })
+}
@@ -0,0 +1,241 @@
+use anyhow::Result;
+use ethers::types::{U256, U512};
+
+/// Convert a U256 to the nearest f64
+pub fn u256_to_f64(value: U256) -> f64 {
+    words_to_f64(&value.0)
+}
+
+/// Little-endian 64-bit words of an unsigned integer as an f64
+fn words_to_f64(words: &[u64]) -> f64 {
+    words.iter().rev().fold(0.0f64, |acc, &word| acc * 2f64.powi(64) + word as f64)
+}
+
+/// Digits of the fixed-point price `reserves_to_price` divides out as an integer
+const PRICE_SCALE_DIGITS: u8 = 18;
+
+/// Spot price (token1 per token0) of a constant-product pool, or None if reserve0 is empty.
+///
+/// The division is done on the raw reserves as integers, reserve1 * 10^(token0_decimals + 18)
+/// over reserve0 * 10^token1_decimals, giving the price with 18 fixed-point digits before it's
+/// converted to a float, so prices below 1 keep their precision too. Equal decimals cancel, so
+/// then only the fixed-point scale is applied. Errors if the decimals are too large for the scaled
+/// reserves to fit in 512 bits.
+pub fn reserves_to_price(reserve0: u128, reserve1: u128, token0_decimals: u8, token1_decimals: u8) -> Result<Option<f64>> {
+    if reserve0 == 0 {
+        return Ok(None);
+    }
+
+    let overflow = || {
+        anyhow::anyhow!("Token decimals {} and {} are too large to price the reserves exactly", token0_decimals, token1_decimals)
+    };
+    let (numerator, denominator) = if token0_decimals == token1_decimals {
+        (U512::from(reserve1) * U512::exp10(PRICE_SCALE_DIGITS as usize), U512::from(reserve0))
+    } else {
+        let numerator = token0_decimals
+            .checked_add(PRICE_SCALE_DIGITS)
+            .and_then(pow10)
+            .and_then(|scale| U512::from(reserve1).checked_mul(scale))
+            .ok_or_else(overflow)?;
+        let denominator = pow10(token1_decimals).and_then(|scale| U512::from(reserve0).checked_mul(scale)).ok_or_else(overflow)?;
+        (numerator, denominator)
+    };
+    let (scaled, remainder) = numerator.div_mod(denominator);
+    let scaled = words_to_f64(&scaled.0) + words_to_f64(&remainder.0) / words_to_f64(&denominator.0);
+    Ok(Some(scaled / 10f64.powi(PRICE_SCALE_DIGITS as i32)))
+}
+
+/// Decode a Q64.96 sqrt price into token1 per token0, adjusted for decimals
+pub fn sqrt_price_x96_to_price(sqrt_price_x96: U256, token0_decimals: u8, token1_decimals: u8) -> f64 {
+    let sqrt_price = u256_to_f64(sqrt_price_x96) / 2f64.powi(96);
+    sqrt_price * sqrt_price * 10f64.powi(token0_decimals as i32 - token1_decimals as i32)
+}
+
+/// Convert a (possibly fractional) tick to a human-readable price, token1 per token0
+pub fn tick_to_price(tick: f64, token0_decimals: u8, token1_decimals: u8) -> f64 {
+    1.0001f64.powf(tick) * 10f64.powi(token0_decimals as i32 - token1_decimals as i32)
+}
+
+/// Round a price to the nearest multiple of `tick`, trimmed to the tick's own decimal places
+/// so the result doesn't carry float noise like 0.30000000000000004
+pub fn snap_to_tick(price: f64, tick: f64) -> f64 {
+    let snapped = (price / tick).round() * tick;
+    let decimals = tick.to_string().split_once('.').map_or(0, |(_, frac)| frac.len());
+    format!("{:.*}", decimals, snapped).parse().unwrap_or(snapped)
+}
+
+/// Spot price of a constant-product pool as an integer scaled by `scale`, computed exactly from
+/// the reserves. None if reserve0 is empty or the result doesn't fit in a U256.
+pub fn reserves_to_fixed_point(
+    reserve0: u128,
+    reserve1: u128,
+    token0_decimals: u8,
+    token1_decimals: u8,
+    scale: U256,
+) -> Option<U256> {
+    let numerator = U512::from(reserve1)
+        .checked_mul(pow10(token0_decimals)?)?
+        .checked_mul(U512::from(scale))?;
+    let denominator = U512::from(reserve0).checked_mul(pow10(token1_decimals)?)?;
+    fixed_point_div(numerator, denominator)
+}
+
+/// Q64.96 sqrt price as an integer price scaled by `scale`, computed exactly
+pub fn sqrt_price_x96_to_fixed_point(
+    sqrt_price_x96: U256,
+    token0_decimals: u8,
+    token1_decimals: u8,
+    scale: U256,
+) -> Option<U256> {
+    let sqrt_price = U512::from(sqrt_price_x96);
+    let numerator = sqrt_price
+        .checked_mul(sqrt_price)?
+        .checked_mul(pow10(token0_decimals)?)?
+        .checked_mul(U512::from(scale))?;
+    let denominator = (U512::one() << 192).checked_mul(pow10(token1_decimals)?)?;
+    fixed_point_div(numerator, denominator)
+}
+
+fn pow10(exponent: u8) -> Option<U512> {
+    U512::from(10).checked_pow(U512::from(exponent))
+}
+
+fn fixed_point_div(numerator: U512, denominator: U512) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+    U256::try_from(numerator / denominator).ok()
+}
+
+/// Amount of token1 out for `amount_in` of token0 on a stable pool, from the x³y + xy³ = k
+/// invariant, before fees. All amounts are in whole tokens. None if either reserve is empty.
+pub fn stable_amount_out(amount_in: f64, reserve0: f64, reserve1: f64) -> Option<f64> {
+    if reserve0 <= 0.0 || reserve1 <= 0.0 {
+        return None;
+    }
+
+    let k = reserve0.powi(3) * reserve1 + reserve0 * reserve1.powi(3);
+    let x = reserve0 + amount_in;
+
+    // Newton's method from the old reserve1, which is above the root, so it converges from above
+    let mut y = reserve1;
+    for _ in 0..255 {
+        let f = x.powi(3) * y + x * y.powi(3) - k;
+        let step = f / (x.powi(3) + 3.0 * x * y * y);
+        y -= step;
+        if step.abs() <= y * 1e-15 {
+            break;
+        }
+    }
+
+    Some(reserve1 - y)
+}
+
+/// Amount of token1 out for `amount_in` of token0 on a volatile x * y = k pool, before fees.
+/// All amounts are in whole tokens. None if either reserve is empty.
+pub fn volatile_amount_out(amount_in: f64, reserve0: f64, reserve1: f64) -> Option<f64> {
+    if reserve0 <= 0.0 || reserve1 <= 0.0 {
+        return None;
+    }
+    Some(reserve1 * amount_in / (reserve0 + amount_in))
+}
+
+/// Spot price (token1 per token0) of an Aerodrome stable pool from its raw reserves, or None if
+/// either is empty. The reserves are normalized to whole tokens first, as the pool normalizes them
+/// to 18 decimals, since the curve only balances like-for-like amounts.
+pub fn stable_reserves_to_price(reserve0: u128, reserve1: u128, token0_decimals: u8, token1_decimals: u8) -> Option<f64> {
+    let whole = |reserve: u128, decimals: u8| reserve as f64 / 10f64.powi(decimals as i32);
+    stable_spot_price(whole(reserve0, token0_decimals), whole(reserve1, token1_decimals))
+}
+
+/// Marginal price (token1 per token0) of a stable pool: minus the slope of x³y + xy³ = k at its
+/// reserves, in whole tokens. None if either reserve is empty.
+pub fn stable_spot_price(reserve0: f64, reserve1: f64) -> Option<f64> {
+    if reserve0 <= 0.0 || reserve1 <= 0.0 {
+        return None;
+    }
+    let (x, y) = (reserve0, reserve1);
+    Some((3.0 * x * x * y + y.powi(3)) / (x.powi(3) + 3.0 * x * y * y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance * expected.abs(), "{} is not within {} of {}", actual, tolerance, expected);
+    }
+
+    #[test]
+    fn reserves_price_keeps_precision_below_one() {
+        // USDC/WETH at 3,000 USDC per WETH: 3M USDC (6 decimals) against 1,000 WETH (18 decimals)
+        let price = reserves_to_price(3_000_000_000_000, 1_000 * 10u128.pow(18), 6, 18).unwrap().unwrap();
+        assert_close(price, 1.0 / 3_000.0, 1e-15);
+        assert_eq!(reserves_to_price(0, 10u128.pow(18), 6, 18).unwrap(), None);
+    }
+
+    #[test]
+    fn weth_usdc_price_keeps_the_quote_tokens_smallest_unit() {
+        // 1,000 WETH (18 decimals) against 3,456,789.123457 USDC (6 decimals)
+        let (weth, usdc) = (1_000 * 10u128.pow(18), 3_456_789_123_457u128);
+        let price = reserves_to_price(weth, usdc, 18, 6).unwrap().unwrap();
+        assert_close(price, 3_456.789_123_457, 1e-15);
+        // One more micro-USDC in the pool still shows in the price, 10^-9 higher
+        let moved = reserves_to_price(weth, usdc + 1, 18, 6).unwrap().unwrap();
+        assert_close(moved - price, 1e-9, 1e-3);
+    }
+
+    #[test]
+    fn equal_decimals_match_the_general_path() {
+        let (reserve0, reserve1) = (987_654_321_987_654_321_987u128, 123_456_789_123_456_789u128);
+        // reserve0 * 1000 at 9 decimals against reserve1 at 6 scales out to the same ratio
+        let general = reserves_to_price(reserve0 * 1000, reserve1, 9, 6).unwrap().unwrap();
+        for decimals in [6, 18, 200] {
+            assert_eq!(reserves_to_price(reserve0, reserve1, decimals, decimals).unwrap().unwrap(), general);
+        }
+    }
+
+    #[test]
+    fn oversized_decimals_are_an_error() {
+        let error = reserves_to_price(1, 1, 200, 6).unwrap_err();
+        assert!(error.to_string().contains("200"));
+    }
+
+    #[test]
+    fn fixed_point_price_holds_uint112_reserves() {
+        let max = (1u128 << 112) - 1;
+        let wad = U256::exp10(18);
+        assert_eq!(reserves_to_fixed_point(max, max, 18, 18, wad), Some(wad));
+        assert_eq!(reserves_to_fixed_point(1, max, 18, 18, U256::one()), Some(U256::from(max)));
+        assert_eq!(reserves_to_fixed_point(max, max, 6, 18, wad), Some(U256::exp10(6)));
+        assert_eq!(reserves_to_fixed_point(0, max, 18, 18, wad), None);
+    }
+
+    #[test]
+    fn matched_stable_pair_prices_near_one() {
+        // USDC/USDbC, both 6 decimals, 0.05% apart: the stable curve stays flat there
+        let price = stable_reserves_to_price(1_000_000_000_000, 1_000_500_000_000, 6, 6).unwrap();
+        assert_close(price, 1.0, 1e-9);
+    }
+
+    #[test]
+    fn imbalanced_stable_pool_prices_from_the_curve() {
+        // x = 1, y = 3: (3x²y + y³) / (x³ + 3xy²) = 36 / 28, well below the constant-product 3
+        let price = stable_reserves_to_price(10u128.pow(18), 3_000_000, 18, 6).unwrap();
+        assert_close(price, 9.0 / 7.0, 1e-12);
+        assert_eq!(stable_reserves_to_price(0, 3_000_000, 18, 6), None);
+    }
+
+    #[test]
+    fn stable_amount_out_keeps_the_invariant() {
+        let (x, y) = (1_000_000.0f64, 3_000_000.0f64);
+        let k = x.powi(3) * y + x * y.powi(3);
+        let out = stable_amount_out(10_000.0, x, y).unwrap();
+        let (x_after, y_after) = (x + 10_000.0, y - out);
+        assert_close(x_after.powi(3) * y_after + x_after * y_after.powi(3), k, 1e-12);
+
+        // A tiny trade fills at the marginal price
+        let tiny = stable_amount_out(1e-3, x, y).unwrap() / 1e-3;
+        assert_close(tiny, stable_spot_price(x, y).unwrap(), 1e-6);
+    }
+}
@@ -0,0 +1,29 @@
+use anyhow::{Context, Result};
+use std::io::{self, BufRead, Write};
+
+/// Ask for a value on stderr until `parse` accepts the answer. An empty answer takes `default`
+/// when there is one.
+pub fn ask<T>(label: &str, default: Option<&str>, parse: impl Fn(&str) -> Result<T>) -> Result<T> {
+    let mut stdin = io::stdin().lock();
+    loop {
+        match default {
+            Some(default) => eprint!("{} [{}]: ", label, default),
+            None => eprint!("{}: ", label),
+        }
+        io::stderr().flush()?;
+
+        let mut line = String::new();
+        if stdin.read_line(&mut line).context("Failed to read input")? == 0 {
+            anyhow::bail!("No input for {}", label);
+        }
+
+        let answer = match (line.trim(), default) {
+            ("", Some(default)) => default,
+            (answer, _) => answer,
+        };
+        match parse(answer) {
+            Ok(value) => return Ok(value),
+            Err(e) => eprintln!("   ❌ {:#}", e),
+        }
+    }
+}
@@ -0,0 +1,1962 @@
+use clap::ValueEnum;
+use ethers::types::{Address, U256};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{analytics, oracle, pricing, PricePoint};
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct TokenInfo {
+    #[schemars(with = "String")]
+    #[serde(serialize_with = "serialize_checksummed")]
+    pub address: Address,
+    pub symbol: String,
+    pub decimals: u8,
+}
+
+impl TokenInfo {
+    /// Placeholder metadata for tokens that weren't read from the chain
+    pub fn unknown(symbol: &str) -> Self {
+        Self {
+            address: Address::zero(),
+            symbol: symbol.to_string(),
+            decimals: 0,
+        }
+    }
+}
+
+/// Prices expressed as a percentage change from a reference block
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Baseline {
+    pub block: u64,
+    pub price: f64,
+    pub twap_pct: f64,
+    pub current_price_pct: f64,
+    pub min_price_pct: f64,
+    pub max_price_pct: f64,
+}
+
+/// How the pool's liquidity changed over the window, by constant-product invariant k
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct LiquidityStats {
+    pub current_k: f64,
+    pub min_k: f64,
+    pub max_k: f64,
+    /// Change in k from the first to the last sample
+    pub change_pct: f64,
+}
+
+impl LiquidityStats {
+    fn from_points(price_points: &[PricePoint], token0_decimals: u8, token1_decimals: u8) -> Option<Self> {
+        let ks: Vec<f64> = price_points
+            .iter()
+            .filter_map(|p| analytics::invariant_k(p, token0_decimals, token1_decimals))
+            .collect();
+        let (first, last) = (*ks.first()?, *ks.last()?);
+
+        Some(Self {
+            current_k: last,
+            min_k: ks.iter().copied().fold(f64::INFINITY, f64::min),
+            max_k: ks.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            change_pct: (last - first) / first * 100.0,
+        })
+    }
+}
+
+/// The pool TWAP next to an external price feed's TWAP over the same samples
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct OracleComparison {
+    #[schemars(with = "String")]
+    #[serde(serialize_with = "serialize_checksummed")]
+    pub feed: Address,
+    pub oracle_twap: f64,
+    /// (pool TWAP - oracle TWAP) / oracle TWAP
+    pub spread_pct: f64,
+}
+
+/// The pool's reserve accumulators at the first and last sample, and the TWAP they imply
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct AccumulatorCheck {
+    pub start_block: u64,
+    pub start_timestamp: u64,
+    pub end_block: u64,
+    pub end_timestamp: u64,
+    #[serde(serialize_with = "serialize_decimal")]
+    #[schemars(with = "String")]
+    pub reserve0_cumulative_start: U256,
+    #[serde(serialize_with = "serialize_decimal")]
+    #[schemars(with = "String")]
+    pub reserve1_cumulative_start: U256,
+    #[serde(serialize_with = "serialize_decimal")]
+    #[schemars(with = "String")]
+    pub reserve0_cumulative_end: U256,
+    #[serde(serialize_with = "serialize_decimal")]
+    #[schemars(with = "String")]
+    pub reserve1_cumulative_end: U256,
+    /// Time-averaged reserve1 over time-averaged reserve0, in token1 per token0
+    pub accumulator_twap: f64,
+    /// Sampled TWAP's difference from the accumulator TWAP
+    pub difference_pct: f64,
+}
+
+/// The last sample's reserve price against the price its block's accumulators imply, from
+/// --spot-divergence-check. The accumulators advance at the reserves from before the block's
+/// swaps, so a gap between the two means the block itself moved the price.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SpotDivergence {
+    pub block: u64,
+    /// reserve1 / reserve0 after the block, in token1 per token0
+    pub spot_price: f64,
+    /// Accumulated reserve1 over reserve0 across the block's interval
+    pub accumulator_price: f64,
+    /// Spot's difference from the accumulator-implied price
+    pub divergence_pct: f64,
+    pub threshold_pct: f64,
+    pub diverged: bool,
+}
+
+/// SHA-256 digests of the report from --hash, so a downstream system can check it wasn't altered
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Integrity {
+    pub algorithm: &'static str,
+    /// How the JSON was canonicalized before hashing
+    pub canonicalization: &'static str,
+    /// Digest of this JSON report with the `integrity` field left out
+    pub report_sha256: String,
+    /// Digest of the full `price_points` series, under --hash-samples
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub samples_sha256: Option<String>,
+}
+
+/// Summary prices as integers scaled by `scale`, for consumers doing integer math
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct FixedPointPrices {
+    #[serde(serialize_with = "serialize_decimal")]
+    #[schemars(with = "String")]
+    pub scale: U256,
+    #[serde(serialize_with = "serialize_decimal")]
+    #[schemars(with = "String")]
+    pub twap: U256,
+    #[serde(serialize_with = "serialize_decimal")]
+    #[schemars(with = "String")]
+    pub current_price: U256,
+    #[serde(serialize_with = "serialize_decimal")]
+    #[schemars(with = "String")]
+    pub min_price: U256,
+    #[serde(serialize_with = "serialize_decimal")]
+    #[schemars(with = "String")]
+    pub max_price: U256,
+}
+
+/// How long sampling and the observe() oracle took, and how far apart their TWAPs are
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Benchmark {
+    pub sampling_seconds: f64,
+    pub observe_seconds: f64,
+    /// None if the pool has no usable observe()
+    pub observe_twap: Option<f64>,
+    /// (sampled TWAP - observe TWAP) / observe TWAP
+    pub discrepancy_pct: Option<f64>,
+}
+
+/// Time-weighted execution price for swapping a fixed amount of token0 on a stable pool
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ExecutionPrice {
+    /// Amount of token0 swapped, in whole tokens
+    pub amount: f64,
+    /// TWAP of token1 received per token0 at each sample's reserves, before fees
+    pub twap: f64,
+    /// (execution TWAP - marginal TWAP) / marginal TWAP
+    pub price_impact_pct: f64,
+}
+
+/// Effective execution price of one trade size on the price impact curve
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ImpactPoint {
+    /// Amount of token0 sold, in whole tokens
+    pub amount: f64,
+    /// Token1 received, before fees
+    pub amount_out: f64,
+    pub effective_price: f64,
+    /// (effective price - spot) / spot
+    pub slippage_pct: f64,
+}
+
+/// Execution prices across trade sizes at the end block's reserves
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ImpactCurve {
+    pub block: u64,
+    /// Whether the stable (x³y + xy³) or volatile (xy) swap formula was used
+    pub stable: bool,
+    /// Marginal price the slippage is measured from
+    pub spot_price: f64,
+    pub points: Vec<ImpactPoint>,
+}
+
+/// How far the spot price and the TWAP are from a user-supplied reference price
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ReferencePrice {
+    pub price: f64,
+    /// (current price - reference) / reference
+    pub spot_deviation_pct: f64,
+    /// (TWAP - reference) / reference
+    pub twap_deviation_pct: f64,
+}
+
+/// How the TWAP was produced, gathered from the effective arguments so a saved report can be
+/// reproduced
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Methodology {
+    /// Where each sample's price came from: reserves, slot0, quoter, lp_wrapper or csv
+    pub price_source: &'static str,
+    /// Whether reserves fall back to token balances when getReserves reverts
+    pub balances_fallback: bool,
+    /// How the samples are averaged
+    pub mean: &'static str,
+    pub weighting: analytics::Weighting,
+    /// How sample blocks were chosen: uniform, log_spaced, timestamps, daily_boundaries, swap_events,
+    /// block_hashes or csv
+    pub sampling: &'static str,
+    pub samples: u64,
+    /// Blocks between uniformly planned samples
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval_blocks: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jitter_blocks: Option<u64>,
+    pub warmup_samples: u64,
+    /// Samples dropped or altered before averaging, e.g. suspected manipulation
+    pub filtering: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub round_to: Option<f64>,
+    /// TOML file --export-config wrote the run's full settings to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub settings_file: Option<String>,
+    pub window_start_block: u64,
+    pub window_start_timestamp: u64,
+    pub window_end_block: u64,
+    pub window_end_timestamp: u64,
+}
+
+/// How much of the window a run stopped by --max-duration covered
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PartialCoverage {
+    /// Last block sampled before the deadline
+    pub collected_through_block: u64,
+    /// Share of the window's blocks, from its start, that were sampled
+    pub window_covered_pct: f64,
+}
+
+/// How much of the window a --weekday-filter or --time-of-day-filter kept
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SessionCoverage {
+    /// The filter, as given
+    pub filter: String,
+    /// Seconds of the window inside the session, which the TWAP is averaged over
+    pub included_seconds: u64,
+    pub window_seconds: u64,
+    /// Samples taken outside the session, left out of the statistics
+    pub excluded_samples: usize,
+    /// Average over the session's time, which becomes the report's TWAP
+    #[serde(skip)]
+    pub twap: f64,
+}
+
+/// Samples --min-reserve-treat-as-gap left out of the TWAP for thin reserves
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ReserveGaps {
+    /// Whole tokens either reserve had to stay at or above
+    pub threshold: f64,
+    pub gap_samples: usize,
+    /// Seconds from each gap sample to the next sample, left out of the TWAP's weighting
+    pub gap_seconds: u64,
+    pub window_seconds: u64,
+    /// Average over the time outside the gaps, which becomes the report's TWAP
+    #[serde(skip)]
+    pub twap: f64,
+}
+
+/// TWAP of a --smooth moving average of the prices, next to the unsmoothed one
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Smoothing {
+    /// Samples averaged around each one
+    pub window: usize,
+    /// The TWAP of the prices as sampled; the report's TWAP is the smoothed one
+    pub raw_twap: f64,
+}
+
+/// Prices --clamp-range capped for display
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ClampedPrices {
+    pub min: f64,
+    pub max: f64,
+    /// Samples whose price was outside the range
+    pub samples: usize,
+}
+
+/// Set when --pair-inverse-threshold flipped the pair, so token0 and token1 are the pool's token1
+/// and token0
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct AutoInversion {
+    pub threshold: f64,
+    /// The pool's own spot price (its token1 per token0) that fell below the threshold
+    pub raw_spot_price: f64,
+}
+
+/// The window actually sampled, when --days reached back before the pool existed
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ClampedWindow {
+    /// Block the pool was created at, the effective start of the window
+    pub start_block: u64,
+    pub requested_days: u64,
+    pub effective_days: f64,
+    /// Blocks between samples after spreading them over the shorter window
+    pub interval_blocks: u64,
+}
+
+/// Which planned block a --sample-retry-different-block sample stands in for
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, JsonSchema)]
+pub struct Substitution {
+    pub requested_block: u64,
+    /// Seconds from the requested block's timestamp to the sampled one's
+    pub timestamp_offset: i64,
+}
+
+/// A sample read from an adjacent block because its planned one was unreadable
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct SubstitutedBlock {
+    pub requested_block: u64,
+    pub block: u64,
+    pub timestamp_offset: i64,
+}
+
+/// The blocks the samples actually spanned against the block range the requested days should
+/// take at the assumed block time, from --block-span
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct BlockSpan {
+    pub first_block: u64,
+    pub last_block: u64,
+    pub spanned_blocks: u64,
+    /// Requested days over the assumed block time
+    pub requested_blocks: u64,
+    pub assumed_block_time: f64,
+    /// The spanned blocks as a share of the requested ones
+    pub spanned_pct: f64,
+    /// Seconds between the first and last sample over the blocks between them
+    pub average_block_time: Option<f64>,
+}
+
+/// The fields of a saved JSON report that --diff compares against
+#[derive(Debug, Deserialize)]
+pub struct PreviousReport {
+    pub pool: Address,
+    pub end_block: Option<u64>,
+    #[serde(deserialize_with = "deserialize_number")]
+    pub twap: f64,
+    #[serde(deserialize_with = "deserialize_number")]
+    pub deviation_pct: f64,
+    pub liquidity: Option<PreviousLiquidity>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreviousLiquidity {
+    #[serde(deserialize_with = "deserialize_number")]
+    pub current_k: f64,
+}
+
+/// Changes since a previously saved report
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct ReportDiff {
+    pub previous_end_block: Option<u64>,
+    pub previous_twap: f64,
+    /// (TWAP - previous TWAP) / previous TWAP
+    pub twap_change_pct: f64,
+    /// Deviation now minus deviation then, in percentage points
+    pub deviation_change_pp: f64,
+    /// Change in current k, when both reports have liquidity
+    pub liquidity_change_pct: Option<f64>,
+}
+
+/// The two pools a triangulated price was derived from
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct CrossInfo {
+    #[schemars(with = "String")]
+    #[serde(serialize_with = "serialize_checksummed")]
+    pub pool_a: Address,
+    #[schemars(with = "String")]
+    #[serde(serialize_with = "serialize_checksummed")]
+    pub pool_b: Address,
+    /// Token shared by both pools
+    pub via: TokenInfo,
+    /// Each pool's own TWAP, in its native token1-per-token0 direction
+    pub pool_a_twap: f64,
+    pub pool_b_twap: f64,
+}
+
+/// One pool's stretch of a --pool-sequence series
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct PoolSegment {
+    #[schemars(with = "String")]
+    #[serde(serialize_with = "serialize_checksummed")]
+    pub pool: Address,
+    /// Block the pool is authoritative from
+    pub from_block: u64,
+    /// Samples read from this pool
+    pub samples: usize,
+}
+
+/// Everything the tool computed for one pool and window
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct TwapReport {
+    #[schemars(with = "String")]
+    #[serde(serialize_with = "serialize_checksummed")]
+    pub pool: Address,
+    /// The pool contract's name(), like "vAMM-WETH/USDC", when it has one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_name: Option<String>,
+    pub token0: TokenInfo,
+    pub token1: TokenInfo,
+    pub days: u64,
+    pub samples: u64,
+    /// Chain head the window ended at, when the samples came from the chain
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_block: Option<u64>,
+    /// Samples the statistics were computed from
+    pub collected_samples: usize,
+    pub twap: f64,
+    /// What the TWAP's intervals were weighted by
+    pub weighting: analytics::Weighting,
+    pub current_price: f64,
+    pub min_price: f64,
+    pub max_price: f64,
+    pub price_range_pct: f64,
+    pub deviation_pct: f64,
+    pub annualized_volatility_pct: Option<f64>,
+    /// Set under --realized-variance
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub realized_variance: Option<analytics::RealizedVariance>,
+    /// Set under --max-drawdown
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_drawdown: Option<analytics::Drawdown>,
+    /// Set under --manipulation-cost
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manipulation_cost: Option<analytics::ManipulationCost>,
+    /// Set under --twap-of-twaps
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub twap_of_twaps: Option<analytics::TwapOfTwaps>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub liquidity: Option<LiquidityStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baseline: Option<Baseline>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cross: Option<CrossInfo>,
+    /// Pools the series was stitched from, oldest first
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pool_sequence: Option<Vec<PoolSegment>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub oracle: Option<OracleComparison>,
+    /// Set under --accumulator-check
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accumulator: Option<AccumulatorCheck>,
+    /// Set under --spot-divergence-check
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spot_divergence: Option<SpotDivergence>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub benchmark: Option<Benchmark>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub execution: Option<ExecutionPrice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub impact_curve: Option<ImpactCurve>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fixed_point: Option<FixedPointPrices>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diff: Option<ReportDiff>,
+    /// Subgraph the prices came from instead of the chain, when the RPC lacked the history
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subgraph: Option<String>,
+    /// Set by --reference-price
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference: Option<ReferencePrice>,
+    /// Set when the run hit --max-duration and the statistics cover only part of the window
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub partial: Option<PartialCoverage>,
+    /// Set when a session filter limited the TWAP to some wall-clock time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<SessionCoverage>,
+    /// Set under --clamp-range
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clamped_prices: Option<ClampedPrices>,
+    /// Set under --smooth
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub smoothing: Option<Smoothing>,
+    /// Set under --min-reserve-treat-as-gap
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reserve_gaps: Option<ReserveGaps>,
+    /// Set when --pair-inverse-threshold quoted the pair the other way round
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auto_inverted: Option<AutoInversion>,
+    /// Set under --hash; covers every other field
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<Integrity>,
+    /// Set when the window was clamped to the pool's creation
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clamped_window: Option<ClampedWindow>,
+    /// Samples --sample-retry-different-block took from an adjacent block
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub block_substitutions: Vec<SubstitutedBlock>,
+    /// Set under --block-span
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_span: Option<BlockSpan>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub methodology: Option<Methodology>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub price_points: Vec<PricePoint>,
+    /// Block and timestamp of the window's first sample, kept for the methodology after
+    /// streaming has dropped it
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub first_sample: Option<(u64, u64)>,
+}
+
+impl TwapReport {
+    /// Compute the summary statistics for a non-empty, time-ordered series of samples
+    pub fn new(
+        pool: Address,
+        token0: TokenInfo,
+        token1: TokenInfo,
+        days: u64,
+        samples: u64,
+        price_points: Vec<PricePoint>,
+    ) -> Self {
+        let twap = analytics::compute_twap(&price_points);
+
+        // Calculate current price (spot price)
+        let current_price = price_points.last().map_or(0.0, |p| p.price);
+
+        // Calculate min and max prices
+        let min_price = price_points.iter().map(|p| p.price).fold(f64::INFINITY, f64::min);
+        let max_price = price_points.iter().map(|p| p.price).fold(f64::NEG_INFINITY, f64::max);
+
+        let liquidity = LiquidityStats::from_points(&price_points, token0.decimals, token1.decimals);
+        let first_sample = price_points.first().map(|p| (p.block, p.timestamp));
+        let block_substitutions = price_points
+            .iter()
+            .filter_map(|p| {
+                let substitution = p.substitution?;
+                Some(SubstitutedBlock {
+                    requested_block: substitution.requested_block,
+                    block: p.block,
+                    timestamp_offset: substitution.timestamp_offset,
+                })
+            })
+            .collect();
+
+        Self {
+            pool,
+            pool_name: None,
+            token0,
+            token1,
+            days,
+            samples,
+            end_block: None,
+            collected_samples: price_points.len(),
+            twap,
+            weighting: analytics::Weighting::Time,
+            current_price,
+            min_price,
+            max_price,
+            price_range_pct: (max_price - min_price) / min_price * 100.0,
+            deviation_pct: (current_price - twap) / twap * 100.0,
+            annualized_volatility_pct: analytics::annualized_volatility(&price_points),
+            realized_variance: None,
+            max_drawdown: None,
+            manipulation_cost: None,
+            twap_of_twaps: None,
+            liquidity,
+            baseline: None,
+            cross: None,
+            pool_sequence: None,
+            oracle: None,
+            accumulator: None,
+            spot_divergence: None,
+            benchmark: None,
+            execution: None,
+            impact_curve: None,
+            fixed_point: None,
+            diff: None,
+            reference: None,
+            subgraph: None,
+            partial: None,
+            session: None,
+            clamped_prices: None,
+            smoothing: None,
+            reserve_gaps: None,
+            auto_inverted: None,
+            integrity: None,
+            clamped_window: None,
+            block_span: None,
+            block_substitutions,
+            methodology: None,
+            price_points,
+            first_sample,
+        }
+    }
+
+    /// Build the report from a streamed aggregate; only the last sample is kept in `price_points`
+    pub fn from_stream(
+        pool: Address,
+        token0: TokenInfo,
+        token1: TokenInfo,
+        days: u64,
+        samples: u64,
+        stream: analytics::StreamingTwap,
+    ) -> Self {
+        let twap = stream.twap();
+        let current_price = stream.last.as_ref().map_or(0.0, |p| p.price);
+        let (min_price, max_price) = (stream.min_price, stream.max_price);
+
+        let liquidity = match (stream.first_k, stream.last_k) {
+            (Some(first), Some(last)) => Some(LiquidityStats {
+                current_k: last,
+                min_k: stream.min_k,
+                max_k: stream.max_k,
+                change_pct: (last - first) / first * 100.0,
+            }),
+            _ => None,
+        };
+
+        Self {
+            pool,
+            pool_name: None,
+            token0,
+            token1,
+            days,
+            samples,
+            end_block: None,
+            collected_samples: stream.count,
+            twap,
+            weighting: analytics::Weighting::Time,
+            current_price,
+            min_price,
+            max_price,
+            price_range_pct: (max_price - min_price) / min_price * 100.0,
+            deviation_pct: (current_price - twap) / twap * 100.0,
+            annualized_volatility_pct: stream.annualized_volatility(),
+            realized_variance: None,
+            max_drawdown: None,
+            manipulation_cost: None,
+            twap_of_twaps: None,
+            liquidity,
+            baseline: None,
+            cross: None,
+            pool_sequence: None,
+            oracle: None,
+            accumulator: None,
+            spot_divergence: None,
+            benchmark: None,
+            execution: None,
+            impact_curve: None,
+            fixed_point: None,
+            diff: None,
+            reference: None,
+            subgraph: None,
+            partial: None,
+            session: None,
+            clamped_prices: None,
+            smoothing: None,
+            reserve_gaps: None,
+            auto_inverted: None,
+            integrity: None,
+            clamped_window: None,
+            block_span: None,
+            block_substitutions: Vec::new(),
+            methodology: None,
+            price_points: stream.last.into_iter().collect(),
+            first_sample: stream.first,
+        }
+    }
+
+    /// Add the interval from a warmup sample before the window to the TWAP, weighted by the mean
+    /// of the warmup and first window prices. Min/max and the other statistics still only cover the window.
+    pub fn include_leading_interval(&mut self, leading: &PricePoint) {
+        let (Some(first), Some(last)) = (self.price_points.first(), self.price_points.last()) else {
+            return;
+        };
+        let window_time = (last.timestamp - first.timestamp) as f64;
+        let leading_time = first.timestamp.saturating_sub(leading.timestamp) as f64;
+        if leading_time == 0.0 {
+            return;
+        }
+
+        let leading_price = (leading.price + first.price) / 2.0;
+        self.twap = (self.twap * window_time + leading_price * leading_time) / (window_time + leading_time);
+        self.deviation_pct = (self.current_price - self.twap) / self.twap * 100.0;
+    }
+
+    /// Replace the TWAP with one averaged over a session filter's time only
+    pub fn set_session(&mut self, coverage: SessionCoverage) {
+        self.twap = coverage.twap;
+        self.deviation_pct = (self.current_price - self.twap) / self.twap * 100.0;
+        self.session = Some(coverage);
+    }
+
+    /// The pool's name(), or its token symbols as `TOKEN0/TOKEN1` when it has none
+    pub fn pool_label(&self) -> String {
+        match &self.pool_name {
+            Some(name) => name.clone(),
+            None => format!("{}/{}", self.token0.symbol, self.token1.symbol),
+        }
+    }
+
+    /// Replace the TWAP with that of a `window`-sample moving average of the prices, keeping the
+    /// sampled prices themselves as they are for export
+    pub fn set_smoothing(&mut self, window: usize) {
+        let smoothed = analytics::moving_average(&self.price_points, window);
+        let raw_twap = self.twap;
+        self.twap = analytics::weighted_twap(&smoothed, self.weighting);
+        self.deviation_pct = (self.current_price - self.twap) / self.twap * 100.0;
+        self.smoothing = Some(Smoothing { window, raw_twap });
+    }
+
+    /// Replace the TWAP with one that leaves out the time after thin-reserve samples
+    pub fn set_reserve_gaps(&mut self, gaps: ReserveGaps) {
+        self.twap = gaps.twap;
+        self.deviation_pct = (self.current_price - self.twap) / self.twap * 100.0;
+        self.reserve_gaps = Some(gaps);
+    }
+
+    /// Record the changes since a previous report of the same pool
+    pub fn set_diff(&mut self, previous: &PreviousReport) {
+        let liquidity_change_pct = match (&self.liquidity, &previous.liquidity) {
+            (Some(now), Some(then)) => Some((now.current_k - then.current_k) / then.current_k * 100.0),
+            _ => None,
+        };
+        self.diff = Some(ReportDiff {
+            previous_end_block: previous.end_block,
+            previous_twap: previous.twap,
+            twap_change_pct: (self.twap - previous.twap) / previous.twap * 100.0,
+            deviation_change_pp: self.deviation_pct - previous.deviation_pct,
+            liquidity_change_pct,
+        });
+    }
+
+    /// Label the report as covering only the window up to its last sample
+    pub fn mark_partial(&mut self, window_start_block: u64, end_block: u64) {
+        let collected_through_block = self.price_points.last().map_or(window_start_block, |p| p.block);
+        let span = end_block.saturating_sub(window_start_block).max(1);
+        self.partial = Some(PartialCoverage {
+            collected_through_block,
+            window_covered_pct: collected_through_block.saturating_sub(window_start_block) as f64 / span as f64 * 100.0,
+        });
+    }
+
+    /// Compare the sampled blocks with the range the requested days span at `assumed_block_time`
+    pub fn set_block_span(&mut self, assumed_block_time: f64) {
+        let (Some(first), Some(last)) = (self.price_points.first(), self.price_points.last()) else {
+            return;
+        };
+        let spanned_blocks = last.block - first.block;
+        let requested_blocks = (self.days as f64 * 86400.0 / assumed_block_time) as u64;
+        self.block_span = Some(BlockSpan {
+            first_block: first.block,
+            last_block: last.block,
+            spanned_blocks,
+            requested_blocks,
+            assumed_block_time,
+            spanned_pct: spanned_blocks as f64 / requested_blocks.max(1) as f64 * 100.0,
+            average_block_time: (spanned_blocks > 0).then(|| (last.timestamp - first.timestamp) as f64 / spanned_blocks as f64),
+        });
+    }
+
+    /// Record the sampling-vs-observe() comparison
+    pub fn set_benchmark(&mut self, sampling_seconds: f64, observe_seconds: f64, observe_twap: Option<f64>) {
+        self.benchmark = Some(Benchmark {
+            sampling_seconds,
+            observe_seconds,
+            observe_twap,
+            discrepancy_pct: observe_twap.map(|observed| (self.twap - observed) / observed * 100.0),
+        });
+    }
+
+    /// Recompute the summary prices as integers scaled by `scale`, from each sample's reserves or
+    /// sqrtPriceX96. Fails if a sample has neither, e.g. quoted prices.
+    pub fn set_fixed_point(&mut self, scale: U256) -> anyhow::Result<()> {
+        let (decimals0, decimals1) = (self.token0.decimals, self.token1.decimals);
+        let mut prices = Vec::with_capacity(self.price_points.len());
+        for point in &self.price_points {
+            let price = match (point.reserve0, point.reserve1, point.sqrt_price_x96) {
+                (Some(reserve0), Some(reserve1), _) => {
+                    pricing::reserves_to_fixed_point(reserve0, reserve1, decimals0, decimals1, scale)
+                }
+                (_, _, Some(sqrt_price_x96)) => {
+                    pricing::sqrt_price_x96_to_fixed_point(sqrt_price_x96, decimals0, decimals1, scale)
+                }
+                _ => anyhow::bail!("Sample at block {} has no reserves or sqrtPriceX96 to price in fixed point", point.block),
+            };
+            let price = price.ok_or_else(|| anyhow::anyhow!("Fixed-point price at block {} overflows", point.block))?;
+            prices.push((point.timestamp, price));
+        }
+
+        let Some(&(_, current_price)) = prices.last() else {
+            return Ok(());
+        };
+        self.fixed_point = Some(FixedPointPrices {
+            scale,
+            twap: analytics::fixed_point_twap(&prices).unwrap_or(current_price),
+            current_price,
+            min_price: prices.iter().map(|&(_, price)| price).min().unwrap_or(current_price),
+            max_price: prices.iter().map(|&(_, price)| price).max().unwrap_or(current_price),
+        });
+        Ok(())
+    }
+
+    /// Cap the sample prices and the current, min and max prices into [min, max]. Only for
+    /// output; the TWAP and the other statistics were already computed from the raw prices.
+    pub fn clamp_prices(&mut self, min: f64, max: f64) {
+        let samples = self.price_points.iter().filter(|point| !(min..=max).contains(&point.price)).count();
+        for price in self.price_points.iter_mut().map(|point| &mut point.price)
+            .chain([&mut self.current_price, &mut self.min_price, &mut self.max_price])
+        {
+            *price = price.clamp(min, max);
+        }
+        self.clamped_prices = Some(ClampedPrices { min, max, samples });
+    }
+
+    /// Snap every reported price to a tick size. Only for output; the statistics were already
+    /// computed from the raw prices.
+    pub fn snap_prices(&mut self, tick: f64) {
+        let snap = |price: &mut f64| *price = pricing::snap_to_tick(*price, tick);
+
+        snap(&mut self.twap);
+        snap(&mut self.current_price);
+        snap(&mut self.min_price);
+        snap(&mut self.max_price);
+        for point in &mut self.price_points {
+            snap(&mut point.price);
+        }
+        if let Some(baseline) = &mut self.baseline {
+            snap(&mut baseline.price);
+        }
+        if let Some(cross) = &mut self.cross {
+            snap(&mut cross.pool_a_twap);
+            snap(&mut cross.pool_b_twap);
+        }
+        if let Some(oracle) = &mut self.oracle {
+            snap(&mut oracle.oracle_twap);
+        }
+        if let Some(execution) = &mut self.execution {
+            snap(&mut execution.twap);
+        }
+        if let Some(curve) = &mut self.impact_curve {
+            snap(&mut curve.spot_price);
+            for point in &mut curve.points {
+                snap(&mut point.effective_price);
+            }
+        }
+        if let Some(observe_twap) = self.benchmark.as_mut().and_then(|b| b.observe_twap.as_mut()) {
+            snap(observe_twap);
+        }
+    }
+
+    /// Express the summary prices relative to the price at a baseline block
+    pub fn set_baseline(&mut self, block: u64, price: f64) {
+        let relative = |value: f64| (value - price) / price * 100.0;
+        self.baseline = Some(Baseline {
+            block,
+            price,
+            twap_pct: relative(self.twap),
+            current_price_pct: relative(self.current_price),
+            min_price_pct: relative(self.min_price),
+            max_price_pct: relative(self.max_price),
+        });
+    }
+
+    /// Recompute the TWAP with a different interval weighting
+    pub fn set_weighting(&mut self, weighting: analytics::Weighting) {
+        self.weighting = weighting;
+        self.twap = analytics::weighted_twap(&self.price_points, weighting);
+        self.deviation_pct = (self.current_price - self.twap) / self.twap * 100.0;
+    }
+
+    /// Compute the execution price of selling each of `amounts` of token0 into the last sample's
+    /// reserves, using the stable or volatile swap formula. Fails if that sample has no reserves.
+    pub fn set_impact_curve(&mut self, amounts: &[f64], stable: bool) -> anyhow::Result<()> {
+        let last = self.price_points.last().ok_or_else(|| anyhow::anyhow!("No samples to compute an impact curve from"))?;
+        let (Some(reserve0), Some(reserve1)) = (last.reserve0, last.reserve1) else {
+            anyhow::bail!("Sample at block {} has no reserves to compute an impact curve from", last.block);
+        };
+        let reserve0 = reserve0 as f64 / 10f64.powi(self.token0.decimals as i32);
+        let reserve1 = reserve1 as f64 / 10f64.powi(self.token1.decimals as i32);
+
+        let empty = || anyhow::anyhow!("Pool had empty reserves at block {}", last.block);
+        let spot_price = if stable {
+            pricing::stable_spot_price(reserve0, reserve1).ok_or_else(empty)?
+        } else {
+            reserve1 / reserve0
+        };
+        let mut points = Vec::with_capacity(amounts.len());
+        for &amount in amounts {
+            let amount_out = if stable {
+                pricing::stable_amount_out(amount, reserve0, reserve1)
+            } else {
+                pricing::volatile_amount_out(amount, reserve0, reserve1)
+            }
+            .ok_or_else(empty)?;
+            let effective_price = amount_out / amount;
+            points.push(ImpactPoint {
+                amount,
+                amount_out,
+                effective_price,
+                slippage_pct: (effective_price - spot_price) / spot_price * 100.0,
+            });
+        }
+
+        self.impact_curve = Some(ImpactCurve {
+            block: last.block,
+            stable,
+            spot_price,
+            points,
+        });
+        Ok(())
+    }
+
+    /// Measure the spot price and the TWAP against an external reference price
+    pub fn set_reference_price(&mut self, price: f64) {
+        self.reference = Some(ReferencePrice {
+            price,
+            spot_deviation_pct: (self.current_price - price) / price * 100.0,
+            twap_deviation_pct: (self.twap - price) / price * 100.0,
+        });
+    }
+
+    /// Compute the time-weighted execution price of swapping `amount` of token0 on a stable pool,
+    /// from each sample's reserves. Fails if a sample has no reserves.
+    pub fn set_execution_price(&mut self, amount: f64) -> anyhow::Result<()> {
+        let (decimals0, decimals1) = (self.token0.decimals as i32, self.token1.decimals as i32);
+        let mut series = Vec::with_capacity(self.price_points.len());
+        for point in &self.price_points {
+            let (Some(reserve0), Some(reserve1)) = (point.reserve0, point.reserve1) else {
+                anyhow::bail!("Sample at block {} has no reserves to compute an execution price from", point.block);
+            };
+            let reserve0 = reserve0 as f64 / 10f64.powi(decimals0);
+            let reserve1 = reserve1 as f64 / 10f64.powi(decimals1);
+            let amount_out = pricing::stable_amount_out(amount, reserve0, reserve1)
+                .ok_or_else(|| anyhow::anyhow!("Pool had empty reserves at block {}", point.block))?;
+            series.push(PricePoint {
+                block: point.block,
+                timestamp: point.timestamp,
+                price: amount_out / amount,
+                ..Default::default()
+            });
+        }
+
+        let twap = analytics::weighted_twap(&series, self.weighting);
+        self.execution = Some(ExecutionPrice {
+            amount,
+            twap,
+            price_impact_pct: (twap - self.twap) / self.twap * 100.0,
+        });
+        Ok(())
+    }
+
+    /// Record the accumulator-implied TWAP between two accumulator reads. Returns false, recording
+    /// nothing, if the accumulators didn't advance.
+    pub fn set_accumulator_check(&mut self, start: oracle::Accumulators, end: oracle::Accumulators) -> bool {
+        let (Some(delta0), Some(delta1)) = (
+            end.reserve0_cumulative.checked_sub(start.reserve0_cumulative),
+            end.reserve1_cumulative.checked_sub(start.reserve1_cumulative),
+        ) else {
+            return false;
+        };
+        if delta0.is_zero() || delta1.is_zero() {
+            return false;
+        }
+
+        let average0 = pricing::u256_to_f64(delta0) / 10f64.powi(self.token0.decimals as i32);
+        let average1 = pricing::u256_to_f64(delta1) / 10f64.powi(self.token1.decimals as i32);
+        let accumulator_twap = average1 / average0;
+        self.accumulator = Some(AccumulatorCheck {
+            start_block: start.block,
+            start_timestamp: start.timestamp,
+            end_block: end.block,
+            end_timestamp: end.timestamp,
+            reserve0_cumulative_start: start.reserve0_cumulative,
+            reserve1_cumulative_start: start.reserve1_cumulative,
+            reserve0_cumulative_end: end.reserve0_cumulative,
+            reserve1_cumulative_end: end.reserve1_cumulative,
+            accumulator_twap,
+            difference_pct: (self.twap - accumulator_twap) / accumulator_twap * 100.0,
+        });
+        true
+    }
+
+    /// Compare the last sample's reserve price with the price implied by accumulators read just
+    /// before and at its block. None if the sample has no reserves or the accumulators didn't advance.
+    pub fn set_spot_divergence(
+        &mut self,
+        before: oracle::Accumulators,
+        after: oracle::Accumulators,
+        threshold_pct: f64,
+    ) -> Option<&SpotDivergence> {
+        let last = self.price_points.last()?;
+        let (reserve0, reserve1) = (last.reserve0?, last.reserve1?);
+        let delta0 = after.reserve0_cumulative.checked_sub(before.reserve0_cumulative).filter(|d| !d.is_zero())?;
+        let delta1 = after.reserve1_cumulative.checked_sub(before.reserve1_cumulative).filter(|d| !d.is_zero())?;
+        if reserve0 == 0 {
+            return None;
+        }
+
+        let scale = 10f64.powi(self.token0.decimals as i32 - self.token1.decimals as i32);
+        let spot_price = reserve1 as f64 / reserve0 as f64 * scale;
+        let accumulator_price = pricing::u256_to_f64(delta1) / pricing::u256_to_f64(delta0) * scale;
+        let divergence_pct = (spot_price - accumulator_price) / accumulator_price * 100.0;
+        self.spot_divergence = Some(SpotDivergence {
+            block: last.block,
+            spot_price,
+            accumulator_price,
+            divergence_pct,
+            threshold_pct,
+            diverged: divergence_pct.abs() > threshold_pct,
+        });
+        self.spot_divergence.as_ref()
+    }
+
+    /// Record an external feed's TWAP over the same window
+    pub fn set_oracle(&mut self, feed: Address, oracle_twap: f64) {
+        self.oracle = Some(OracleComparison {
+            feed,
+            oracle_twap,
+            spread_pct: (self.twap - oracle_twap) / oracle_twap * 100.0,
+        });
+    }
+
+    /// One summary number, None when the report doesn't have it (e.g. volatility from one sample)
+    pub fn metric(&self, metric: Metric) -> Option<f64> {
+        match metric {
+            Metric::Twap => Some(self.twap),
+            Metric::Spot => Some(self.current_price),
+            Metric::Min => Some(self.min_price),
+            Metric::Max => Some(self.max_price),
+            Metric::Median => analytics::median_price(&self.price_points),
+            Metric::Volatility => self.annualized_volatility_pct,
+            Metric::Deviation => Some(self.deviation_pct),
+            Metric::Range => Some(self.price_range_pct),
+        }
+    }
+
+    pub fn metrics_view<'a>(&'a self, metrics: &'a [Metric]) -> MetricsView<'a> {
+        MetricsView { report: self, metrics }
+    }
+
+    /// Print only the --metrics, one per line, in the order asked for
+    pub fn print_metrics(&self, metrics: &[Metric]) {
+        let (base, quote) = (&self.token0.symbol, &self.token1.symbol);
+        for &metric in metrics {
+            let value = self.metric(metric);
+            let shown = match (metric, value) {
+                (_, None) => "n/a".to_string(),
+                (Metric::Volatility | Metric::Deviation | Metric::Range, Some(pct)) => format!("{:.2}%", pct),
+                (_, Some(price)) => format!("{} {} per {}", format_price(price), quote, base),
+            };
+            println!("{}: {}", metric.key(), shown);
+        }
+    }
+
+    /// Print the human-readable results block
+    pub fn print_text(&self) {
+        let (base, quote) = (&self.token0.symbol, &self.token1.symbol);
+
+        println!("📈 RESULTS");
+        println!("═══════════════════════════════════════");
+        if self.cross.is_none() && self.pool_sequence.is_none() && !self.pool.is_zero() {
+            println!("🏷️  {} ({})", self.pool_label(), checksum(&self.pool));
+        }
+        if let Some(partial) = &self.partial {
+            println!("⏰ PARTIAL: --max-duration hit after {:.1}% of the window (through block {})",
+                partial.window_covered_pct, partial.collected_through_block);
+        }
+        if let Some(session) = &self.session {
+            println!("🗓️  SESSION ({}): TWAP over {:.1} of {:.1} days, {} samples outside it excluded", session.filter,
+                session.included_seconds as f64 / 86400.0, session.window_seconds as f64 / 86400.0, session.excluded_samples);
+        }
+        if let Some(inversion) = &self.auto_inverted {
+            println!("🔄 AUTO-INVERTED: quoted {} per {}, since the pool's {} per {} price {} is below {}",
+                quote, base, base, quote, format_price(inversion.raw_spot_price), inversion.threshold);
+        }
+        if let Some(gaps) = &self.reserve_gaps {
+            println!("🕳️  RESERVE GAPS: {} samples below {} tokens of reserves, {} of {} left out of the TWAP",
+                gaps.gap_samples, gaps.threshold, crate::format_duration(gaps.gap_seconds), crate::format_duration(gaps.window_seconds));
+        }
+        if let Some(clamped) = self.clamped_prices.as_ref().filter(|clamped| clamped.samples > 0) {
+            println!("🗜️  CLAMPED: {} sample prices capped into [{}, {}]; current/min/max are shown capped, the TWAP is not",
+                clamped.samples, clamped.min, clamped.max);
+        }
+        if let Some(url) = &self.subgraph {
+            println!("🛰️  SUBGRAPH-SOURCED: hourly prices from {}, not read from the chain (blocks are estimates)", url);
+        }
+        if let Some(clamped) = &self.clamped_window {
+            println!("🪟 Window clamped to the pool's creation at block {}: {:.1} of {} days",
+                clamped.start_block, clamped.effective_days, clamped.requested_days);
+        }
+        if !self.block_substitutions.is_empty() {
+            println!("🔁 {} samples read from an adjacent block, their planned one being unreadable:", self.block_substitutions.len());
+            for substituted in &self.block_substitutions {
+                println!("   Block {} instead of {} ({:+}s)", substituted.block, substituted.requested_block, substituted.timestamp_offset);
+            }
+        }
+        if let Some(span) = &self.block_span {
+            println!("🧱 Blocks {}-{}: {} blocks, {:.1}% of the ~{} that {} days take at {}s per block",
+                span.first_block, span.last_block, span.spanned_blocks, span.spanned_pct, span.requested_blocks, self.days,
+                span.assumed_block_time);
+            if let Some(block_time) = span.average_block_time {
+                println!("   Blocks came every {:.3}s on average over the samples", block_time);
+            }
+        }
+        if let Some(cross) = &self.cross {
+            println!("🔀 Triangulated {} per {} via {}", quote, base, cross.via.symbol);
+            println!("   Pool A {} TWAP: {}", checksum(&cross.pool_a), format_price(cross.pool_a_twap));
+            println!("   Pool B {} TWAP: {}", checksum(&cross.pool_b), format_price(cross.pool_b_twap));
+        }
+        if let Some(sequence) = &self.pool_sequence {
+            println!("🔗 Stitched across {} pools", sequence.len());
+            for segment in sequence {
+                println!("   {} from block {} ({} samples)", checksum(&segment.pool), segment.from_block, segment.samples);
+            }
+        }
+        println!("🎯 {}-Day TWAP: {} {} per {}", self.days, format_price(self.twap), quote, base);
+        match self.weighting {
+            analytics::Weighting::Time => println!("⚖️  Weighted by: time"),
+            analytics::Weighting::Blocks => println!("⚖️  Weighted by: block count"),
+        }
+        if let Some(smoothing) = &self.smoothing {
+            println!("〰️  Smoothed over {} samples; unsmoothed TWAP: {} {} per {}", smoothing.window,
+                format_price(smoothing.raw_twap), quote, base);
+        }
+        match self.price_points.last() {
+            Some(last) => println!("💵 Current Price: {} {} per {} (block {})", format_price(self.current_price), quote, base, last.block),
+            None => println!("💵 Current Price: {} {} per {}", format_price(self.current_price), quote, base),
+        }
+        println!("📊 Min Price: {}", format_price(self.min_price));
+        println!("📊 Max Price: {}", format_price(self.max_price));
+        println!("📉 Price Range: {:.2}%", self.price_range_pct);
+        println!("📍 Deviation from TWAP: {:.2}%", self.deviation_pct);
+        if let Some(reference) = &self.reference {
+            println!("🧭 Reference Price: {} (spot {:+.2}%, TWAP {:+.2}%)",
+                format_price(reference.price), reference.spot_deviation_pct, reference.twap_deviation_pct);
+        }
+        if let Some(volatility) = self.annualized_volatility_pct {
+            println!("🌪️  Annualized Volatility: {:.2}%", volatility);
+        }
+        if let Some(realized) = &self.realized_variance {
+            println!("🎲 Realized Variance: {:.8} over the window, {:.8} annualized ({} returns)",
+                realized.window_variance, realized.annualized_variance, realized.returns);
+            println!("🎲 Realized Volatility: {:.2}% ({}-day year)", realized.annualized_volatility_pct, realized.annualization_days);
+        }
+        if let Some(drawdown) = &self.max_drawdown {
+            if drawdown.drawdown_pct > 0.0 {
+                println!("📉 Max Drawdown: {:.2}% from {} (block {}, {}) to {} (block {}, {})", drawdown.drawdown_pct,
+                    format_price(drawdown.peak_price), drawdown.peak_block, crate::format_timestamp(drawdown.peak_timestamp),
+                    format_price(drawdown.trough_price), drawdown.trough_block, crate::format_timestamp(drawdown.trough_timestamp));
+                match drawdown.recovered_timestamp.zip(drawdown.recovered_block) {
+                    Some((timestamp, block)) => println!("   Recovered to the peak at block {} ({})", block, crate::format_timestamp(timestamp)),
+                    None => println!("   Not recovered by the end of the window"),
+                }
+            } else {
+                println!("📉 Max Drawdown: none, the price never fell below an earlier sample");
+            }
+        }
+        if let Some(cost) = &self.manipulation_cost {
+            println!("🏴‍☠️ Cost to skew TWAP {:+.2}%: ~{:.4} {} at block {} ({:.2}% of the weight, {:+.2}% price push)",
+                cost.target_pct, cost.capital_token1, self.token1.symbol, cost.block, cost.weight_share * 100.0, cost.required_move_pct);
+        }
+        if let Some(nested) = &self.twap_of_twaps {
+            println!("🪆 TWAP of {} sub-period TWAPs ({} each): {} {} per {}", nested.periods.len(),
+                crate::format_duration(nested.period_seconds), format_price(nested.aggregate), quote, base);
+            for period in &nested.periods {
+                println!("   {} → {}: {}", crate::format_timestamp(period.start), crate::format_timestamp(period.end), format_price(period.twap));
+            }
+        }
+        if let Some(fixed_point) = &self.fixed_point {
+            println!("🔢 Fixed-point TWAP (×{}): {}", fixed_point.scale, fixed_point.twap);
+            println!("🔢 Fixed-point Current Price: {}", fixed_point.current_price);
+            println!("🔢 Fixed-point Min/Max: {} / {}", fixed_point.min_price, fixed_point.max_price);
+        }
+        if let Some(liquidity) = &self.liquidity {
+            println!("💧 Liquidity (k = reserve0 × reserve1): {:.6e}", liquidity.current_k);
+            println!("💧 k Range: {:.6e} – {:.6e} ({:+.2}% over the window)",
+                liquidity.min_k, liquidity.max_k, liquidity.change_pct);
+        }
+        if let Some(last) = self.price_points.last() {
+            if let (Some(sqrt_price_x96), Some(tick)) = (last.sqrt_price_x96, last.tick) {
+                println!("🧮 Current sqrtPriceX96: {}", sqrt_price_x96);
+                println!("🧮 Current Tick: {}", tick);
+            }
+        }
+        if let Some(end_block) = self.end_block {
+            println!("🧱 End Block: {}", end_block);
+        }
+        println!("═══════════════════════════════════════");
+
+        if let Some(oracle) = &self.oracle {
+            println!();
+            println!("🔮 POOL VS ORACLE ({})", checksum(&oracle.feed));
+            println!("🎯 Pool TWAP:   {}", format_price(self.twap));
+            println!("🎯 Oracle TWAP: {}", format_price(oracle.oracle_twap));
+            println!("↔️  Spread: {:+.2}%", oracle.spread_pct);
+        }
+
+        if let Some(accumulator) = &self.accumulator {
+            println!();
+            println!("🧮 SAMPLED VS ACCUMULATORS (blocks {}-{})", accumulator.start_block, accumulator.end_block);
+            println!("🎯 Sampled TWAP:     {}", format_price(self.twap));
+            println!("🎯 Accumulator TWAP: {}", format_price(accumulator.accumulator_twap));
+            println!("↔️  Difference: {:+.2}%", accumulator.difference_pct);
+            println!("🧾 reserve0Cumulative: {} -> {}", accumulator.reserve0_cumulative_start, accumulator.reserve0_cumulative_end);
+            println!("🧾 reserve1Cumulative: {} -> {}", accumulator.reserve1_cumulative_start, accumulator.reserve1_cumulative_end);
+        }
+
+        if let Some(check) = &self.spot_divergence {
+            println!();
+            println!("🧮 SPOT VS ACCUMULATORS (block {})", check.block);
+            println!("💵 Reserve price:     {}", format_price(check.spot_price));
+            println!("🎯 Accumulator price: {}", format_price(check.accumulator_price));
+            if check.diverged {
+                println!("🚨 Divergence: {:+.2}% (over {}%; the block moved the price)", check.divergence_pct, check.threshold_pct);
+            } else {
+                println!("↔️  Divergence: {:+.2}% (within {}%)", check.divergence_pct, check.threshold_pct);
+            }
+        }
+
+        if let Some(benchmark) = &self.benchmark {
+            println!();
+            println!("⏱️  SAMPLING VS OBSERVE()");
+            println!("🎯 Sampled TWAP: {} in {:.2}s", format_price(self.twap), benchmark.sampling_seconds);
+            match (benchmark.observe_twap, benchmark.discrepancy_pct) {
+                (Some(observe_twap), Some(discrepancy)) => {
+                    println!("🎯 observe() TWAP: {} in {:.2}s", format_price(observe_twap), benchmark.observe_seconds);
+                    println!("↔️  Discrepancy: {:+.4}%", discrepancy);
+                }
+                _ => println!("🎯 observe() TWAP: unavailable for this pool"),
+            }
+        }
+
+        if let Some(execution) = &self.execution {
+            println!();
+            println!("🔁 EXECUTION PRICE FOR {} {} (stable swap, before fees)", execution.amount, base);
+            println!("🎯 Marginal TWAP:  {} {} per {}", format_price(self.twap), quote, base);
+            println!("🎯 Execution TWAP: {} {} per {}", format_price(execution.twap), quote, base);
+            println!("↔️  Price Impact: {:+.4}%", execution.price_impact_pct);
+        }
+
+        if let Some(curve) = &self.impact_curve {
+            let formula = if curve.stable { "stable" } else { "volatile" };
+            println!();
+            println!("📐 PRICE IMPACT CURVE AT BLOCK {} ({} swap, before fees)", curve.block, formula);
+            println!("💵 Spot: {} {} per {}", format_price(curve.spot_price), quote, base);
+            for point in &curve.points {
+                println!("   {:>14} {} → {} {} per {} ({:+.4}%)",
+                    point.amount, base, format_price(point.effective_price), quote, base, point.slippage_pct);
+            }
+        }
+
+        if let Some(diff) = &self.diff {
+            println!();
+            match diff.previous_end_block {
+                Some(block) => println!("🔁 CHANGE SINCE PREVIOUS REPORT (block {})", block),
+                None => println!("🔁 CHANGE SINCE PREVIOUS REPORT"),
+            }
+            println!("🎯 TWAP: {} → {} ({:+.2}%)", format_price(diff.previous_twap), format_price(self.twap), diff.twap_change_pct);
+            println!("📍 Deviation: {:+.2} pp", diff.deviation_change_pp);
+            if let Some(change) = diff.liquidity_change_pct {
+                println!("💧 Liquidity (k): {:+.2}%", change);
+            }
+        }
+
+        if let Some(baseline) = &self.baseline {
+            println!();
+            println!("📐 RELATIVE TO BLOCK {} ({} {} per {})", baseline.block, format_price(baseline.price), quote, base);
+            println!("🎯 TWAP: {:+.2}%", baseline.twap_pct);
+            println!("💵 Current Price: {:+.2}%", baseline.current_price_pct);
+            println!("📊 Min Price: {:+.2}%", baseline.min_price_pct);
+            println!("📊 Max Price: {:+.2}%", baseline.max_price_pct);
+        }
+
+        if let Some(integrity) = &self.integrity {
+            println!();
+            println!("🔏 Report SHA-256: {}", integrity.report_sha256);
+            if let Some(samples) = &integrity.samples_sha256 {
+                println!("🔏 Samples SHA-256: {}", samples);
+            }
+        }
+    }
+
+    /// Render the results as Markdown tables, with the per-sample series when `include_samples`
+    pub fn to_markdown(&self, include_samples: bool) -> String {
+        let (base, quote) = (escape_markdown(&self.token0.symbol), escape_markdown(&self.token1.symbol));
+        let mut rows: Vec<(String, String)> = Vec::new();
+        let mut row = |metric: &str, value: String| rows.push((metric.to_string(), value));
+
+        if let Some(cross) = &self.cross {
+            row("Triangulated via", escape_markdown(&cross.via.symbol));
+            row("Pool A TWAP", format!("{:.8} (`{}`)", cross.pool_a_twap, checksum(&cross.pool_a)));
+            row("Pool B TWAP", format!("{:.8} (`{}`)", cross.pool_b_twap, checksum(&cross.pool_b)));
+        } else if let Some(sequence) = &self.pool_sequence {
+            for segment in sequence {
+                row("Pool", format!("`{}` from block {} ({} samples)", checksum(&segment.pool), segment.from_block, segment.samples));
+            }
+        } else {
+            row("Pool", format!("{} (`{}`)", escape_markdown(&self.pool_label()), checksum(&self.pool)));
+        }
+        row("Window", format!("{} days, {} samples", self.days, self.collected_samples));
+        if let Some(partial) = &self.partial {
+            row("Partial", format!("{:.1}% of the window, through block {}", partial.window_covered_pct, partial.collected_through_block));
+        }
+        if let Some(session) = &self.session {
+            row("Session", format!("{} ({:.1} of {:.1} days)", session.filter,
+                session.included_seconds as f64 / 86400.0, session.window_seconds as f64 / 86400.0));
+        }
+        if let Some(inversion) = &self.auto_inverted {
+            row("Direction", format!("auto-inverted to {} per {} (pool price {:.8} below {})",
+                quote, base, inversion.raw_spot_price, inversion.threshold));
+        }
+        if let Some(gaps) = &self.reserve_gaps {
+            row("Reserve gaps", format!("{} samples below {} tokens, {} of {} excluded", gaps.gap_samples, gaps.threshold,
+                crate::format_duration(gaps.gap_seconds), crate::format_duration(gaps.window_seconds)));
+        }
+        if let Some(clamped) = self.clamped_prices.as_ref().filter(|clamped| clamped.samples > 0) {
+            row("Clamped", format!("{} sample prices capped into [{}, {}] for display", clamped.samples, clamped.min, clamped.max));
+        }
+        if let Some(url) = &self.subgraph {
+            row("Source", format!("subgraph hourly prices from {} (block numbers estimated)", escape_markdown(url)));
+        }
+        if let Some(clamped) = &self.clamped_window {
+            row("Clamped window", format!("{:.1} of {} days, from pool creation at block {}",
+                clamped.effective_days, clamped.requested_days, clamped.start_block));
+        }
+        if !self.block_substitutions.is_empty() {
+            let substituted: Vec<String> = self
+                .block_substitutions
+                .iter()
+                .map(|substituted| format!("{} for {} ({:+}s)", substituted.block, substituted.requested_block, substituted.timestamp_offset))
+                .collect();
+            row("Substituted blocks", substituted.join(", "));
+        }
+        if let Some(span) = &self.block_span {
+            let average = span.average_block_time.map_or_else(String::new, |block_time| format!(", {:.3}s per block", block_time));
+            row("Block span", format!("{}-{} ({} blocks, {:.1}% of ~{} requested{})", span.first_block, span.last_block,
+                span.spanned_blocks, span.spanned_pct, span.requested_blocks, average));
+        }
+        row(&format!("{}-Day TWAP", self.days), format!("{:.8} {} per {}", self.twap, quote, base));
+        row("Weighted by", match self.weighting {
+            analytics::Weighting::Time => "time".to_string(),
+            analytics::Weighting::Blocks => "block count".to_string(),
+        });
+        if let Some(smoothing) = &self.smoothing {
+            row("Unsmoothed TWAP", format!("{:.8} ({}-sample moving average above)", smoothing.raw_twap, smoothing.window));
+        }
+        row("Current Price", match self.price_points.last() {
+            Some(last) => format!("{} (block {})", format_price(self.current_price), last.block),
+            None => format_price(self.current_price),
+        });
+        row("Min Price", format!("{:.8}", self.min_price));
+        row("Max Price", format!("{:.8}", self.max_price));
+        row("Price Range", format!("{:.2}%", self.price_range_pct));
+        row("Deviation from TWAP", format!("{:.2}%", self.deviation_pct));
+        if let Some(reference) = &self.reference {
+            row("Reference Price", format_price(reference.price));
+            row("Spot vs Reference", format!("{:+.2}%", reference.spot_deviation_pct));
+            row("TWAP vs Reference", format!("{:+.2}%", reference.twap_deviation_pct));
+        }
+        if let Some(volatility) = self.annualized_volatility_pct {
+            row("Annualized Volatility", format!("{:.2}%", volatility));
+        }
+        if let Some(realized) = &self.realized_variance {
+            row("Realized Variance", format!("{:.8} ({:.8} annualized)", realized.window_variance, realized.annualized_variance));
+            row("Realized Volatility", format!("{:.2}% ({}-day year)", realized.annualized_volatility_pct, realized.annualization_days));
+        }
+if let Some(drawdown) = &self.max_drawdown {
+            let recovery = match drawdown.recovered_block {
+                Some(block) => format!("recovered at block {}", block),
+                None => "not recovered".to_string(),
+            };
+            row("Max Drawdown", format!("{:.2}% (block {} to {}, {})", drawdown.drawdown_pct, drawdown.peak_block,
+                drawdown.trough_block, recovery));
+        }
+                if let Some(cost) = &self.manipulation_cost {
+            row(&format!("Cost to Skew TWAP {:+.2}%", cost.target_pct),
+                format!("~{:.4} {} at block {} ({:+.2}% push)", cost.capital_token1, self.token1.symbol, cost.block, cost.required_move_pct));
+        }
+        if let Some(nested) = &self.twap_of_twaps {
+            row(&format!("TWAP of {} Sub-period TWAPs", nested.periods.len()),
+                format!("{:.8} ({} each)", nested.aggregate, crate::format_duration(nested.period_seconds)));
+            for period in &nested.periods {
+                row(&format!("Sub-period from {}", crate::format_timestamp(period.start)), format!("{:.8}", period.twap));
+            }
+        }
+        if let Some(fixed_point) = &self.fixed_point {
+            row(&format!("Fixed-point TWAP (×{})", fixed_point.scale), fixed_point.twap.to_string());
+        }
+        if let Some(liquidity) = &self.liquidity {
+            row("Liquidity (k)", format!("{:.6e} ({:+.2}% over the window)", liquidity.current_k, liquidity.change_pct));
+        }
+        if let Some(oracle) = &self.oracle {
+            row("Oracle TWAP", format!("{:.8} (`{}`)", oracle.oracle_twap, checksum(&oracle.feed)));
+            row("Pool vs Oracle Spread", format!("{:+.2}%", oracle.spread_pct));
+        }
+        if let Some(accumulator) = &self.accumulator {
+            row("Accumulator TWAP", format!("{} ({:+.2}% sampled)", format_price(accumulator.accumulator_twap), accumulator.difference_pct));
+        }
+        if let Some(check) = &self.spot_divergence {
+            row("Spot vs Accumulators", format!("{} vs {} ({:+.2}%{})", format_price(check.spot_price),
+                format_price(check.accumulator_price), check.divergence_pct, if check.diverged { ", diverged" } else { "" }));
+        }
+        if let Some(benchmark) = &self.benchmark {
+            row("observe() TWAP", match (benchmark.observe_twap, benchmark.discrepancy_pct) {
+                (Some(observe_twap), Some(discrepancy)) => format!("{} ({:+.4}%)", format_price(observe_twap), discrepancy),
+                _ => "unavailable".to_string(),
+            });
+        }
+        if let Some(execution) = &self.execution {
+            row(&format!("Execution TWAP for {} {}", execution.amount, base),
+                format!("{} ({:+.4}% impact)", format_price(execution.twap), execution.price_impact_pct));
+        }
+        if let Some(curve) = &self.impact_curve {
+            for point in &curve.points {
+                row(&format!("Execution price selling {} {} at block {}", point.amount, base, curve.block),
+                    format!("{} ({:+.4}% from spot)", format_price(point.effective_price), point.slippage_pct));
+            }
+        }
+        if let Some(baseline) = &self.baseline {
+            row(&format!("TWAP vs Block {}", baseline.block), format!("{:+.2}%", baseline.twap_pct));
+        }
+        if let Some(end_block) = self.end_block {
+            row("End Block", end_block.to_string());
+        }
+        if let Some(integrity) = &self.integrity {
+            row("Report SHA-256", format!("`{}`", integrity.report_sha256));
+            if let Some(samples) = &integrity.samples_sha256 {
+                row("Samples SHA-256", format!("`{}`", samples));
+            }
+        }
+
+        let mut out = format!("## TWAP: {} per {}\n\n| Metric | Value |\n|---|---|\n", quote, base);
+        for (metric, value) in rows {
+            out.push_str(&format!("| {} | {} |\n", metric, value));
+        }
+
+        if include_samples && !self.price_points.is_empty() {
+            out.push_str("\n### Samples\n\n| Block | Time | Price |\n|---:|---|---:|\n");
+            for point in &self.price_points {
+                out.push_str(&format!("| {} | {} | {} |\n", point.block, crate::format_timestamp(point.timestamp), format_price(point.price)));
+            }
+        }
+        out
+    }
+
+    /// Render the summary as Prometheus text-exposition gauges
+    pub fn to_prometheus(&self) -> String {
+        let labels = format!(
+            "pool=\"{}\",pair=\"{}/{}\"",
+            checksum(&self.pool),
+            escape_label(&self.token0.symbol),
+            escape_label(&self.token1.symbol)
+        );
+
+        let gauges = [
+            ("twap_price", "Time-weighted average price over the window", self.twap),
+            ("twap_spot_price", "Price at the end of the window", self.current_price),
+            ("twap_min_price", "Lowest sampled price in the window", self.min_price),
+            ("twap_max_price", "Highest sampled price in the window", self.max_price),
+            ("twap_deviation_percent", "Deviation of the spot price from the TWAP in percent", self.deviation_pct),
+            ("twap_sample_count", "Number of samples the TWAP was computed from", self.collected_samples as f64),
+        ];
+
+        let mut out = String::new();
+        for (name, help, value) in gauges {
+            out.push_str(&format!("# HELP {} {}\n# TYPE {} gauge\n{}{{{}}} {}\n", name, help, name, name, labels, value));
+        }
+        out
+    }
+}
+
+/// Crude TWAP estimate from only the first and last block of the window
+#[derive(Debug, Serialize)]
+pub struct QuickEstimate {
+    #[serde(serialize_with = "serialize_checksummed")]
+    pub pool: Address,
+    pub token0: TokenInfo,
+    pub token1: TokenInfo,
+    pub start: PricePoint,
+    pub end: PricePoint,
+    /// Simple mean of the start and end prices
+    pub estimated_twap: f64,
+    pub approximate: bool,
+}
+
+impl QuickEstimate {
+    pub fn new(pool: Address, token0: TokenInfo, token1: TokenInfo, start: PricePoint, end: PricePoint) -> Self {
+        Self {
+            pool,
+            token0,
+            token1,
+            estimated_twap: (start.price + end.price) / 2.0,
+            start,
+            end,
+            approximate: true,
+        }
+    }
+
+    /// Print the estimate, labeled so it can't be mistaken for a sampled TWAP
+    pub fn print_text(&self) {
+        let (base, quote) = (&self.token0.symbol, &self.token1.symbol);
+
+        println!("⚡ QUICK ESTIMATE (approximation, not a sampled TWAP)");
+        println!("═══════════════════════════════════════");
+        println!("🏁 Start Price (block {}): {} {} per {}", self.start.block, format_price(self.start.price), quote, base);
+        println!("💵 End Price (block {}): {} {} per {}", self.end.block, format_price(self.end.price), quote, base);
+        println!("≈  Estimated TWAP: {} {} per {}", format_price(self.estimated_twap), quote, base);
+        println!("═══════════════════════════════════════");
+        println!("⚠️  Average of two prices only; moves inside the window are ignored");
+    }
+}
+
+/// The run --validate checked, as it would have been carried out
+#[derive(Debug, Serialize)]
+pub struct ResolvedConfig {
+    /// Which input selects the pools: pool, wrapper, cross, compare_pools, index, pool_sequence or list_pools
+    pub mode: &'static str,
+    pub target: String,
+    /// The RPC's scheme and host; it isn't contacted
+    pub rpc: String,
+    pub days: u64,
+    pub samples: u64,
+    pub warmup_samples: u64,
+    /// Spacing between samples, absent for modes that pick their own blocks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval_seconds: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interval_blocks: Option<u64>,
+    /// Where the window ends
+    pub end: String,
+    pub weighting: analytics::Weighting,
+    pub output_timezone: String,
+    pub format: String,
+}
+
+impl ResolvedConfig {
+    pub fn print_text(&self) {
+        println!("✅ CONFIGURATION VALID");
+        println!("═══════════════════════════════════════");
+        println!("📍 {}: {}", self.mode, self.target);
+        println!("🌐 RPC: {} (not contacted)", self.rpc);
+        println!("⏰ Period: {} days, ending at {}", self.days, self.end);
+        println!("📊 Samples: {} (plus {} warmup)", self.samples, self.warmup_samples);
+        if let (Some(seconds), Some(blocks)) = (self.interval_seconds, self.interval_blocks) {
+            println!("🔬 Resolution: a sample every {} (~{} blocks)", crate::format_duration(seconds), blocks);
+        }
+        println!("⚖️  Weighted by: {}", match self.weighting {
+            analytics::Weighting::Time => "time",
+            analytics::Weighting::Blocks => "block count",
+        });
+        println!("🕒 Output timezone: {}", self.output_timezone);
+        println!("📝 Format: {}", self.format);
+        println!("═══════════════════════════════════════");
+    }
+}
+
+/// A pool's tokens and price at one block, from the `info` subcommand
+#[derive(Debug, Serialize)]
+pub struct PoolSnapshot {
+    #[serde(serialize_with = "serialize_checksummed")]
+    pub pool: Address,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub token0: TokenInfo,
+    pub token1: TokenInfo,
+    pub block: u64,
+    /// Raw reserves, absent for prices read from slot0 or a quoter
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reserve0: Option<u128>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reserve1: Option<u128>,
+    pub price: f64,
+}
+
+impl PoolSnapshot {
+    pub fn print_text(&self) {
+        println!("🔎 POOL INFO (block {})", self.block);
+        println!("═══════════════════════════════════════");
+        match &self.name {
+            Some(name) => println!("📍 Pool: {} ({})", checksum(&self.pool), name),
+            None => println!("📍 Pool: {}", checksum(&self.pool)),
+        }
+        for (name, token) in [("Token0", &self.token0), ("Token1", &self.token1)] {
+            println!("📌 {}: {} ({}), {} decimals", name, token.symbol, checksum(&token.address), token.decimals);
+        }
+        if let (Some(reserve0), Some(reserve1)) = (self.reserve0, self.reserve1) {
+            let whole = |reserve: u128, decimals: u8| reserve as f64 / 10f64.powi(decimals as i32);
+            println!("💧 Reserves: {:.6} {} / {:.6} {}", whole(reserve0, self.token0.decimals), self.token0.symbol,
+                whole(reserve1, self.token1.decimals), self.token1.symbol);
+        }
+        println!("💵 Price: {} {} per {}", format_price(self.price), self.token1.symbol, self.token0.symbol);
+        println!("═══════════════════════════════════════");
+    }
+}
+
+/// TWAP read directly from the pool's tick-cumulative oracle
+#[derive(Debug, Serialize)]
+pub struct ObservedTwap {
+    #[serde(serialize_with = "serialize_checksummed")]
+    pub pool: Address,
+    pub token0: TokenInfo,
+    pub token1: TokenInfo,
+    pub days: u64,
+    pub end_block: u64,
+    /// Arithmetic mean tick over the window
+    pub mean_tick: f64,
+    pub twap: f64,
+}
+
+impl ObservedTwap {
+    pub fn new(pool: crate::PoolInfo, days: u64, end_block: u64, mean_tick: f64, twap: f64) -> Self {
+        Self {
+            pool: pool.address,
+            token0: pool.token0,
+            token1: pool.token1,
+            days,
+            end_block,
+            mean_tick,
+            twap,
+        }
+    }
+
+    /// Print the observed TWAP
+    pub fn print_text(&self) {
+        let (base, quote) = (&self.token0.symbol, &self.token1.symbol);
+
+        println!("📈 RESULTS (on-chain observe() oracle)");
+        println!("═══════════════════════════════════════");
+        println!("🎯 {}-Day TWAP: {} {} per {}", self.days, format_price(self.twap), quote, base);
+        println!("🧮 Mean Tick: {:.4}", self.mean_tick);
+        println!("🧱 Ending at block {}", self.end_block);
+        println!("═══════════════════════════════════════");
+    }
+}
+
+/// One pool's TWAP in a --compare-pools run, quoted in the first pool's direction
+#[derive(Debug, Serialize)]
+pub struct ComparedPool {
+    #[serde(serialize_with = "serialize_checksummed")]
+    pub pool: Address,
+    pub base: TokenInfo,
+    pub quote: TokenInfo,
+    pub collected_samples: usize,
+    pub twap: f64,
+    pub current_price: f64,
+    /// Current reserve0 × reserve1 in whole tokens, when the pool was priced from reserves
+    pub liquidity_k: Option<f64>,
+    /// (this TWAP - first pool's TWAP) / first pool's TWAP
+    pub spread_pct: f64,
+}
+
+/// Print a table of pools quoting the same pair over the same window
+pub fn print_comparison(days: u64, pools: &[ComparedPool]) {
+    let Some(reference) = pools.first() else {
+        return;
+    };
+    let (base, quote) = (&reference.base.symbol, &reference.quote.symbol);
+
+    println!("📈 {}-DAY TWAP ACROSS {} POOLS ({} per {})", days, pools.len(), quote, base);
+    println!("═══════════════════════════════════════");
+    println!("{:<42}  {:>18}  {:>18}  {:>14}  {:>10}", "Pool", "TWAP", "Current", "Liquidity k", "Spread");
+    for pool in pools {
+        let liquidity = pool.liquidity_k.map_or("-".to_string(), |k| format!("{:.4e}", k));
+        println!(
+            "{:<42}  {:>18}  {:>18}  {:>14}  {:>+9.3}%",
+            checksum(&pool.pool), format_price(pool.twap), format_price(pool.current_price), liquidity, pool.spread_pct
+        );
+    }
+    println!("═══════════════════════════════════════");
+
+    let cheapest = pools.iter().min_by(|a, b| a.twap.total_cmp(&b.twap)).unwrap_or(reference);
+    let richest = pools.iter().max_by(|a, b| a.twap.total_cmp(&b.twap)).unwrap_or(reference);
+    println!(
+        "↔️  Widest spread: {:.3}% ({} vs {})",
+        (richest.twap - cheapest.twap) / cheapest.twap * 100.0,
+        checksum(&richest.pool),
+        checksum(&cheapest.pool)
+    );
+    println!("   Spreads are relative to the first pool");
+}
+
+/// One pool in a --index basket
+#[derive(Debug, Serialize)]
+pub struct IndexComponent {
+    #[serde(serialize_with = "serialize_checksummed")]
+    pub pool: Address,
+    pub token0: TokenInfo,
+    pub token1: TokenInfo,
+    /// Share of the index, after normalizing the weights to sum to 1
+    pub weight: f64,
+    pub start_price: f64,
+    pub twap: f64,
+    /// TWAP relative to the window-start price
+    pub normalized_twap: f64,
+    /// Latest price relative to the window-start price
+    pub normalized_current: f64,
+}
+
+/// A weighted basket of pools, each normalized to 1.0 at the window's start
+#[derive(Debug, Serialize)]
+pub struct IndexReport {
+    pub days: u64,
+    pub components: Vec<IndexComponent>,
+    /// Time-weighted average of the composite index
+    pub index_twap: f64,
+    pub index_current: f64,
+    pub series: Vec<PricePoint>,
+}
+
+impl IndexReport {
+    /// Print the components and the composite index
+    pub fn print_text(&self) {
+        println!("📈 {}-DAY BASKET INDEX (1.0 = window start)", self.days);
+        println!("═══════════════════════════════════════");
+        for component in &self.components {
+            println!(
+                "   {} {}/{} ×{:.3}: TWAP {:.6}, now {:.6}",
+                checksum(&component.pool),
+                component.token1.symbol,
+                component.token0.symbol,
+                component.weight,
+                component.normalized_twap,
+                component.normalized_current,
+            );
+        }
+        println!("═══════════════════════════════════════");
+        println!("🎯 Index TWAP: {:.6}", self.index_twap);
+        println!("💵 Index Now: {:.6} ({:+.2}% over the window)", self.index_current, (self.index_current - 1.0) * 100.0);
+    }
+}
+
+/// Whether one planned sample block could be read
+#[derive(Debug, Serialize)]
+pub struct ProbedBlock {
+    pub block: u64,
+    pub readable: bool,
+}
+
+/// Result of --probe: a go/no-go check of a spread of the planned sample blocks
+#[derive(Debug, Serialize)]
+pub struct ProbeReport {
+    pub planned_samples: usize,
+    pub probed: Vec<ProbedBlock>,
+    /// Fraction of the probed blocks that were readable
+    pub success_rate: f64,
+    /// Oldest probed block from which every later probe was readable, if the newest was
+    pub history_starts_at: Option<u64>,
+}
+
+impl ProbeReport {
+    pub fn new(planned_samples: usize, probed: Vec<ProbedBlock>) -> Self {
+        let readable = probed.iter().filter(|probe| probe.readable).count();
+        let history_starts_at = probed
+            .iter()
+            .rev()
+            .take_while(|probe| probe.readable)
+            .last()
+            .map(|probe| probe.block);
+
+        Self {
+            planned_samples,
+            success_rate: readable as f64 / probed.len().max(1) as f64,
+            history_starts_at,
+            probed,
+        }
+    }
+
+    /// Print the probe summary
+    pub fn print_text(&self) {
+        let readable = self.probed.iter().filter(|probe| probe.readable).count();
+
+        println!("🩺 PROBE ({} planned samples)", self.planned_samples);
+        println!("═══════════════════════════════════════");
+        for probe in &self.probed {
+            println!("   Block {}: {}", probe.block, if probe.readable { "✅ readable" } else { "❌ failed" });
+        }
+        println!("═══════════════════════════════════════");
+        println!("📊 {}/{} probes readable ({:.0}%)", readable, self.probed.len(), self.success_rate * 100.0);
+        match (self.history_starts_at, self.probed.first()) {
+            (Some(start), Some(first)) if start == first.block => println!("✅ The whole window is readable"),
+            (Some(start), _) => println!("✂️  History cuts off before block {}", start),
+            (None, _) => println!("❌ The newest planned block isn't readable"),
+        }
+    }
+}
+
+/// Read a float written either as a JSON number or, under --json-compact-numbers string, a string
+fn deserialize_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Number {
+        Float(f64),
+        Text(String),
+    }
+
+    match Number::deserialize(deserializer)? {
+        Number::Float(value) => Ok(value),
+        Number::Text(text) => text.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+/// A summary number --metrics can pick
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Metric {
+    Twap,
+    /// The current (last sampled) price
+    Spot,
+    Min,
+    Max,
+    /// Median sampled price
+    Median,
+    /// Annualized volatility, in percent
+    Volatility,
+    /// Spot's deviation from the TWAP, in percent
+    Deviation,
+    /// Max over min, in percent
+    Range,
+}
+
+impl Metric {
+    fn key(self) -> &'static str {
+        match self {
+            Metric::Twap => "twap",
+            Metric::Spot => "current_price",
+            Metric::Min => "min_price",
+            Metric::Max => "max_price",
+            Metric::Median => "median_price",
+            Metric::Volatility => "annualized_volatility_pct",
+            Metric::Deviation => "deviation_pct",
+            Metric::Range => "price_range_pct",
+        }
+    }
+}
+
+/// Just the --metrics of a report, serialized in the order they were asked for
+pub struct MetricsView<'a> {
+    report: &'a TwapReport,
+    metrics: &'a [Metric],
+}
+
+impl Serialize for MetricsView<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+        let mut map = serializer.serialize_map(Some(self.metrics.len()))?;
+        for &metric in self.metrics {
+            map.serialize_entry(metric.key(), &self.report.metric(metric))?;
+        }
+        map.end()
+    }
+}
+
+/// How displayed prices are cut to --decimals places
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum Rounding {
+    /// Drop the extra digits (toward zero)
+    Truncate,
+    /// Round to the nearest, ties to even
+    #[default]
+    Nearest,
+    /// Round up
+    Ceil,
+    /// Round down
+    Floor,
+}
+
+/// Decimal places that show `price` to `significant` digits: more for small prices, none for ones
+/// with at least that many digits before the point
+pub fn significant_decimals(price: f64, significant: usize) -> usize {
+    if !(price.is_finite() && price != 0.0) {
+        return significant.saturating_sub(1);
+    }
+    let magnitude = price.abs().log10().floor() as i64;
+    (significant as i64 - 1 - magnitude).max(0) as usize
+}
+
+/// A price for display, cut to --decimals places with the --rounding mode.
+///
+/// Works on the shortest decimal that round-trips to the float, so a price like 1.15 that
+/// is stored as 1.149999... still truncates to 1.1 rather than 1.14.
+pub fn format_price(price: f64) -> String {
+    let (decimals, rounding) = crate::PRICE_DISPLAY.get().copied().unwrap_or((8, Rounding::Nearest));
+    if rounding == Rounding::Nearest || !price.is_finite() {
+        return format!("{:.*}", decimals, price);
+    }
+
+    let shortest = price.abs().to_string();
+    let (whole, fraction) = shortest.split_once('.').unwrap_or((&shortest, ""));
+    let mut digits: Vec<u8> = whole.bytes().chain(fraction.bytes().chain(std::iter::repeat(b'0')).take(decimals)).collect();
+    let dropped_nonzero = fraction.bytes().skip(decimals).any(|digit| digit != b'0');
+
+    // Away from zero when ceil rounds a positive price or floor a negative one
+    let away = dropped_nonzero
+        && match rounding {
+            Rounding::Ceil => price > 0.0,
+            Rounding::Floor => price < 0.0,
+            _ => false,
+        };
+    if away {
+        let mut i = digits.len();
+        loop {
+            if i == 0 {
+                digits.insert(0, b'1');
+                break;
+            }
+            i -= 1;
+            if digits[i] == b'9' {
+                digits[i] = b'0';
+            } else {
+                digits[i] += 1;
+                break;
+            }
+        }
+    }
+
+    let digits = String::from_utf8(digits).expect("ASCII digits");
+    let (whole, fraction) = digits.split_at(digits.len() - decimals);
+    let sign = if price < 0.0 && digits.bytes().any(|digit| digit != b'0') { "-" } else { "" };
+    if decimals == 0 {
+        format!("{}{}", sign, whole)
+    } else {
+        format!("{}{}.{}", sign, whole, fraction)
+    }
+}
+
+/// Escape characters that would break a Markdown table cell
+fn escape_markdown(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('|', "\\|")
+}
+
+/// An address in EIP-55 checksummed form, as block explorers show it
+pub fn checksum(address: &Address) -> String {
+    ethers::utils::to_checksum(address, None)
+}
+
+pub fn serialize_checksummed<S: Serializer>(address: &Address, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&checksum(address))
+}
+
+/// Serialize a U256 as a decimal string rather than ethers' default hex
+fn serialize_decimal<S: Serializer>(value: &U256, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&value.to_string())
+}
+
+/// Escape a Prometheus label value
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// JSON Schema describing the `--format json` output
+pub fn json_schema() -> String {
+    let schema = schemars::schema_for!(TwapReport);
+    serde_json::to_string_pretty(&schema).expect("schema serializes")
+}
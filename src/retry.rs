@@ -0,0 +1,59 @@
+use anyhow::Result;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::error::TwapError;
+
+/// Retries failed RPC reads with exponential backoff plus random jitter
+pub struct RetryPolicy {
+    retries: u32,
+    base_delay: Duration,
+    rng: Mutex<StdRng>,
+}
+
+impl RetryPolicy {
+    /// A fixed `seed` makes the jitter sequence reproducible
+    pub fn new(retries: u32, seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+
+        Self {
+            retries,
+            base_delay: Duration::from_millis(500),
+            rng: Mutex::new(rng),
+        }
+    }
+
+    /// Delay before retry number `attempt` (starting at 0): base * 2^attempt plus up to half that again
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self.base_delay * 2u32.saturating_pow(attempt);
+        let jitter_ms = self.rng.lock().unwrap().gen_range(0..=delay.as_millis() as u64 / 2);
+        delay + Duration::from_millis(jitter_ms)
+    }
+
+    /// Run `op` until it succeeds or the retries are used up, returning the last error.
+    /// Failures that retrying can't fix, like a pruned node, are returned straight away.
+    pub async fn run<T, F, Fut>(&self, mut op: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            match op().await {
+                Ok(value) => return Ok(value),
+                Err(e) if attempt >= self.retries => return Err(e),
+                Err(e) if e.downcast_ref::<TwapError>().is_some_and(|e| !e.is_transient()) => return Err(e),
+                Err(_) => {
+                    tokio::time::sleep(self.backoff(attempt)).await;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+}
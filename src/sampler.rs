@@ -0,0 +1,289 @@
+use anyhow::{Context, Result};
+use ethers::prelude::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::cache::ReservesCache;
+use crate::report::TokenInfo;
+use crate::{error, pricing};
+use crate::{AerodromePool, LpWrapper, Quoter, ERC20};
+
+/// Pool price at a single block, with the raw values it was derived from
+#[derive(Debug, Default)]
+pub struct Sample {
+    pub price: f64,
+    pub reserve0: Option<u128>,
+    pub reserve1: Option<u128>,
+    pub sqrt_price_x96: Option<U256>,
+    pub tick: Option<i32>,
+}
+
+/// Reads a pool's price at historical blocks
+pub struct Sampler {
+    pool_address: Address,
+    pool: AerodromePool<Provider<Http>>,
+    token0: ERC20<Provider<Http>>,
+    token1: ERC20<Provider<Http>>,
+    token0_decimals: u8,
+    token1_decimals: u8,
+    concentrated: bool,
+    balances_fallback: bool,
+    warned_balances_fallback: AtomicBool,
+    quoter: Option<(Quoter<Provider<Http>>, U256)>,
+    warned_quoter_fallback: AtomicBool,
+    wrapper: Option<LpWrapper<Provider<Http>>>,
+    block_hashes: HashMap<U64, H256>,
+    reserves_cache: Option<Arc<ReservesCache>>,
+    caller: Option<Address>,
+    stable: bool,
+}
+
+impl Sampler {
+    pub fn new(
+        provider: Arc<Provider<Http>>,
+        pool_address: Address,
+        token0: &TokenInfo,
+        token1: &TokenInfo,
+        concentrated: bool,
+        balances_fallback: bool,
+    ) -> Self {
+        Self {
+            pool_address,
+            pool: AerodromePool::new(pool_address, provider.clone()),
+            token0: ERC20::new(token0.address, provider.clone()),
+            token1: ERC20::new(token1.address, provider),
+            token0_decimals: token0.decimals,
+            token1_decimals: token1.decimals,
+            concentrated,
+            balances_fallback,
+            warned_balances_fallback: AtomicBool::new(false),
+            quoter: None,
+            warned_quoter_fallback: AtomicBool::new(false),
+            wrapper: None,
+            block_hashes: HashMap::new(),
+            reserves_cache: None,
+            caller: None,
+            stable: false,
+        }
+    }
+
+    /// Price samples by quoting `amount` of token0 on a quoter contract, falling back to
+    /// reserves when the quote reverts
+    pub fn with_quoter(mut self, quoter: Address, amount: f64) -> Result<Self> {
+        if !(amount > 0.0 && amount.is_finite()) {
+            anyhow::bail!("--quote-amount must be positive");
+        }
+        let amount_in: U256 = ethers::utils::parse_units(amount.to_string(), self.token0_decimals as u32)
+            .context("Invalid --quote-amount")?
+            .into();
+
+        self.quoter = Some((Quoter::new(quoter, self.pool.client()), amount_in));
+        Ok(self)
+    }
+
+    /// Treat the pool address as an LP wrapper and read its getTotalAmounts() in place of reserves.
+    /// Errors if the contract doesn't have it.
+    pub async fn with_wrapper(mut self) -> Result<Self> {
+        let wrapper = LpWrapper::new(self.pool_address, self.pool.client());
+        wrapper.get_total_amounts().call().await.context(
+            "Contract has no getTotalAmounts(); --wrapper supports Gamma Hypervisors and ICHI vaults",
+        )?;
+
+        self.wrapper = Some(wrapper);
+        Ok(self)
+    }
+
+    /// Read state at these blocks by hash instead of by number, so each read sees exactly that block
+    pub fn with_block_hashes(mut self, block_hashes: HashMap<U64, H256>) -> Self {
+        self.block_hashes = block_hashes;
+        self
+    }
+
+    /// Consult and fill a reserves cache around getReserves reads
+    pub fn with_reserves_cache(mut self, cache: Arc<ReservesCache>) -> Self {
+        self.reserves_cache = Some(cache);
+        self
+    }
+
+    /// Send the pool, quoter, wrapper and balance reads from this address, for pools that gate their views by caller
+    pub fn with_caller(mut self, caller: Address) -> Self {
+        self.caller = Some(caller);
+        self
+    }
+
+    /// Price reserves on Aerodrome's stable x³y + xy³ = k curve instead of x * y = k
+    pub fn with_stable_curve(mut self) -> Self {
+        self.stable = true;
+        self
+    }
+
+    /// Whether reserves are priced on the stable curve
+    pub fn stable(&self) -> bool {
+        self.stable
+    }
+
+    /// The block to read at: its pinned hash if there is one, otherwise its number
+    fn block_id(&self, block: U64) -> BlockId {
+        match self.block_hashes.get(&block) {
+            Some(&hash) => BlockId::Hash(hash),
+            None => BlockId::Number(BlockNumber::Number(block)),
+        }
+    }
+
+    /// Read the price at a block, or None if the pool was empty there
+    pub async fn read_price(&self, block: U64) -> Result<Option<Sample>> {
+        if self.concentrated {
+            return self.read_slot0_price(block).await;
+        }
+
+        if let Some(quoted) = self.read_quoted_price(block).await {
+            return Ok(Some(quoted));
+        }
+
+        let (reserve0, reserve1) = self.read_reserves(block).await?;
+
+        // Calculate price (token1 per token0)
+        let price = if self.stable {
+            pricing::stable_reserves_to_price(reserve0, reserve1, self.token0_decimals, self.token1_decimals)
+        } else {
+            pricing::reserves_to_price(reserve0, reserve1, self.token0_decimals, self.token1_decimals)?
+        };
+        Ok(price.map(|price| Sample {
+            price,
+            reserve0: Some(reserve0),
+            reserve1: Some(reserve1),
+            ..Default::default()
+        }))
+    }
+
+    /// Execution price for the configured input amount, or None if there's no quoter or it reverted
+    async fn read_quoted_price(&self, block: U64) -> Option<Sample> {
+        let (quoter, amount_in) = self.quoter.as_ref()?;
+
+        let quote = self
+            .as_caller(quoter.get_amount_out(*amount_in, self.token0.address()))
+            .block(self.block_id(block))
+            .call()
+            .await;
+
+        match quote {
+            Ok(amount_out) => {
+                let amount_in = pricing::u256_to_f64(*amount_in) / 10f64.powi(self.token0_decimals as i32);
+                let amount_out = pricing::u256_to_f64(amount_out) / 10f64.powi(self.token1_decimals as i32);
+                Some(Sample {
+                    price: amount_out / amount_in,
+                    ..Default::default()
+                })
+            }
+            Err(_) => {
+                if !self.warned_quoter_fallback.swap(true, Ordering::Relaxed) {
+                    status!();
+                    status!("⚠️  Quoter call reverted at block {}, falling back to reserve pricing", block);
+                }
+                None
+            }
+        }
+    }
+
+    async fn read_slot0_price(&self, block: U64) -> Result<Option<Sample>> {
+        // Get the current sqrt price and tick at this block
+        let (sqrt_price_x96, tick) = self
+            .as_caller(self.pool.slot_0())
+            .block(self.block_id(block))
+            .call()
+            .await
+            .map_err(|e| error::historical_read(e, "slot0", block.as_u64()))?;
+
+        if sqrt_price_x96.is_zero() {
+            return Ok(None);
+        }
+
+        Ok(Some(Sample {
+            price: pricing::sqrt_price_x96_to_price(sqrt_price_x96, self.token0_decimals, self.token1_decimals),
+            sqrt_price_x96: Some(sqrt_price_x96),
+            tick: Some(tick),
+            ..Default::default()
+        }))
+    }
+
+    /// Get reserves at a block, falling back to token balances if enabled
+    async fn read_reserves(&self, block: U64) -> Result<(u128, u128)> {
+        // Pinned blocks may be off the canonical chain, so they bypass the number-keyed cache
+        let cache = self.reserves_cache.as_ref().filter(|_| !self.block_hashes.contains_key(&block));
+        let remember = |reserves: (u128, u128)| {
+            if let Some(cache) = cache {
+                cache.insert(self.pool_address, block.as_u64(), reserves);
+            }
+            reserves
+        };
+        if let Some(reserves) = cache.and_then(|cache| cache.get(self.pool_address, block.as_u64())) {
+            return Ok(reserves);
+        }
+
+        if let Some(wrapper) = &self.wrapper {
+            return self.read_wrapper_amounts(wrapper, block).await.map(remember);
+        }
+
+        let reserves = self.as_caller(self.pool.get_reserves()).block(self.block_id(block)).call().await;
+
+        match reserves {
+            Ok((reserve0, reserve1, _)) => Ok(remember((reserve0, reserve1))),
+            Err(_) if self.balances_fallback => {
+                if !self.warned_balances_fallback.swap(true, Ordering::Relaxed) {
+                    status!();
+                    status!("⚠️  getReserves reverted, falling back to token balances");
+                    status!("   Balances include any protocol fees held by the pool, so prices may be slightly off");
+                }
+                self.read_balances(block).await
+            }
+            Err(e) => Err(error::historical_read(e, "reserves", block.as_u64())),
+        }
+    }
+
+    /// Read an LP wrapper's underlying token amounts at a block as a stand-in for reserves
+    async fn read_wrapper_amounts(&self, wrapper: &LpWrapper<Provider<Http>>, block: U64) -> Result<(u128, u128)> {
+        let (total0, total1) = self
+            .as_caller(wrapper.get_total_amounts())
+            .block(self.block_id(block))
+            .call()
+            .await
+            .map_err(|e| error::historical_read(e, "wrapper total amounts", block.as_u64()))?;
+
+        let total0 = u128::try_from(total0).map_err(|_| anyhow::anyhow!("wrapper total0 overflows u128"))?;
+        let total1 = u128::try_from(total1).map_err(|_| anyhow::anyhow!("wrapper total1 overflows u128"))?;
+
+        Ok((total0, total1))
+    }
+
+    /// Send a read from the configured caller, if there is one
+    fn as_caller<D: abi::Detokenize>(&self, call: ContractCall<Provider<Http>, D>) -> ContractCall<Provider<Http>, D> {
+        match self.caller {
+            Some(caller) => call.from(caller),
+            None => call,
+        }
+    }
+
+    /// Read the pool's token balances at a block as a stand-in for its reserves
+    async fn read_balances(&self, block: U64) -> Result<(u128, u128)> {
+        let block_id = self.block_id(block);
+
+        let balance0 = self
+            .as_caller(self.token0.balance_of(self.pool_address))
+            .block(block_id)
+            .call()
+            .await
+            .map_err(|e| error::historical_read(e, "token0 balance", block.as_u64()))?;
+        let balance1 = self
+            .as_caller(self.token1.balance_of(self.pool_address))
+            .block(block_id)
+            .call()
+            .await
+            .map_err(|e| error::historical_read(e, "token1 balance", block.as_u64()))?;
+
+        let balance0 = u128::try_from(balance0).map_err(|_| anyhow::anyhow!("token0 balance overflows u128"))?;
+        let balance1 = u128::try_from(balance1).map_err(|_| anyhow::anyhow!("token1 balance overflows u128"))?;
+
+        Ok((balance0, balance1))
+    }
+}
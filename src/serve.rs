@@ -0,0 +1,142 @@
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+
+/// Largest request head read before giving up on a connection
+const MAX_REQUEST_BYTES: usize = 8192;
+
+/// How long a connection gets to send its request head before it's dropped
+const REQUEST_HEAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A request the handler rejects because of its parameters, answered with 400 instead of 500
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct BadRequest(pub String);
+
+/// Serve `GET /twap?...` on `listen`, passing the query parameters to `handler` and returning its
+/// JSON. At most `max_concurrent` requests are computed at once; further requests wait. A slot is
+/// only taken once a request's head has arrived, so idle connections can't hold one.
+pub async fn serve<F, Fut>(listen: &str, max_concurrent: usize, handler: F) -> Result<()>
+where
+    F: Fn(HashMap<String, String>) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Result<String>> + Send,
+{
+    let listener = TcpListener::bind(listen).await.context(format!("Failed to listen on {}", listen))?;
+    let permits = Arc::new(Semaphore::new(max_concurrent.max(1)));
+    status!("🌐 Serving TWAP reports on http://{}/twap?pool=ADDR&days=N&samples=N", listen);
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                status!("⚠️  Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        let (permits, handler) = (permits.clone(), handler.clone());
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &permits, handler).await {
+                status!("⚠️  Request from {} failed: {:#}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle_connection<F, Fut>(mut stream: TcpStream, permits: &Semaphore, handler: F) -> Result<()>
+where
+    F: Fn(HashMap<String, String>) -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    let head = tokio::time::timeout(REQUEST_HEAD_TIMEOUT, read_request_head(&mut stream))
+        .await
+        .map_err(|_| anyhow::anyhow!("no request head within {}s", REQUEST_HEAD_TIMEOUT.as_secs()))??;
+    let request_line = head.lines().next().unwrap_or_default();
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+
+    let (status, body) = match reqwest::Url::parse(&format!("http://localhost{}", target)) {
+        _ if method != "GET" => (405, error_json("only GET is supported")),
+        Ok(url) if url.path() == "/twap" => {
+            let query = url.query_pairs().into_owned().collect();
+            let _permit = permits.acquire().await?;
+            match handler(query).await {
+                Ok(json) => (200, json),
+                Err(e) if e.is::<BadRequest>() => (400, error_json(&e.to_string())),
+                Err(e) => (500, error_json(&format!("{:#}", e))),
+            }
+        }
+        Ok(_) => (404, error_json("unknown path; use /twap")),
+        Err(_) => (400, error_json("malformed request target")),
+    };
+
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        _ => "Internal Server Error",
+    };
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status, reason, body.len(), body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Read up to the blank line ending the request head; request bodies are ignored
+async fn read_request_head(stream: &mut TcpStream) -> Result<String> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 1024];
+    while !buffer.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if buffer.len() > MAX_REQUEST_BYTES {
+            anyhow::bail!("request head over {} bytes", MAX_REQUEST_BYTES);
+        }
+    }
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+fn error_json(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn idle_connections_dont_hold_the_only_slot() {
+        // Find a free port, then serve on it with room for one request at a time
+        let listen = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+        let server = listen.clone();
+        tokio::spawn(async move { serve(&server, 1, |_| async { Ok("{}".to_string()) }).await });
+        let connect = || async {
+            for _ in 0..50 {
+                if let Ok(stream) = TcpStream::connect(&listen).await {
+                    return stream;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            panic!("server didn't start on {}", listen);
+        };
+
+        let _idle = connect().await;
+        let mut request = connect().await;
+        request.write_all(b"GET /twap?pool=0x1 HTTP/1.1\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        tokio::time::timeout(Duration::from_secs(5), request.read_to_string(&mut response)).await.unwrap().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.ends_with("{}"));
+    }
+}
@@ -0,0 +1,142 @@
+use chrono::{Datelike, NaiveDate, NaiveTime, TimeZone, Timelike, Weekday};
+use chrono_tz::Tz;
+
+use crate::PricePoint;
+
+/// Days of the week a --weekday-filter keeps
+#[derive(Clone, Debug)]
+pub struct Weekdays(pub Vec<Weekday>);
+
+/// Parse `weekdays`, `weekends` or a comma-separated list of day names like `mon,wed,fri`
+pub fn parse_weekdays(value: &str) -> Result<Weekdays, String> {
+    use Weekday::*;
+    let days = match value.trim().to_ascii_lowercase().as_str() {
+        "weekdays" => vec![Mon, Tue, Wed, Thu, Fri],
+        "weekends" => vec![Sat, Sun],
+        list => list
+            .split(',')
+            .map(|day| day.trim().parse::<Weekday>().map_err(|_| format!("'{}' is not a day of the week", day.trim())))
+            .collect::<Result<_, _>>()?,
+    };
+    Ok(Weekdays(days))
+}
+
+/// Daily wall-clock window a --time-of-day-filter keeps, [start, end); wraps past midnight when
+/// end is earlier than start
+#[derive(Clone, Copy, Debug)]
+pub struct TimeOfDay {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl std::fmt::Display for TimeOfDay {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start.format("%H:%M"), self.end.format("%H:%M"))
+    }
+}
+
+/// Parse `HH:MM-HH:MM`, like `13:00-21:00`
+pub fn parse_time_of_day(value: &str) -> Result<TimeOfDay, String> {
+    let (start, end) = value.split_once('-').ok_or_else(|| format!("'{}' should be HH:MM-HH:MM", value))?;
+    let parse = |time: &str| {
+        NaiveTime::parse_from_str(time.trim(), "%H:%M").map_err(|_| format!("'{}' is not an HH:MM time", time.trim()))
+    };
+    let range = TimeOfDay { start: parse(start)?, end: parse(end)? };
+    if range.start == range.end {
+        return Err(format!("'{}' is an empty window", value));
+    }
+    Ok(range)
+}
+
+/// Which wall-clock time, in a timezone, samples are kept from
+#[derive(Clone, Debug)]
+pub struct SessionFilter {
+    pub timezone: Tz,
+    pub weekdays: Option<Weekdays>,
+    pub time_of_day: Option<TimeOfDay>,
+}
+
+impl SessionFilter {
+    /// Whether a sample taken at `timestamp` is in the session
+    pub fn includes(&self, timestamp: u64) -> bool {
+        let Some(datetime) = chrono::DateTime::from_timestamp(timestamp as i64, 0) else {
+            return false;
+        };
+        let local = datetime.with_timezone(&self.timezone);
+        let in_hours = self.time_of_day.is_none_or(|range| {
+            let time = NaiveTime::from_hms_opt(local.hour(), local.minute(), local.second()).unwrap_or_default();
+            if range.start < range.end {
+                range.start <= time && time < range.end
+            } else {
+                time >= range.start || time < range.end
+            }
+        });
+        in_hours && self.includes_date(local.date_naive())
+    }
+
+    fn includes_date(&self, date: NaiveDate) -> bool {
+        self.weekdays.as_ref().is_none_or(|days| days.0.contains(&date.weekday()))
+    }
+
+    /// The session's stretches of a local day, as timestamps
+    fn day_ranges(&self, date: NaiveDate) -> Vec<(u64, u64)> {
+        let (day_start, day_end) = (self.local_midnight(date), self.local_midnight(date + chrono::Days::new(1)));
+        let at = |time: NaiveTime| self.local_time(date, time).clamp(day_start, day_end);
+        match self.time_of_day {
+            None => vec![(day_start, day_end)],
+            Some(range) if range.start < range.end => vec![(at(range.start), at(range.end))],
+            Some(range) => vec![(day_start, at(range.end)), (at(range.start), day_end)],
+        }
+    }
+
+    /// Seconds of [from, to) that fall in the session, walking the local days it spans
+    pub fn included_seconds(&self, from: u64, to: u64) -> u64 {
+        let Some(first) = chrono::DateTime::from_timestamp(from as i64, 0) else {
+            return 0;
+        };
+        let mut date = first.with_timezone(&self.timezone).date_naive();
+        let mut included = 0;
+        loop {
+            if self.local_midnight(date) >= to {
+                return included;
+            }
+            if self.includes_date(date) {
+                for (start, end) in self.day_ranges(date) {
+                    included += end.min(to).saturating_sub(start.max(from));
+                }
+            }
+            date = date + chrono::Days::new(1);
+        }
+    }
+
+    fn local_midnight(&self, date: NaiveDate) -> u64 {
+        self.local_time(date, NaiveTime::MIN)
+    }
+
+    /// Timestamp of a local wall-clock time; one skipped by a DST change resolves an hour later
+    fn local_time(&self, date: NaiveDate, time: NaiveTime) -> u64 {
+        let local = date.and_time(time);
+        self.timezone
+            .from_local_datetime(&local)
+            .earliest()
+            .or_else(|| self.timezone.from_local_datetime(&(local + chrono::Duration::hours(1))).earliest())
+            .map_or(0, |datetime| datetime.timestamp().max(0) as u64)
+    }
+
+    /// Time-weighted average over only the session's time: each sample's price counts for the part
+    /// of its interval (until the next sample) inside the session. Also returns the session seconds
+    /// and the window's total seconds.
+    pub fn twap(&self, price_points: &[PricePoint]) -> (Option<f64>, u64, u64) {
+        let (mut weighted, mut included) = (0.0f64, 0u64);
+        for pair in price_points.windows(2) {
+            let seconds = self.included_seconds(pair[0].timestamp, pair[1].timestamp);
+            weighted += pair[0].price * seconds as f64;
+            included += seconds;
+        }
+        let window = match (price_points.first(), price_points.last()) {
+            (Some(first), Some(last)) => last.timestamp - first.timestamp,
+            _ => 0,
+        };
+        ((included > 0).then(|| weighted / included as f64), included, window)
+    }
+}
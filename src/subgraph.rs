@@ -0,0 +1,80 @@
+use anyhow::{Context, Result};
+use ethers::types::Address;
+use serde::Deserialize;
+
+/// Entities per query, the most a Graph node returns for one `first`
+const PAGE_SIZE: usize = 1000;
+
+#[derive(Deserialize)]
+struct Response {
+    data: Option<Data>,
+    errors: Option<Vec<GraphError>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Data {
+    pool_hour_datas: Vec<HourData>,
+}
+
+#[derive(Deserialize)]
+struct GraphError {
+    message: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HourData {
+    period_start_unix: u64,
+    /// Token1 per token0, as a BigDecimal string
+    token1_price: String,
+}
+
+/// Hourly prices (token1 per token0) of a pool from a Uniswap-V3-style subgraph's `poolHourDatas`,
+/// for hours starting in [from_timestamp, to_timestamp], oldest first.
+///
+/// Pages through the results by `periodStartUnix` rather than `skip`, which Graph nodes cap.
+pub async fn fetch_hourly_prices(url: &str, pool: Address, from_timestamp: u64, to_timestamp: u64) -> Result<Vec<(u64, f64)>> {
+    let client = reqwest::Client::new();
+    let mut prices = Vec::new();
+    let mut after = from_timestamp.saturating_sub(1);
+
+    loop {
+        let query = format!(
+            "{{ poolHourDatas(first: {}, orderBy: periodStartUnix, orderDirection: asc, \
+             where: {{ pool: \"{:?}\", periodStartUnix_gt: {}, periodStartUnix_lte: {} }}) \
+             {{ periodStartUnix token1Price }} }}",
+            PAGE_SIZE, pool, after, to_timestamp
+        );
+        let body = serde_json::json!({ "query": query }).to_string();
+        let text = client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .body(body)
+            .send()
+            .await
+            .context(format!("Failed to query subgraph {}", url))?
+            .text()
+            .await
+            .context(format!("Failed to read the response from subgraph {}", url))?;
+        let response: Response = serde_json::from_str(&text).context(format!("Subgraph {} returned an unexpected response", url))?;
+
+        if let Some(errors) = response.errors {
+            let messages: Vec<String> = errors.into_iter().map(|e| e.message).collect();
+            anyhow::bail!("Subgraph {} rejected the query: {}", url, messages.join("; "));
+        }
+        let page = response.data.context(format!("Subgraph {} returned no data", url))?.pool_hour_datas;
+
+        let full_page = page.len() == PAGE_SIZE;
+        for hour in page {
+            after = hour.period_start_unix;
+            match hour.token1_price.parse::<f64>() {
+                Ok(price) if price > 0.0 && price.is_finite() => prices.push((hour.period_start_unix, price)),
+                _ => {}
+            }
+        }
+        if !full_page {
+            return Ok(prices);
+        }
+    }
+}